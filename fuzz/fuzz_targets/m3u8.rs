@@ -0,0 +1,23 @@
+#![no_main]
+
+use hotmart_ripper::m3u8;
+use libfuzzer_sys::fuzz_target;
+
+// Any input may be rejected, but none may panic, and whatever parses must
+// come back unchanged after being written out and parsed again.
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else { return };
+    let Ok(playlist) = m3u8::parse(input) else { return };
+
+    let written = playlist.to_string();
+    let reparsed = m3u8::parse(&written).unwrap_or_else(|e| panic!("written playlist does not parse: {}\n{}", e, written));
+    assert_eq!(playlist, reparsed, "playlist did not round-trip:\n{}", written);
+
+    if let m3u8::Playlist::Media(media) = &playlist {
+        for segment in &media.segments {
+            if let Some(key) = &segment.key {
+                key.iv_for(segment.sequence);
+            }
+        }
+    }
+});
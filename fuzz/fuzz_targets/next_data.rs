@@ -0,0 +1,14 @@
+#![no_main]
+
+use hotmart_ripper::metadata::{self, VideoMetadata};
+use libfuzzer_sys::fuzz_target;
+
+// Embed pages come from the network; reading them may fail but must not panic,
+// and metadata read from a page always points at a playlist to try.
+fuzz_target!(|data: &[u8]| {
+    let Ok(html) = std::str::from_utf8(data) else { return };
+    let Ok(application_data) = metadata::application_data(html) else { return };
+    let Ok(video) = VideoMetadata::from_page("fuzz", &application_data) else { return };
+
+    assert!(video.master_url().is_some(), "{:?}", video);
+});
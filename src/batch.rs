@@ -0,0 +1,66 @@
+//! Batch files listing several videos to download.
+//!
+//! One video per line: the embed URL, optionally followed by whitespace and
+//! the output path. Blank lines and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # Module 1
+//! https://player.hotmart.com/embed/abc?signature=...&token=...  01 Welcome.mp4
+//! https://player.hotmart.com/embed/def?signature=...&token=...
+//! ```
+
+use std::path::PathBuf;
+
+use crate::config::OutputFormat;
+use crate::downloader::EmbedUrl;
+use crate::error::{Error, Result};
+
+/// One video of a batch file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchEntry {
+    /// 1-based line number in the batch file.
+    pub line: usize,
+    pub url: String,
+    /// Output path given on the line, if any.
+    pub output: Option<PathBuf>,
+}
+
+impl BatchEntry {
+    /// The output path given on the line, ending in the extension of `format`.
+    ///
+    /// An `.mp4` or `.ts` extension is replaced; other dots, as in
+    /// `Lesson 1.5 intro`, are part of the name.
+    pub fn output_path(&self, format: OutputFormat) -> Option<PathBuf> {
+        let output = self.output.as_ref()?;
+        let is_video = output.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("mp4") || extension.eq_ignore_ascii_case("ts")
+        });
+        let mut path = if is_video { output.with_extension("") } else { output.clone() }.into_os_string();
+        path.push(".");
+        path.push(format.extension());
+        Some(path.into())
+    }
+}
+
+/// Parses a batch file. Every URL is checked up front, so a typo is reported
+/// before any download starts.
+pub fn parse(input: &str) -> Result<Vec<BatchEntry>> {
+    let mut entries = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (url, output) = match line.split_once(char::is_whitespace) {
+            Some((url, output)) => (url, Some(PathBuf::from(output.trim()))),
+            None => (line, None),
+        };
+        EmbedUrl::parse(url).map_err(|e| match e {
+            Error::UrlParse(reason) => Error::UrlParse(format!("line {}: {}", index + 1, reason)),
+            e => e,
+        })?;
+        entries.push(BatchEntry { line: index + 1, url: url.to_string(), output });
+    }
+    Ok(entries)
+}
@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use tokio::fs;
+
+use crate::error::Result;
+use crate::playlist::MediaSegment;
+
+/// Progress of an interrupted download, stored next to the output file.
+///
+/// Segments are written strictly in playlist order, so the completed ones are
+/// always a prefix of the playlist and a count plus the file length at that
+/// point is enough to continue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Checkpoint {
+    pub video_id: String,
+    /// Selected variant's playlist URL without its query string.
+    pub variant: String,
+    /// [`fingerprint`] of the media playlist being downloaded.
+    pub fingerprint: String,
+    pub completed_segments: usize,
+    /// Length of the output file once `completed_segments` were written.
+    pub bytes_written: u64,
+}
+
+impl Checkpoint {
+    const VERSION: u64 = 1;
+
+    /// Sidecar path for `output`, e.g. `lesson.mp4.resume.json`.
+    pub fn path_for(output: &Path) -> PathBuf {
+        sibling_path(output, ".resume.json")
+    }
+
+    /// Reads the checkpoint at `path`. Missing or unreadable files yield `None`,
+    /// which simply means the download starts over.
+    pub async fn load(path: &Path) -> Option<Self> {
+        let data = fs::read(path).await.ok()?;
+        let value: Value = serde_json::from_slice(&data).ok()?;
+        if value["version"].as_u64() != Some(Self::VERSION) {
+            return None;
+        }
+
+        Some(Self {
+            video_id: value["video_id"].as_str()?.to_string(),
+            variant: value["variant"].as_str()?.to_string(),
+            fingerprint: value["fingerprint"].as_str()?.to_string(),
+            completed_segments: usize::try_from(value["completed_segments"].as_u64()?).ok()?,
+            bytes_written: value["bytes_written"].as_u64()?,
+        })
+    }
+
+    /// Replaces the checkpoint at `path`. The new state is written to a
+    /// temporary file first so a crash never leaves a truncated checkpoint.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let value = json!({
+            "version": Self::VERSION,
+            "video_id": self.video_id,
+            "variant": self.variant,
+            "fingerprint": self.fingerprint,
+            "completed_segments": self.completed_segments,
+            "bytes_written": self.bytes_written,
+        });
+
+        let tmp_path = sibling_path(path, ".tmp");
+        fs::write(&tmp_path, value.to_string()).await?;
+        fs::rename(&tmp_path, path).await?;
+        Ok(())
+    }
+
+    /// Why this saved checkpoint cannot be continued by a run in state `current`
+    /// whose output file is `file_len` bytes long, or `None` if it can.
+    pub fn mismatch(&self, current: &Checkpoint, total_segments: usize, file_len: Option<u64>) -> Option<String> {
+        if self.video_id != current.video_id {
+            Some(format!("it belongs to video {}", self.video_id))
+        } else if self.variant != current.variant {
+            Some("a different variant was selected".to_string())
+        } else if self.fingerprint != current.fingerprint || self.completed_segments > total_segments {
+            Some("the playlist has changed".to_string())
+        } else if file_len.is_none_or(|len| len < self.bytes_written) {
+            Some("the partial output file is missing or shorter than recorded".to_string())
+        } else {
+            None
+        }
+    }
+
+    pub async fn remove(path: &Path) -> Result<()> {
+        match fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Partial output for `output`, e.g. `lesson.mp4.part`. Downloads are written
+/// here and only renamed to `output` once complete.
+pub(crate) fn part_path_for(output: &Path) -> PathBuf {
+    sibling_path(output, ".part")
+}
+
+/// `path` with `suffix` appended to its file name.
+pub(crate) fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// `url` without its query string and fragment, which is where Hotmart puts
+/// the signature. Re-signed URLs for the same video compare equal.
+pub(crate) fn strip_signature(url: &str) -> &str {
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    &url[..end]
+}
+
+/// Identifies a media playlist independently of its signatures, so a freshly
+/// signed URL for the same video resumes while a changed playlist does not.
+pub(crate) fn fingerprint(segments: &[MediaSegment]) -> String {
+    let mut hash = Fnv1a::default();
+    hash.write_u64(segments.len() as u64);
+    for segment in segments {
+        hash.write_str(strip_signature(&segment.url));
+        hash.write_u64(segment.sequence);
+        if let Some(range) = &segment.byte_range {
+            hash.write_u64(range.start);
+            hash.write_u64(range.end);
+        }
+        if let Some(key) = &segment.key {
+            hash.write_str(strip_signature(&key.url));
+            hash.write(&key.iv);
+        }
+        if let Some(map) = &segment.map {
+            hash.write_str(strip_signature(&map.url));
+            if let Some(range) = &map.byte_range {
+                hash.write_u64(range.start);
+                hash.write_u64(range.end);
+            }
+        }
+    }
+    format!("{:016x}", hash.0)
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is fixed, which matters
+/// for a value that is persisted and compared across builds.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    /// Length-prefixed so adjacent strings cannot run into each other.
+    fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write(value.as_bytes());
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::downloader::HotmartDownloader;
+use crate::error::Result;
+use crate::select::VariantSelector;
+
+/// Browser user agent sent with every request unless overridden.
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:131.0) Gecko/20100101 Firefox/131.0";
+
+/// Player origin sent as `Origin` and `Referer` unless overridden.
+pub const DEFAULT_PLAYER_URL: &str = "https://player.hotmart.com";
+
+/// Content player API endpoint asked for the master playlist when the embed
+/// page cannot be parsed, unless overridden.
+pub const DEFAULT_API_URL: &str = "https://contentplayer.hotmart.com/video/content";
+
+/// Number of segments fetched in parallel unless overridden.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// Number of times a failed request is retried unless overridden.
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Settings used to construct a [`HotmartDownloader`].
+#[derive(Debug, Clone)]
+pub struct DownloaderConfig {
+    /// `User-Agent` header sent with every request.
+    pub user_agent: String,
+    /// Timeout applied to each HTTP request.
+    pub timeout: Duration,
+    /// Extra headers sent with every request, after the built-in ones.
+    pub headers: Vec<(String, String)>,
+    /// Origin of the player, sent as `Origin` and `Referer` with every request.
+    pub player_url: String,
+    /// URL of the content player API endpoint.
+    pub api_url: String,
+    /// Proxy every request goes through, e.g. `http://127.0.0.1:8080`. When
+    /// `None` the `HTTP_PROXY` and `HTTPS_PROXY` environment variables apply.
+    pub proxy: Option<String>,
+    /// Number of segments fetched and decrypted in parallel.
+    pub jobs: usize,
+    /// How failed playlist, key and segment requests are retried.
+    pub retry: RetryPolicy,
+    /// Whether an interrupted download continues from its checkpoint file.
+    pub resume: bool,
+    /// Container written for MPEG-TS streams.
+    pub output_format: OutputFormat,
+    /// Which variant of the master playlist is downloaded.
+    pub quality: VariantSelector,
+    /// Preferred language of the audio rendition, e.g. `pt-BR`. When `None`
+    /// the playlist's default audio is used.
+    pub audio_language: Option<String>,
+    /// Which subtitle renditions are downloaded.
+    pub subtitles: SubtitleSelection,
+    /// How downloaded subtitles are saved.
+    pub subtitle_format: SubtitleFormat,
+}
+
+impl Default for DownloaderConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            timeout: Duration::from_secs(30),
+            headers: Vec::new(),
+            player_url: DEFAULT_PLAYER_URL.to_string(),
+            api_url: DEFAULT_API_URL.to_string(),
+            proxy: None,
+            jobs: DEFAULT_JOBS,
+            retry: RetryPolicy::default(),
+            resume: true,
+            output_format: OutputFormat::default(),
+            quality: VariantSelector::best(),
+            audio_language: None,
+            subtitles: SubtitleSelection::default(),
+            subtitle_format: SubtitleFormat::default(),
+        }
+    }
+}
+
+/// What to do with streams delivered as MPEG-TS segments.
+///
+/// Fragmented MP4 streams are always written as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Remux into an MP4 file with `moov` at the front.
+    #[default]
+    Mp4,
+    /// Keep the concatenated MPEG-TS segments.
+    Ts,
+}
+
+impl OutputFormat {
+    /// File extension of the output, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Ts => "ts",
+        }
+    }
+}
+
+/// Which `#EXT-X-MEDIA:TYPE=SUBTITLES` renditions of the selected variant to download.
+///
+/// Parses from `none`, `all` or a comma-separated list of language tags.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SubtitleSelection {
+    #[default]
+    None,
+    All,
+    /// Renditions in these languages, matched like [`select_rendition`](crate::select_rendition) does.
+    Languages(Vec<String>),
+}
+
+impl FromStr for SubtitleSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "none" | "" => Ok(Self::None),
+            "all" => Ok(Self::All),
+            list => Ok(Self::Languages(list.split(',').map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect())),
+        }
+    }
+}
+
+/// How downloaded subtitles are saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubtitleFormat {
+    /// A WebVTT file next to the video, e.g. `lesson.pt-BR.vtt`.
+    #[default]
+    Vtt,
+    /// A SubRip file next to the video, e.g. `lesson.pt-BR.srt`.
+    Srt,
+    /// A `mov_text` track in the MP4. Falls back to [`Vtt`](Self::Vtt) when
+    /// the stream is not remuxed.
+    Embedded,
+}
+
+impl FromStr for SubtitleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "vtt" | "webvtt" => Ok(Self::Vtt),
+            "srt" => Ok(Self::Srt),
+            "embed" | "embedded" | "mov_text" => Ok(Self::Embedded),
+            other => Err(format!("invalid subtitle format {:?}; expected vtt, srt or embed", other)),
+        }
+    }
+}
+
+/// Exponential backoff for requests that failed with a transient error.
+///
+/// Retry `n` waits a delay spread between half and all of
+/// `base_delay * 2^(n-1)`, capped at `max_delay`. A `Retry-After` header
+/// sent with a 429 or 503 takes precedence over the computed delay.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_RETRIES,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry `attempt` (1-based), including jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let ceiling = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        ceiling / 2 + (ceiling / 2).mul_f64(jitter())
+    }
+}
+
+/// A number in `[0, 1)` that differs from call to call. It only has to spread
+/// retries out, not be random: `RandomState` seeds its keys once per thread
+/// and then increments them, and hashing nothing with those keys is enough
+/// without pulling in an RNG crate.
+fn jitter() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Builder for [`HotmartDownloader`], obtained from [`HotmartDownloader::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct DownloaderBuilder {
+    config: DownloaderConfig,
+}
+
+impl DownloaderBuilder {
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Adds a header to every request. Replaces a built-in header of the same name.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Sets the player origin, e.g. to point the downloader at a mirror or a test server.
+    pub fn player_url(mut self, url: impl Into<String>) -> Self {
+        self.config.player_url = url.into();
+        self
+    }
+
+    /// Sets the content player API endpoint.
+    pub fn api_url(mut self, url: impl Into<String>) -> Self {
+        self.config.api_url = url.into();
+        self
+    }
+
+    /// Sends every request through the proxy at `url`.
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.config.proxy = Some(url.into());
+        self
+    }
+
+    /// Sets how many segments are fetched in parallel. Values below 1 are treated as 1.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.config.jobs = jobs.max(1);
+        self
+    }
+
+    /// Sets how many times a failed request is retried. 0 disables retrying.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.config.retry.max_retries = retries;
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry = policy;
+        self
+    }
+
+    /// Enables or disables continuing from a checkpoint left by an interrupted run.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.config.resume = resume;
+        self
+    }
+
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.config.output_format = format;
+        self
+    }
+
+    pub fn quality(mut self, quality: VariantSelector) -> Self {
+        self.config.quality = quality;
+        self
+    }
+
+    /// Prefers audio renditions in `language`, an RFC 5646 tag such as `pt-BR`.
+    pub fn audio_language(mut self, language: impl Into<String>) -> Self {
+        self.config.audio_language = Some(language.into());
+        self
+    }
+
+    pub fn subtitles(mut self, subtitles: SubtitleSelection) -> Self {
+        self.config.subtitles = subtitles;
+        self
+    }
+
+    pub fn subtitle_format(mut self, format: SubtitleFormat) -> Self {
+        self.config.subtitle_format = format;
+        self
+    }
+
+    pub fn config(&self) -> &DownloaderConfig {
+        &self.config
+    }
+
+    pub fn build(self) -> Result<HotmartDownloader> {
+        HotmartDownloader::with_config(self.config)
+    }
+}
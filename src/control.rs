@@ -0,0 +1,100 @@
+//! Cancelling and pausing downloads from outside the pipeline.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// Cancels or pauses the downloads of a [`HotmartDownloader`](crate::HotmartDownloader),
+/// from another task or thread.
+///
+/// Clones control the same downloads. While paused, requests already in
+/// flight finish but no new segment is requested. Cancelling stops a download
+/// at the next segment boundary: requests in flight are dropped, the segments
+/// already written stay recorded in the checkpoint, and the download fails
+/// with [`Error::Cancelled`]. Running it again later resumes from there.
+///
+/// Cancelling is final; a cancelled handle cannot be resumed.
+#[derive(Debug, Clone)]
+pub struct DownloadControl {
+    state: Arc<watch::Sender<State>>,
+}
+
+impl Default for DownloadControl {
+    fn default() -> Self {
+        Self { state: Arc::new(watch::channel(State::Running).0) }
+    }
+}
+
+impl DownloadControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.state.send_replace(State::Cancelled);
+    }
+
+    /// Holds back new requests until [`resume`](Self::resume) is called.
+    pub fn pause(&self) {
+        self.transition(State::Running, State::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.transition(State::Paused, State::Running);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.borrow() == State::Paused
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.state.borrow() == State::Cancelled
+    }
+
+    fn transition(&self, from: State, to: State) {
+        self.state.send_if_modified(|state| {
+            let applies = *state == from;
+            if applies {
+                *state = to;
+            }
+            applies
+        });
+    }
+
+    /// Waits while paused. Fails once cancelled.
+    pub(crate) async fn proceed(&self) -> Result<()> {
+        let mut state = self.state.subscribe();
+        let state = state.wait_for(|state| *state != State::Paused).await
+            .map(|state| *state)
+            .unwrap_or(State::Cancelled);
+        match state {
+            State::Cancelled => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Completes once cancelled.
+    async fn cancelled(&self) {
+        let mut state = self.state.subscribe();
+        let _ = state.wait_for(|state| *state == State::Cancelled).await;
+    }
+
+    /// Runs `future` unless cancelled first, in which case it is dropped.
+    pub(crate) async fn run<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => Err(Error::Cancelled),
+            result = future => result,
+        }
+    }
+}
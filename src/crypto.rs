@@ -0,0 +1,14 @@
+use aes::Aes128;
+use cbc::Decryptor;
+use cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
+
+use crate::error::DecryptError;
+
+/// Decrypts an AES-128-CBC segment with PKCS#7 padding, as used by HLS `METHOD=AES-128`.
+pub fn decrypt_segment(data: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, DecryptError> {
+    let decryptor = Decryptor::<Aes128>::new_from_slices(key, iv)
+        .map_err(|_| DecryptError::InvalidLength { key_len: key.len(), iv_len: iv.len() })?;
+
+    decryptor.decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| DecryptError::BadPadding)
+}
@@ -0,0 +1,808 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, Response, header};
+use serde_json::Value;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use url::Url;
+
+use crate::checkpoint::{fingerprint, part_path_for, sibling_path, strip_signature, Checkpoint};
+use crate::config::{DownloaderBuilder, DownloaderConfig, OutputFormat, SubtitleFormat};
+use crate::control::DownloadControl;
+use crate::error::{Error, Result};
+use crate::fetcher::{SegmentFetcher, WorkerTask};
+use crate::http::{check_status, with_retry};
+use crate::info::VideoInfo;
+use crate::m3u8::{self, MediaType, Playlist};
+use crate::playlist::{resolve_renditions, resolve_segments, resolve_variants, InitSegment, MediaSegment, Rendition, Variant};
+use crate::progress::{EventSink, ProgressEvent};
+use crate::remux;
+use crate::select::{select_rendition, select_subtitles, VariantSelector};
+use crate::subtitles::{self, Cue, SubtitleTrack};
+use crate::metadata::{self, VideoMetadata};
+use crate::template::OutputTemplate;
+
+/// The parts of a player embed URL the pipeline needs.
+#[derive(Debug, Clone)]
+pub struct EmbedUrl {
+    pub url: Url,
+    pub video_id: String,
+    pub token: String,
+    pub signature: String,
+}
+
+impl EmbedUrl {
+    pub fn parse(embed_url: &str) -> Result<Self> {
+        let url = Url::parse(embed_url)
+            .map_err(|e| Error::UrlParse(format!("{}: {}", embed_url, e)))?;
+        let video_id = url.path_segments()
+            .ok_or_else(|| Error::UrlParse(format!("{}: invalid URL path", embed_url)))?
+            .next_back()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| Error::UrlParse(format!("{}: no video ID found", embed_url)))?
+            .to_string();
+
+        // Get both token and signature from URL
+        let query_param = |name: &str| url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_default();
+        let token = query_param("token");
+        let signature = query_param("signature");
+
+        Ok(Self { url, video_id, token, signature })
+    }
+}
+
+/// Downloads Hotmart videos from their player embed URLs.
+pub struct HotmartDownloader {
+    client: Client,
+    config: DownloaderConfig,
+    control: DownloadControl,
+}
+
+impl HotmartDownloader {
+    /// Creates a downloader with the default configuration.
+    pub fn new() -> Result<Self> {
+        Self::with_config(DownloaderConfig::default())
+    }
+
+    pub fn builder() -> DownloaderBuilder {
+        DownloaderBuilder::default()
+    }
+
+    pub fn with_config(config: DownloaderConfig) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert("User-Agent", header_value(&config.user_agent)?);
+        headers.insert("Accept", header::HeaderValue::from_static("*/*"));
+        headers.insert("Accept-Language", header::HeaderValue::from_static("en-US,en;q=0.5"));
+        let player_url = config.player_url.trim_end_matches('/');
+        headers.insert("Origin", header_value(player_url)?);
+        headers.insert("Referer", header_value(&format!("{}/", player_url))?);
+        headers.insert("Connection", header::HeaderValue::from_static("keep-alive"));
+        for (name, value) in &config.headers {
+            let name = header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::InvalidHeader(format!("name {:?}", name)))?;
+            headers.insert(name, header_value(value)?);
+        }
+
+        let mut client = Client::builder()
+            .default_headers(headers)
+            .timeout(config.timeout);
+        if let Some(proxy) = &config.proxy {
+            client = client.proxy(reqwest::Proxy::all(proxy).map_err(Error::Client)?);
+        }
+        let client = client.build().map_err(Error::Client)?;
+
+        Ok(Self { client, config, control: DownloadControl::default() })
+    }
+
+    /// Pauses or cancels every download of this downloader. Clone the handle
+    /// to use it while a download is running.
+    pub fn control(&self) -> &DownloadControl {
+        &self.control
+    }
+
+    /// Runs the full pipeline for `embed_url` and writes the video to `save_path`.
+    ///
+    /// Stages: resolve the master playlist, select a variant, enumerate its
+    /// segments, then fetch and decrypt them in parallel and write them in order.
+    /// Requests that fail with a transient error are retried as configured by
+    /// [`DownloaderConfig::retry`], and every retry is reported to `on_event`.
+    ///
+    /// The video is written to `<save_path>.part` and renamed to `save_path`
+    /// only once every segment is on disk, so an existing file is never
+    /// replaced by a broken one. Progress is recorded in a checkpoint next to
+    /// `save_path` after every segment; running the download again, even from
+    /// a freshly signed URL for the same video, continues from the partial file.
+    ///
+    /// If the variant's audio is a separate rendition, the one in
+    /// [`DownloaderConfig::audio_language`] (or the default one) is downloaded
+    /// after the video, with its own partial file and checkpoint.
+    ///
+    /// MPEG-TS streams are remuxed into MP4, together with the separate audio,
+    /// unless [`DownloaderConfig::output_format`] asks for the raw stream. In
+    /// that case the audio is saved next to the video as e.g. `lesson.pt-BR.aac`.
+    /// Separate audio the remuxer cannot take, i.e. when either stream is
+    /// fragmented MP4, fails with [`Error::UnsupportedAudio`] before any
+    /// segment is downloaded.
+    ///
+    /// Subtitle renditions picked by [`DownloaderConfig::subtitles`] are
+    /// stitched into one cue list each and saved as [`DownloaderConfig::subtitle_format`]
+    /// asks, next to the video or as tracks of the MP4.
+    ///
+    /// The download can be paused and cancelled through [`control`](Self::control);
+    /// a cancelled download keeps its checkpoint and fails with [`Error::Cancelled`].
+    pub async fn download<F, P>(&self, embed_url: &str, save_path: P, on_event: F) -> Result<()>
+    where
+        F: Fn(ProgressEvent) + Send + Sync + 'static,
+        P: AsRef<Path>,
+    {
+        let embed = EmbedUrl::parse(embed_url)?;
+        let sink: EventSink = Arc::new(on_event);
+
+        // Fetch master playlist URL either directly or via API
+        let metadata = self.control.run(self.resolve(&embed, &*sink)).await?;
+        self.download_resolved(&embed, &metadata, save_path.as_ref(), sink).await
+    }
+
+    /// Like [`download`](Self::download), but saves the video to the path
+    /// `output` yields for it, filled in from the [`VideoMetadata`] found
+    /// while resolving the playlist. Missing directories are created.
+    /// Returns the path the video was saved to.
+    pub async fn download_to<F>(&self, embed_url: &str, output: &OutputTemplate, on_event: F) -> Result<PathBuf>
+    where
+        F: Fn(ProgressEvent) + Send + Sync + 'static,
+    {
+        self.download_claiming(embed_url, output, None, on_event).await
+    }
+
+    /// [`download_to`](Self::download_to), claiming the rendered path for video
+    /// `index` in `claims` first. A path another video has claimed fails with
+    /// [`Error::OutputConflict`].
+    async fn download_claiming<F>(
+        &self,
+        embed_url: &str,
+        output: &OutputTemplate,
+        claims: Option<(&OutputClaims, usize)>,
+        on_event: F,
+    ) -> Result<PathBuf>
+    where
+        F: Fn(ProgressEvent) + Send + Sync + 'static,
+    {
+        let embed = EmbedUrl::parse(embed_url)?;
+        let sink: EventSink = Arc::new(on_event);
+
+        let metadata = self.control.run(self.resolve(&embed, &*sink)).await?;
+        let save_path = output.render(&metadata, self.config.output_format.extension());
+        if let Some((claims, index)) = claims {
+            match claims.lock().unwrap().entry(save_path.clone()) {
+                Entry::Occupied(claim) => return Err(Error::OutputConflict { path: save_path, other: *claim.get() }),
+                Entry::Vacant(claim) => {
+                    claim.insert(index);
+                }
+            }
+        }
+        if let Some(parent) = save_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).await?;
+        }
+        self.download_resolved(&embed, &metadata, &save_path, sink).await?;
+        Ok(save_path)
+    }
+
+    /// Runs the pipeline from the master playlist on.
+    async fn download_resolved(&self, embed: &EmbedUrl, metadata: &VideoMetadata, save_path: &Path, sink: EventSink) -> Result<()> {
+        let on_event = &*sink;
+
+        // Pick the variant matching the configured quality
+        let (_, variants, renditions) = self.control.run(self.open_asset(metadata, on_event)).await?;
+        let variant = self.config.quality.select(&variants)
+            .cloned()
+            .ok_or_else(|| Error::NoMatchingVariant { quality: self.config.quality.to_string() })?;
+        on_event(ProgressEvent::VariantSelected { variant: variant.clone() });
+
+        // Audio delivered as a separate rendition is downloaded alongside the
+        // video; renditions without a URI are already muxed into the variant.
+        let audio = variant.audio.as_deref()
+            .and_then(|group| select_rendition(&renditions, MediaType::Audio, group, self.config.audio_language.as_deref()))
+            .filter(|rendition| rendition.url.is_some())
+            .cloned();
+        if let Some(rendition) = &audio {
+            on_event(ProgressEvent::RenditionSelected { rendition: rendition.clone() });
+        }
+
+        // Fetch list of segments
+        let media_segments = self.control.run(self.get_media_segments(&variant.url, on_event)).await?;
+        let audio_segments = match audio.as_ref().and_then(|rendition| rendition.url.as_deref()) {
+            Some(url) => self.control.run(self.get_media_segments(url, on_event)).await?,
+            None => Vec::new(),
+        };
+        if audio.is_some() && self.config.output_format == OutputFormat::Mp4 {
+            let (video, audio) = (Container::of(&media_segments), Container::of(&audio_segments));
+            if video != Container::Ts || audio == Container::Fmp4 {
+                return Err(Error::UnsupportedAudio { audio: audio.to_string(), video: video.to_string() });
+            }
+        }
+        let total_segments = media_segments.len() + audio_segments.len();
+        on_event(ProgressEvent::SegmentsFound { total: total_segments });
+
+        let fetcher = SegmentFetcher::new(self.client.clone(), self.config.retry.clone(), sink.clone(), self.control.clone());
+        let checkpoint = |url: &str, segments: &[MediaSegment]| Checkpoint {
+            video_id: embed.video_id.clone(),
+            variant: strip_signature(url).to_string(),
+            fingerprint: fingerprint(segments),
+            completed_segments: 0,
+            bytes_written: 0,
+        };
+
+        let part_path = self.download_track(
+            &media_segments,
+            save_path,
+            checkpoint(&variant.url, &media_segments),
+            &fetcher,
+            (0, total_segments),
+            on_event,
+        ).await?;
+        let audio_track = match &audio {
+            Some(rendition) => {
+                let audio_output = sibling_path(save_path, ".audio");
+                let part_path = self.download_track(
+                    &audio_segments,
+                    &audio_output,
+                    checkpoint(rendition.url.as_deref().unwrap_or_default(), &audio_segments),
+                    &fetcher,
+                    (media_segments.len(), total_segments),
+                    on_event,
+                ).await?;
+                Some((rendition, audio_output, part_path))
+            }
+            None => None,
+        };
+
+        let is_ts = media_segments.iter().chain(&audio_segments).all(|segment| segment.map.is_none());
+        let remux = is_ts && self.config.output_format == OutputFormat::Mp4;
+
+        // Cue times are made relative to the first video frame, which is only
+        // known for MPEG-TS; otherwise the subtitles' own timestamp map is trusted.
+        let subtitle_renditions = variant.subtitles.as_deref()
+            .map(|group| select_subtitles(&renditions, group, &self.config.subtitles))
+            .unwrap_or_default();
+        let mut subtitle_tracks = Vec::new();
+        if !subtitle_renditions.is_empty() {
+            let start = if is_ts { ts_start_time(&part_path).await? } else { None };
+            for rendition in subtitle_renditions {
+                on_event(ProgressEvent::RenditionSelected { rendition: rendition.clone() });
+                let url = rendition.url.as_deref().expect("select_subtitles only returns renditions with a URI");
+                let cues = self.control.run(self.get_subtitles(url, &fetcher, start, on_event)).await?;
+                subtitle_tracks.push(SubtitleTrack { language: rendition.language.clone(), cues });
+            }
+        }
+        let embed_subtitles = remux && self.config.subtitle_format == SubtitleFormat::Embedded;
+
+        if remux {
+            self.control.proceed().await?;
+            on_event(ProgressEvent::Remuxing);
+            let remux_path = sibling_path(save_path, ".remux");
+            let audio_part = audio_track.as_ref().map(|(_, _, part_path)| part_path.as_path());
+            let embedded = if embed_subtitles { std::mem::take(&mut subtitle_tracks) } else { Vec::new() };
+            if let Err(e) = remux_to_mp4(&part_path, audio_part, embedded, &remux_path).await {
+                let _ = fs::remove_file(&remux_path).await;
+                return Err(e);
+            }
+            fs::rename(&remux_path, save_path).await?;
+            fs::remove_file(&part_path).await?;
+            if let Some(audio_part) = audio_part {
+                fs::remove_file(audio_part).await?;
+            }
+        } else {
+            // Separate audio only gets here with the raw stream asked for, and
+            // is kept next to the video.
+            fs::rename(&part_path, save_path).await?;
+            if let Some((rendition, _, audio_part)) = &audio_track {
+                let container = Container::sniff(audio_part, &audio_segments).await?;
+                fs::rename(audio_part, audio_sidecar_path(save_path, rendition, container)).await?;
+            }
+        }
+        write_subtitle_sidecars(save_path, &subtitle_tracks, self.config.subtitle_format).await?;
+        Checkpoint::remove(&Checkpoint::path_for(save_path)).await?;
+        if let Some((_, audio_output, _)) = &audio_track {
+            Checkpoint::remove(&Checkpoint::path_for(audio_output)).await?;
+        }
+        on_event(ProgressEvent::Finished { path: save_path.to_path_buf() });
+
+        Ok(())
+    }
+
+    /// Downloads several videos, given as `(embed URL, output template)`
+    /// pairs, up to `parallel` at a time.
+    ///
+    /// Each video goes through [`download_to`](Self::download_to) on its own,
+    /// so one failure does not stop the others. Events are reported together
+    /// with the index of the video they belong to. Returns the path of each
+    /// video, or why it failed, in the order given.
+    ///
+    /// Two videos must not share a partial file and checkpoint, so a video
+    /// whose output path another one of the batch already uses fails with
+    /// [`Error::OutputConflict`].
+    pub async fn download_batch<F>(&self, videos: &[(String, OutputTemplate)], parallel: usize, on_event: F) -> Vec<Result<PathBuf>>
+    where
+        F: Fn(usize, ProgressEvent) + Send + Sync + 'static,
+    {
+        let on_event = Arc::new(on_event);
+        let claims = OutputClaims::default();
+        let claims = &claims;
+        let mut results: Vec<(usize, Result<PathBuf>)> = stream::iter(videos.iter().enumerate())
+            .map(|(index, (url, output))| {
+                let on_event = on_event.clone();
+                async move {
+                    let result = self.download_claiming(url, output, Some((claims, index)), move |event| on_event(index, event)).await;
+                    (index, result)
+                }
+            })
+            .buffer_unordered(parallel.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Downloads `segments` into the partial file for `output` and returns its
+    /// path. Progress is checkpointed next to `output` after every segment, and
+    /// reported as segments `offset + 1..` of `total`.
+    async fn download_track<F>(
+        &self,
+        segments: &[MediaSegment],
+        output: &Path,
+        mut checkpoint: Checkpoint,
+        fetcher: &SegmentFetcher,
+        (offset, total): (usize, usize),
+        on_event: &F,
+    ) -> Result<PathBuf>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let part_path = part_path_for(output);
+        let checkpoint_path = Checkpoint::path_for(output);
+        let mut output_file = self.open_output(&part_path, &checkpoint_path, &mut checkpoint, segments.len(), (offset, total), on_event).await?;
+        let first_segment = checkpoint.completed_segments;
+
+        // Segments are fetched and decrypted by up to `jobs` workers. `buffered`
+        // yields results in playlist order and never holds more than `jobs` of
+        // them, so memory stays bounded however long the video is.
+        let mut fetched = stream::iter(segments.iter().cloned().enumerate().skip(first_segment))
+            .map(|(i, segment)| {
+                let fetcher = fetcher.clone();
+                WorkerTask::spawn(async move { fetcher.fetch_segment(i, &segment).await })
+            })
+            .buffered(self.config.jobs.max(1));
+
+        // Fragmented MP4 streams need their initialization section written before
+        // the first fragment, and again whenever the playlist switches to a new one.
+        let mut current_map: Option<&InitSegment> = None;
+        let mut written_init: Option<Vec<u8>> = None;
+
+        // When resuming, the section in effect before the first missing segment
+        // is already in the file.
+        if let Some(map) = first_segment.checked_sub(1).and_then(|i| segments[i].map.as_ref()) {
+            written_init = Some(self.control.run(fetcher.fetch_init(first_segment - 1, map)).await?);
+            current_map = Some(map);
+        }
+
+        for (i, segment) in segments.iter().enumerate().skip(first_segment) {
+            if let Some(map) = segment.map.as_ref().filter(|map| Some(*map) != current_map) {
+                let init_data = self.control.run(fetcher.fetch_init(i, map)).await?;
+
+                // Identical sections (e.g. the same map re-declared after a
+                // discontinuity) are only written once.
+                if written_init.as_ref() != Some(&init_data) {
+                    output_file.write_all(&init_data).await?;
+                    checkpoint.bytes_written += init_data.len() as u64;
+                    written_init = Some(init_data);
+                }
+                current_map = Some(map);
+            }
+
+            // Cancelling drops the fetches in flight; what is on disk so far
+            // is already in the checkpoint.
+            let segment_data = self.control.run(async {
+                fetched.next().await.expect("one fetch is queued per segment")
+            }).await?;
+            output_file.write_all(&segment_data).await?;
+
+            // The data must reach the file before the checkpoint claims it.
+            output_file.flush().await?;
+            checkpoint.completed_segments = i + 1;
+            checkpoint.bytes_written += segment_data.len() as u64;
+            checkpoint.save(&checkpoint_path).await?;
+            on_event(ProgressEvent::Segment { current: offset + i + 1, total });
+        }
+
+        output_file.flush().await?;
+        output_file.sync_all().await?;
+        drop(output_file);
+
+        let written = fs::metadata(&part_path).await?.len();
+        if written != checkpoint.bytes_written {
+            return Err(Error::OutputCheck {
+                reason: format!("{} holds {} bytes but {} were written", part_path.display(), written, checkpoint.bytes_written),
+            });
+        }
+        Ok(part_path)
+    }
+
+    /// Describes the streams behind `embed_url` without downloading them.
+    ///
+    /// The master playlist is resolved as in [`download`](Self::download), and
+    /// the media playlist of the variant [`DownloaderConfig::quality`] picks is
+    /// read for the duration, segment count and encryption.
+    pub async fn info<F>(&self, embed_url: &str, on_event: &F) -> Result<VideoInfo>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let embed = EmbedUrl::parse(embed_url)?;
+        let metadata = self.resolve(&embed, on_event).await?;
+        let (asset, variants, renditions) = self.open_asset(&metadata, on_event).await?;
+
+        let selected = self.config.quality.position(&variants);
+        let inspected = match selected {
+            Some(i) => &variants[i],
+            None => VariantSelector::best().select(&variants).expect("get_streams returns at least one variant"),
+        };
+        let playlist = m3u8::parse_media(&self.fetch_text(&inspected.url, on_event).await?)?;
+
+        Ok(VideoInfo {
+            video_id: embed.video_id,
+            master_url: metadata.assets[asset].url.clone(),
+            asset,
+            duration: playlist.duration(),
+            segment_count: playlist.segments.len(),
+            encryption: playlist.segments.iter().find_map(|segment| segment.key.as_ref()).map(|key| key.method.clone()),
+            variants,
+            renditions,
+            selected,
+            metadata,
+        })
+    }
+
+    /// Opens the partial output file. If a checkpoint for the same video,
+    /// variant and playlist exists, the file is cut back to the recorded length
+    /// and `checkpoint` takes over its progress; otherwise the file is recreated.
+    async fn open_output<F>(
+        &self,
+        part_path: &Path,
+        checkpoint_path: &Path,
+        checkpoint: &mut Checkpoint,
+        total_segments: usize,
+        (offset, total): (usize, usize),
+        on_event: &F,
+    ) -> Result<File>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let saved = match Checkpoint::load(checkpoint_path).await {
+            Some(saved) if self.config.resume => saved,
+            _ => return Ok(File::create(part_path).await?),
+        };
+
+        let file_len = fs::metadata(part_path).await.ok().map(|metadata| metadata.len());
+        if let Some(reason) = saved.mismatch(checkpoint, total_segments, file_len) {
+            on_event(ProgressEvent::ResumeDiscarded { reason });
+            return Ok(File::create(part_path).await?);
+        }
+
+        let mut file = OpenOptions::new().write(true).open(part_path).await?;
+        file.set_len(saved.bytes_written).await?;
+        file.seek(SeekFrom::End(0)).await?;
+        on_event(ProgressEvent::Resumed { completed: offset + saved.completed_segments, total });
+        *checkpoint = saved;
+        Ok(file)
+    }
+
+    /// Finds the master playlist URL, falling back to the content API when the
+    /// embed page cannot be parsed.
+    pub async fn resolve_master_playlist<F>(&self, embed: &EmbedUrl, on_event: &F) -> Result<String>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let metadata = self.resolve(embed, on_event).await?;
+        Ok(metadata.master_url().expect("resolve returns at least one asset").to_string())
+    }
+
+    /// Describes the video from the `__NEXT_DATA__` JSON of the embed page,
+    /// falling back to the content API when the page cannot be parsed. The
+    /// returned metadata has at least one media asset, the master playlist.
+    pub async fn resolve<F>(&self, embed: &EmbedUrl, on_event: &F) -> Result<VideoMetadata>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let from_page = match self.get_page_data(embed.url.as_str(), on_event).await {
+            Ok(data) => VideoMetadata::from_page(&embed.video_id, &data),
+            Err(e) => Err(e),
+        };
+        match from_page {
+            Ok(metadata) => Ok(metadata),
+            Err(e) => {
+                on_event(ProgressEvent::PageFallback { reason: e.to_string() });
+                let config = self.get_api_config(&embed.video_id, &embed.token, &embed.signature, on_event).await?;
+                VideoMetadata::from_api(&embed.video_id, &config)
+            }
+        }
+    }
+
+    /// Extracts the master playlist URL from the `__NEXT_DATA__` JSON of the embed page.
+    pub async fn get_master_playlist_url<F>(&self, embed_url: &str, on_event: &F) -> Result<String>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let metadata = VideoMetadata::from_page("", &self.get_page_data(embed_url, on_event).await?)?;
+        Ok(metadata.master_url().expect("from_page returns at least one asset").to_string())
+    }
+
+    /// Fetches the embed page and returns the `applicationData` object of its
+    /// `__NEXT_DATA__` JSON.
+    async fn get_page_data<F>(&self, embed_url: &str, on_event: &F) -> Result<Value>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let page_html = self.fetch_text(embed_url, on_event).await?;
+        metadata::application_data(&page_html)
+    }
+
+    /// Lists the variant streams of a master playlist in playlist order.
+    ///
+    /// If `master_url` already points at a media playlist it is returned as the only variant.
+    pub async fn get_variants<F>(&self, master_url: &str, on_event: &F) -> Result<Vec<Variant>>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        Ok(self.get_streams(master_url, on_event).await?.0)
+    }
+
+    /// Lists the streams of the first media asset, in order of
+    /// [preference](VideoMetadata::preferred_assets), whose master playlist can
+    /// be fetched and parsed. Returns its index in `metadata.assets` with the
+    /// streams; if every asset fails, the first asset's error is returned.
+    async fn open_asset<F>(&self, metadata: &VideoMetadata, on_event: &F) -> Result<(usize, Vec<Variant>, Vec<Rendition>)>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let mut first_error = None;
+        for i in metadata.preferred_assets() {
+            let url = &metadata.assets[i].url;
+            on_event(ProgressEvent::MasterPlaylist { url: url.clone() });
+            match self.get_streams(url, on_event).await {
+                Ok((variants, renditions)) => return Ok((i, variants, renditions)),
+                Err(e) => {
+                    on_event(ProgressEvent::AssetFallback { url: url.clone(), reason: e.to_string() });
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or_else(|| Error::PlaylistNotFound { reason: "no supported media asset".to_string() }))
+    }
+
+    /// Lists the variant streams and `#EXT-X-MEDIA` renditions of a master playlist.
+    async fn get_streams<F>(&self, master_url: &str, on_event: &F) -> Result<(Vec<Variant>, Vec<Rendition>)>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let master_playlist = match m3u8::parse(&self.fetch_text(master_url, on_event).await?)? {
+            Playlist::Master(master) => master,
+            Playlist::Media(_) => return Ok((vec![Variant::media_playlist(master_url)], Vec::new())),
+        };
+
+        let variants = resolve_variants(master_url, &master_playlist)?;
+        if variants.is_empty() {
+            return Err(Error::NoVariants);
+        }
+        Ok((variants, resolve_renditions(master_url, &master_playlist)?))
+    }
+
+    /// Picks the variant of a master playlist that `selector` prefers.
+    pub async fn select_variant<F>(&self, master_url: &str, selector: &VariantSelector, on_event: &F) -> Result<Variant>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let variants = self.get_variants(master_url, on_event).await?;
+        selector.select(&variants)
+            .cloned()
+            .ok_or_else(|| Error::NoMatchingVariant { quality: selector.to_string() })
+    }
+
+    /// Picks the variant with the highest resolution, then the highest `BANDWIDTH`.
+    pub async fn get_best_quality_stream<F>(&self, master_url: &str, on_event: &F) -> Result<Variant>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        self.select_variant(master_url, &VariantSelector::best(), on_event).await
+    }
+
+    /// Lists the segments of a media playlist along with their encryption keys,
+    /// byte ranges and initialization sections.
+    pub async fn get_media_segments<F>(&self, playlist_url: &str, on_event: &F) -> Result<Vec<MediaSegment>>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let playlist = m3u8::parse_media(&self.fetch_text(playlist_url, on_event).await?)?;
+        resolve_segments(playlist_url, &playlist)
+    }
+
+    /// Fetches the WebVTT segments of a subtitle playlist and stitches them
+    /// into one list of cues, timed from the MPEG-TS timestamp `start`.
+    async fn get_subtitles<F>(&self, playlist_url: &str, fetcher: &SegmentFetcher, start: Option<u64>, on_event: &F) -> Result<Vec<Cue>>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let segments = self.get_media_segments(playlist_url, on_event).await?;
+        let mut texts = Vec::with_capacity(segments.len());
+        for (i, segment) in segments.iter().enumerate() {
+            texts.push(String::from_utf8_lossy(&fetcher.fetch_segment(i, segment).await?).into_owned());
+        }
+        subtitles::stitch(&texts, start)
+    }
+
+    /// Asks the content player API for the master playlist URL.
+    pub async fn get_api_playlist_url<F>(&self, video_id: &str, token: &str, signature: &str, on_event: &F) -> Result<String>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let metadata = VideoMetadata::from_api(video_id, &self.get_api_config(video_id, token, signature, on_event).await?)?;
+        Ok(metadata.master_url().expect("from_api returns one asset").to_string())
+    }
+
+    /// Asks the content player API for the video's configuration.
+    async fn get_api_config<F>(&self, video_id: &str, token: &str, signature: &str, on_event: &F) -> Result<Value>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        let api_url = self.config.api_url.as_str();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+
+        let request = self.client.post(api_url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json, text/plain, */*")
+            .header("x-hotmart-app", "web-player")
+            .header("x-hotmart-key", token)
+            .json(&serde_json::json!({
+                "videoId": video_id,
+                "token": token,
+                "timestamp": timestamp,
+                "signature": signature,
+                "captcha": serde_json::Value::Null,
+                "locale": "en"
+            }));
+
+        let body = with_retry(&self.config.retry, &self.control, api_url, on_event, || async {
+            let request = request.try_clone().expect("JSON request bodies can be cloned");
+            let response = request.send().await.map_err(|e| Error::request(api_url, e))?;
+            check_status(api_url, response)?
+                .text()
+                .await
+                .map_err(|e| Error::request(api_url, e))
+        }).await?;
+        serde_json::from_str(&body).map_err(|e| Error::PlaylistNotFound {
+            reason: format!("invalid API response: {}", e),
+        })
+    }
+
+    async fn get(&self, url: &str) -> Result<Response> {
+        let response = self.client.get(url).send().await.map_err(|e| Error::request(url, e))?;
+        check_status(url, response)
+    }
+
+    async fn fetch_text<F>(&self, url: &str, on_event: &F) -> Result<String>
+    where
+        F: Fn(ProgressEvent) + ?Sized,
+    {
+        with_retry(&self.config.retry, &self.control, url, on_event, || async {
+            self.get(url).await?.text().await.map_err(|e| Error::request(url, e))
+        }).await
+    }
+}
+
+/// Runs the remuxer on the blocking thread pool.
+async fn remux_to_mp4(input: &Path, audio: Option<&Path>, subtitles: Vec<SubtitleTrack>, output: &Path) -> Result<()> {
+    let (input, audio, output) = (input.to_path_buf(), audio.map(Path::to_path_buf), output.to_path_buf());
+    tokio::task::spawn_blocking(move || remux::ts_to_mp4_with(&input, audio.as_deref(), &subtitles, &output))
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Reads the start time of an MPEG-TS file on the blocking thread pool.
+async fn ts_start_time(input: &Path) -> Result<Option<u64>> {
+    let input = input.to_path_buf();
+    tokio::task::spawn_blocking(move || remux::start_time(&input))
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Saves subtitle tracks next to the video, e.g. `lesson.pt-BR.vtt`. Tracks
+/// that share a language get a number appended.
+async fn write_subtitle_sidecars(save_path: &Path, tracks: &[SubtitleTrack], format: SubtitleFormat) -> Result<()> {
+    let mut used = Vec::new();
+    for track in tracks {
+        let label = track.language.as_deref().unwrap_or("subtitles");
+        let count = used.iter().filter(|&&used| used == label).count();
+        used.push(label);
+        let label = if count == 0 { label.to_string() } else { format!("{}-{}", label, count + 1) };
+
+        let (extension, contents) = match format {
+            SubtitleFormat::Srt => ("srt", subtitles::to_srt(&track.cues)),
+            SubtitleFormat::Vtt | SubtitleFormat::Embedded => ("vtt", subtitles::to_webvtt(&track.cues)),
+        };
+        fs::write(save_path.with_extension(format!("{}.{}", label, extension)), contents).await?;
+    }
+    Ok(())
+}
+
+/// Output paths taken by the videos of a batch, with the index of the video.
+type OutputClaims = Mutex<HashMap<PathBuf, usize>>;
+
+/// How a stream's segments are packaged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Ts,
+    Fmp4,
+    /// ADTS frames with ID3 timestamps.
+    PackedAudio,
+}
+
+impl Container {
+    /// The packaging as far as the playlist tells: packed audio is only
+    /// recognised by the `.aac` extension of its segment URLs.
+    fn of(segments: &[MediaSegment]) -> Self {
+        if segments.iter().any(|segment| segment.map.is_some()) {
+            Self::Fmp4
+        } else if segments.first().is_some_and(|segment| strip_signature(&segment.url).ends_with(".aac")) {
+            Self::PackedAudio
+        } else {
+            Self::Ts
+        }
+    }
+
+    /// The packaging of the downloaded stream at `path`, from its first bytes
+    /// unless the playlist has an `#EXT-X-MAP`.
+    async fn sniff(path: &Path, segments: &[MediaSegment]) -> Result<Self> {
+        if Self::of(segments) == Self::Fmp4 {
+            return Ok(Self::Fmp4);
+        }
+        let mut head = Vec::with_capacity(4);
+        File::open(path).await?.take(4).read_to_end(&mut head).await?;
+        Ok(if remux::is_packed_audio(&head) { Self::PackedAudio } else { Self::Ts })
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ts => "MPEG-TS",
+            Self::Fmp4 => "fragmented MP4",
+            Self::PackedAudio => "packed AAC",
+        })
+    }
+}
+
+/// Where an audio rendition that was not muxed into the video is kept, e.g.
+/// `lesson.pt-BR.aac` next to `lesson.ts`.
+fn audio_sidecar_path(save_path: &Path, rendition: &Rendition, container: Container) -> PathBuf {
+    let extension = match container {
+        Container::Ts => "ts",
+        Container::Fmp4 => "m4a",
+        Container::PackedAudio => "aac",
+    };
+    let label = rendition.language.as_deref().unwrap_or(&rendition.group_id);
+    save_path.with_extension(format!("{}.{}", label, extension))
+}
+
+fn header_value(value: &str) -> Result<header::HeaderValue> {
+    header::HeaderValue::from_str(value)
+        .map_err(|_| Error::InvalidHeader(format!("value {:?}", value)))
+}
@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Result type used throughout the crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while resolving or downloading a video.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The embed URL (or a URL found in a playlist) could not be parsed.
+    #[error("invalid URL: {0}")]
+    UrlParse(String),
+
+    /// The server rejected a signed URL, which usually means the link has expired.
+    #[error("link expired or access denied (HTTP {status}) at {url}")]
+    LinkExpired { url: String, status: u16 },
+
+    /// Neither the embed page nor the content API yielded a master playlist URL.
+    #[error("master playlist not found: {reason}")]
+    PlaylistNotFound { reason: String },
+
+    /// The master playlist does not list any variant streams.
+    #[error("master playlist contains no variant streams")]
+    NoVariants,
+
+    /// None of the variant streams satisfies the requested quality.
+    #[error("no variant stream matches quality {quality}")]
+    NoMatchingVariant { quality: String },
+
+    /// A playlist was fetched but its contents are malformed.
+    #[error("invalid playlist: {reason}")]
+    InvalidPlaylist { reason: String },
+
+    /// A request returned a non-success status other than an expired link.
+    #[error("HTTP {status} at {url}")]
+    HttpStatus { url: String, status: u16, retry_after: Option<Duration> },
+
+    /// A request timed out.
+    #[error("request to {url} timed out")]
+    Timeout { url: String },
+
+    /// A request failed before a response was received, or while reading its body.
+    #[error("request to {url} failed: {source}")]
+    Network {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// A decryption key could not be fetched.
+    #[error("failed to fetch key {url}: {reason}")]
+    KeyFetch { url: String, status: Option<u16>, reason: String },
+
+    /// A segment could not be decrypted, usually because of a wrong key or IV.
+    #[error("failed to decrypt segment {segment_index}: {source}")]
+    Decrypt {
+        segment_index: usize,
+        #[source]
+        source: DecryptError,
+    },
+
+    /// The downloaded MPEG-TS stream could not be remuxed into MP4.
+    #[error("remux failed: {reason}")]
+    Remux { reason: String },
+
+    /// A separate audio rendition cannot be muxed into the MP4 output.
+    #[error("cannot mux {audio} audio into {video} video; save the raw stream to keep the audio as a separate file")]
+    UnsupportedAudio { audio: String, video: String },
+
+    /// A WebVTT subtitle segment could not be parsed.
+    #[error("invalid subtitles: {reason}")]
+    Subtitles { reason: String },
+
+    /// Another video of the same batch is saved to the same path.
+    #[error("{} is also the output of video {} of the batch", .path.display(), .other + 1)]
+    OutputConflict { path: PathBuf, other: usize },
+
+    /// The finished output failed a consistency check and was not moved into place.
+    #[error("output check failed: {reason}")]
+    OutputCheck { reason: String },
+
+    /// The download was cancelled through its [`DownloadControl`](crate::DownloadControl).
+    #[error("download cancelled")]
+    Cancelled,
+
+    /// A header passed to the downloader configuration is not valid HTTP.
+    #[error("invalid header {0}")]
+    InvalidHeader(String),
+
+    /// The HTTP client could not be constructed.
+    #[error("failed to build HTTP client: {0}")]
+    Client(#[source] reqwest::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl From<crate::m3u8::ParseError> for Error {
+    fn from(e: crate::m3u8::ParseError) -> Self {
+        Error::InvalidPlaylist { reason: e.to_string() }
+    }
+}
+
+impl Error {
+    /// Wraps a transport error for `url`, separating timeouts from other failures.
+    pub(crate) fn request(url: &str, source: reqwest::Error) -> Self {
+        if source.is_timeout() {
+            Error::Timeout { url: url.to_string() }
+        } else {
+            Error::Network { url: url.to_string(), source }
+        }
+    }
+
+    pub(crate) fn invalid_playlist(reason: impl Into<String>) -> Self {
+        Error::InvalidPlaylist { reason: reason.into() }
+    }
+
+    /// Whether the failure is likely transient and the request worth repeating.
+    ///
+    /// Timeouts, dropped connections, 408, 429 and 5xx responses are retried.
+    /// Expired links, 404s and everything that is not a transport problem are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout { .. } => true,
+            Error::Network { source, .. } => !source.is_builder(),
+            Error::HttpStatus { status, .. } => matches!(status, 408 | 429 | 500..=599),
+            _ => false,
+        }
+    }
+
+    /// Whether the signed link has expired, also when it was the key server that refused it.
+    pub fn is_link_expired(&self) -> bool {
+        match self {
+            Error::LinkExpired { .. } => true,
+            Error::KeyFetch { status: Some(status), .. } => crate::http::means_link_expired(*status),
+            _ => false,
+        }
+    }
+
+    /// Delay requested by the server through a `Retry-After` header.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::HttpStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn remux(reason: impl Into<String>) -> Self {
+        Error::Remux { reason: reason.into() }
+    }
+
+    /// HTTP status code associated with the failure, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::LinkExpired { status, .. } | Error::HttpStatus { status, .. } => Some(*status),
+            Error::KeyFetch { status, .. } => *status,
+            Error::Network { source, .. } => source.status().map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+}
+
+/// Why [`decrypt_segment`](crate::decrypt_segment) failed.
+#[derive(Debug, Error)]
+pub enum DecryptError {
+    #[error("key must be 16 bytes and IV must be 16 bytes (got {key_len} and {iv_len})")]
+    InvalidLength { key_len: usize, iv_len: usize },
+
+    #[error("bad padding; the key or IV is probably wrong")]
+    BadPadding,
+}
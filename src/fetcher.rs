@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use reqwest::Client;
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+
+use crate::config::RetryPolicy;
+use crate::control::DownloadControl;
+use crate::crypto::decrypt_segment;
+use crate::error::{Error, Result};
+use crate::http::{fetch_range, with_retry};
+use crate::playlist::{InitSegment, MediaSegment, SegmentKey};
+use crate::progress::EventSink;
+
+/// Fetches and decrypts segments for the download workers.
+///
+/// Clones share one key cache, and each key is fetched once even when several
+/// workers ask for it at the same time. No request is started while the
+/// download is paused.
+#[derive(Clone)]
+pub(crate) struct SegmentFetcher {
+    client: Client,
+    retry: RetryPolicy,
+    on_event: EventSink,
+    control: DownloadControl,
+    keys: Arc<KeyCells>,
+}
+
+/// One cell per key URL, filled by whichever worker needs the key first.
+type KeyCells = Mutex<HashMap<String, Arc<OnceCell<Vec<u8>>>>>;
+
+impl SegmentFetcher {
+    pub(crate) fn new(client: Client, retry: RetryPolicy, on_event: EventSink, control: DownloadControl) -> Self {
+        Self { client, retry, on_event, control, keys: Arc::default() }
+    }
+
+    /// Fetches segment `index` and decrypts it if needed.
+    pub(crate) async fn fetch_segment(&self, index: usize, segment: &MediaSegment) -> Result<Vec<u8>> {
+        self.fetch_decrypted(index, &segment.url, segment.byte_range.clone(), segment.key.as_ref()).await
+    }
+
+    /// Fetches the initialization section that precedes segment `index`.
+    pub(crate) async fn fetch_init(&self, index: usize, map: &InitSegment) -> Result<Vec<u8>> {
+        self.fetch_decrypted(index, &map.url, map.byte_range.clone(), map.key.as_ref()).await
+    }
+
+    async fn fetch_decrypted(
+        &self,
+        index: usize,
+        url: &str,
+        range: Option<Range<u64>>,
+        key: Option<&SegmentKey>,
+    ) -> Result<Vec<u8>> {
+        let data = self.fetch(url, range).await?;
+
+        // If segment is encrypted, decrypt it
+        match key {
+            Some(key) => {
+                let decryption_key = self.key(&key.url).await?;
+                decrypt_segment(&data, &decryption_key, &key.iv)
+                    .map_err(|source| Error::Decrypt { segment_index: index, source })
+            }
+            None => Ok(data),
+        }
+    }
+
+    /// Returns the key at `url`, fetching it on first use.
+    async fn key(&self, url: &str) -> Result<Vec<u8>> {
+        let cell = self.keys.lock().unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+
+        let key = cell.get_or_try_init(|| async {
+            self.fetch(url, None).await.map_err(|e| match e {
+                Error::Cancelled => e,
+                e => Error::KeyFetch { url: url.to_string(), status: e.status(), reason: e.to_string() },
+            })
+        }).await?;
+        Ok(key.clone())
+    }
+
+    async fn fetch(&self, url: &str, range: Option<Range<u64>>) -> Result<Vec<u8>> {
+        with_retry(&self.retry, &self.control, url, &*self.on_event, || fetch_range(&self.client, url, range.clone())).await
+    }
+}
+
+/// A spawned task that is aborted when dropped, so a failed download does not
+/// leave its workers running.
+pub(crate) struct WorkerTask<T>(JoinHandle<T>);
+
+impl<T: Send + 'static> WorkerTask<T> {
+    pub(crate) fn spawn(future: impl Future<Output = T> + Send + 'static) -> Self {
+        Self(tokio::spawn(future))
+    }
+}
+
+impl<T> Future for WorkerTask<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.0).poll(cx).map(|result| match result {
+            Ok(value) => value,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("worker task stopped unexpectedly: {}", e),
+        })
+    }
+}
+
+impl<T> Drop for WorkerTask<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
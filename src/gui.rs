@@ -2,187 +2,423 @@ use eframe::egui;
 use eframe::App;
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
-use std::path::PathBuf;
-use crate::HotmartDownloader;
+use std::path::{Path, PathBuf};
+use hotmart_ripper::{DownloadControl, Error, HotmartDownloader, ProgressEvent};
 use rfd::FileDialog;
 use std::process::Command;
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 
+/// Most downloads the queue runs at the same time.
+const MAX_PARALLEL: usize = 8;
+
+/// Where a video in the queue stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobState {
+    Queued,
+    Running,
+    /// Cancelled, but the task has not stopped yet.
+    Cancelling,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// Progress of a queued video, shared with the task downloading it.
+struct JobStatus {
+    state: JobState,
+    progress: f32,
+    message: String,
+}
+
+/// A video in the download queue.
+struct Job {
+    url: String,
+    save_path: PathBuf,
+    status: Arc<Mutex<JobStatus>>,
+    /// Controls the current attempt, once one has started.
+    control: Option<DownloadControl>,
+    /// Leaves the queue once its task has stopped.
+    removing: bool,
+}
+
+/// A change to the queue asked for from its list, applied once the list is drawn.
+enum QueueAction {
+    MoveUp(usize),
+    MoveDown(usize),
+    Remove(usize),
+    Retry(usize),
+    ChangeDestination(usize),
+}
+
 pub struct HotmartGui {
     url_input: String,
-    status: Arc<Mutex<String>>,
-    progress: Arc<Mutex<f32>>,
-    is_downloading: Arc<Mutex<bool>>,
-    download_complete: Arc<Mutex<bool>>,
-    rt: Runtime,
     save_path: Option<PathBuf>,
+    queue: Vec<Job>,
+    /// Number of videos downloaded at the same time.
+    parallel: usize,
+    /// Why the last change to the queue was refused.
+    notice: Option<String>,
+    rt: Runtime,
 }
 
 impl Default for HotmartGui {
     fn default() -> Self {
         Self {
             url_input: String::new(),
-            status: Arc::new(Mutex::new("Ready".to_string())),
-            progress: Arc::new(Mutex::new(0.0)),
-            is_downloading: Arc::new(Mutex::new(false)),
-            download_complete: Arc::new(Mutex::new(false)),
-            rt: Runtime::new().expect("Failed to create Tokio runtime"),
             save_path: None,
+            queue: Vec::new(),
+            parallel: 2,
+            notice: None,
+            rt: Runtime::new().expect("Failed to create Tokio runtime"),
         }
     }
 }
 
-impl HotmartGui {
-    fn open_video(&self) {
-        if let Some(path) = &self.save_path {
-            #[cfg(target_os = "windows")]
-            {
-                // On Windows, use the CREATE_NO_WINDOW flag to prevent command prompt from flashing
-                const CREATE_NO_WINDOW: u32 = 0x08000000;
-                Command::new("cmd")
-                    .args(["/C", "start", "", path.to_str().unwrap_or("")])
-                    .creation_flags(CREATE_NO_WINDOW)
-                    .spawn()
-                    .ok();
+impl Job {
+    fn new(url: String, save_path: PathBuf) -> Self {
+        let status = JobStatus { state: JobState::Queued, progress: 0.0, message: "Queued".to_string() };
+        Self { url, save_path, status: Arc::new(Mutex::new(status)), control: None, removing: false }
+    }
+
+    fn state(&self) -> JobState {
+        self.status.lock().unwrap().state
+    }
+
+    /// Puts a finished job back in the queue. A cancelled or failed download
+    /// continues from its checkpoint.
+    fn requeue(&mut self) {
+        let mut status = self.status.lock().unwrap();
+        status.state = JobState::Queued;
+        status.progress = 0.0;
+        status.message = "Queued".to_string();
+        self.control = None;
+    }
+
+    /// Cancels the running download. The job keeps its slot until the task stops.
+    fn cancel(&self) {
+        let mut status = self.status.lock().unwrap();
+        if let (JobState::Running, Some(control)) = (status.state, &self.control) {
+            control.cancel();
+            status.state = JobState::Cancelling;
+            status.message = "Cancelling...".to_string();
+        }
+    }
+
+    /// Whether the job has a task that is still running.
+    fn is_active(&self) -> bool {
+        matches!(self.state(), JobState::Running | JobState::Cancelling)
+    }
+
+    /// Starts downloading on `rt`; the task reports into the shared status.
+    fn start(&mut self, rt: &Runtime) {
+        let downloader = match HotmartDownloader::new() {
+            Ok(downloader) => downloader,
+            Err(e) => {
+                let mut status = self.status.lock().unwrap();
+                status.state = JobState::Failed;
+                status.message = format!("Failed to initialize downloader: {}", e);
+                return;
             }
+        };
+        let control = downloader.control().clone();
+        self.control = Some(control.clone());
+        {
+            let mut status = self.status.lock().unwrap();
+            status.state = JobState::Running;
+            status.message = "Starting...".to_string();
+        }
+
+        let url = self.url.clone();
+        let save_path = self.save_path.clone();
+        let status = self.status.clone();
+        let status_for_progress = self.status.clone();
 
-            #[cfg(target_os = "macos")]
-            {
-                Command::new("open")
-                    .arg(path)
-                    .spawn()
-                    .ok();
+        rt.spawn(async move {
+            let result = downloader.download(
+                &url,
+                save_path,
+                move |event| {
+                    let mut status = status_for_progress.lock().unwrap();
+                    if status.state == JobState::Cancelling {
+                        return;
+                    }
+                    match event {
+                        ProgressEvent::Segment { current, total } => {
+                            status.progress = current as f32 / total as f32;
+                            // Segments already in flight still arrive after pausing
+                            let state = if control.is_paused() { "Paused at" } else { "Downloading..." };
+                            status.message = format!("{} {}/{}", state, current, total);
+                        }
+                        ProgressEvent::Resumed { completed, total } => {
+                            status.progress = completed as f32 / total as f32;
+                            status.message = format!("Resuming after segment {}/{}", completed, total);
+                        }
+                        ProgressEvent::Retry { attempt, max_retries, delay, .. } => {
+                            status.message = format!(
+                                "Request failed, retrying in {:.1}s ({}/{})",
+                                delay.as_secs_f64(),
+                                attempt,
+                                max_retries
+                            );
+                        }
+                        ProgressEvent::Remuxing => {
+                            status.message = "Remuxing into MP4...".to_string();
+                        }
+                        _ => {}
+                    }
+                },
+            ).await;
+
+            let mut status = status.lock().unwrap();
+            let (state, message) = match result {
+                Ok(()) => (JobState::Done, "Download complete!".to_string()),
+                Err(e) if e.is_link_expired() => {
+                    (JobState::Failed, "Error: the link has expired, copy a fresh URL from the player".to_string())
+                }
+                Err(Error::Cancelled) => (JobState::Cancelled, "Cancelled. Retry to continue where it stopped".to_string()),
+                Err(e) => (JobState::Failed, format!("Error: {}", e)),
+            };
+            status.state = state;
+            status.message = message;
+        });
+    }
+}
+
+impl HotmartGui {
+    /// Starts queued videos, first in the queue first, until `parallel` are running.
+    /// Downloads that are still stopping count as running.
+    fn start_queued(&mut self) {
+        let mut running = self.queue.iter().filter(|job| job.is_active()).count();
+        for job in &mut self.queue {
+            if running >= self.parallel {
+                break;
+            }
+            if job.state() == JobState::Queued {
+                job.start(&self.rt);
+                running += 1;
             }
+        }
+    }
+
+    /// Position of the job other than `except` that saves to `path`. Two jobs
+    /// writing the same file would overwrite each other's partial downloads.
+    fn job_saving_to(&self, path: &Path, except: Option<usize>) -> Option<usize> {
+        self.queue.iter()
+            .enumerate()
+            .position(|(i, job)| Some(i) != except && job.save_path == path)
+    }
+
+    fn add(&mut self) {
+        let save_path = self.save_path.clone().unwrap();
+        if let Some(other) = self.job_saving_to(&save_path, None) {
+            self.notice = Some(format!("Video {} in the queue already saves to {}", other + 1, save_path.display()));
+            return;
+        }
+        self.notice = None;
+        let url = std::mem::take(&mut self.url_input).trim().to_string();
+        self.save_path = None;
+        self.queue.push(Job::new(url, save_path));
+    }
 
-            #[cfg(target_os = "linux")]
-            {
-                Command::new("xdg-open")
-                    .arg(path)
-                    .spawn()
-                    .ok();
+    fn apply(&mut self, action: QueueAction) {
+        match action {
+            QueueAction::MoveUp(i) if i > 0 => self.queue.swap(i, i - 1),
+            QueueAction::MoveDown(i) if i + 1 < self.queue.len() => self.queue.swap(i, i + 1),
+            QueueAction::Remove(i) => {
+                // A running download is cancelled first, and leaves the queue once
+                // its task has stopped; its checkpoint stays on disk.
+                let job = &mut self.queue[i];
+                if job.is_active() {
+                    job.cancel();
+                    job.removing = true;
+                } else {
+                    self.queue.remove(i);
+                }
             }
+            QueueAction::Retry(i) => self.queue[i].requeue(),
+            QueueAction::ChangeDestination(i) => {
+                if let Some(path) = save_dialog() {
+                    match self.job_saving_to(&path, Some(i)) {
+                        Some(other) => self.notice = Some(format!("Video {} in the queue already saves to {}", other + 1, path.display())),
+                        None => {
+                            self.notice = None;
+                            self.queue[i].save_path = path;
+                        }
+                    }
+                }
+            }
+            _ => {}
         }
     }
 }
 
+fn save_dialog() -> Option<PathBuf> {
+    FileDialog::new()
+        .set_title("Save video as")
+        .add_filter("MP4 video", &["mp4"])
+        .save_file()
+}
+
+fn open_video(path: &Path) {
+    #[cfg(target_os = "windows")]
+    {
+        // On Windows, use the CREATE_NO_WINDOW flag to prevent command prompt from flashing
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        Command::new("cmd")
+            .args(["/C", "start", "", path.to_str().unwrap_or("")])
+            .creation_flags(CREATE_NO_WINDOW)
+            .spawn()
+            .ok();
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        Command::new("open")
+            .arg(path)
+            .spawn()
+            .ok();
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        Command::new("xdg-open")
+            .arg(path)
+            .spawn()
+            .ok();
+    }
+}
+
 impl eframe::App for HotmartGui {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.queue.retain(|job| !job.removing || job.is_active());
+        self.start_queued();
+        let mut actions = Vec::new();
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.heading("Hotmart Video Downloader");
+            });
 
-                // URL input
-                ui.add_space(20.0);
-                ui.horizontal(|ui| {
-                    ui.label("Video URL:");
-                    ui.text_edit_singleline(&mut self.url_input);
-                });
-
-                // Save location
-                ui.add_space(10.0);
-                ui.horizontal(|ui| {
-                    ui.label("Save location:");
-                    if let Some(path) = &self.save_path {
-                        ui.label(path.to_string_lossy().to_string());
-                    } else {
-                        ui.label("Not selected");
-                    }
-                    if ui.button("Browse").clicked() {
-                        if let Some(path) = FileDialog::new()
-                            .set_title("Save video as")
-                            .add_filter("MP4 video", &["mp4"])
-                            .save_file() {
-                            self.save_path = Some(path);
-                        }
+            // URL input
+            ui.add_space(20.0);
+            ui.horizontal(|ui| {
+                ui.label("Video URL:");
+                ui.text_edit_singleline(&mut self.url_input);
+            });
+
+            // Save location
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                ui.label("Save location:");
+                if let Some(path) = &self.save_path {
+                    ui.label(path.to_string_lossy().to_string());
+                } else {
+                    ui.label("Not selected");
+                }
+                if ui.button("Browse").clicked() {
+                    if let Some(path) = save_dialog() {
+                        self.save_path = Some(path);
                     }
-                });
+                }
+            });
 
-                // Download button
-                ui.add_space(20.0);
-                let is_downloading = *self.is_downloading.lock().unwrap();
+            // Add to queue button
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
                 if ui.add_enabled(
-                    !is_downloading && !self.url_input.is_empty() && self.save_path.is_some(),
-                    egui::Button::new("Download")
+                    !self.url_input.trim().is_empty() && self.save_path.is_some(),
+                    egui::Button::new("Add to queue")
                 ).clicked() {
-                    let url = self.url_input.clone();
-                    let progress = self.progress.clone();
-                    let is_downloading = self.is_downloading.clone();
-                    let save_path = self.save_path.clone().unwrap();
-                    let download_complete = self.download_complete.clone();
-
-                    // Clone status Arc for both closures
-                    let status_for_progress = self.status.clone();
-                    let status_for_completion = self.status.clone();
-
-                    *self.is_downloading.lock().unwrap() = true;
-                    *self.download_complete.lock().unwrap() = false;
-
-                    self.rt.spawn(async move {
-                        match HotmartDownloader::new() {
-                            Ok(downloader) => {
-                                let result = downloader.download_video_with_progress_and_path(
-                                    &url,
-                                    save_path,
-                                    move |current, total| {
-                                        if total > 0 {
-                                            *progress.lock().unwrap() = current as f32 / total as f32;
-                                            *status_for_progress.lock().unwrap() = format!(
-                                                "Downloading... {}/{}",
-                                                current,
-                                                total
-                                            );
-                                        }
-                                    },
-                                ).await;
+                    self.add();
+                }
+                ui.add_space(20.0);
+                ui.label("Parallel downloads:");
+                ui.add(egui::Slider::new(&mut self.parallel, 1..=MAX_PARALLEL));
+            });
+            if let Some(notice) = &self.notice {
+                ui.label(notice);
+            }
+
+            // Queue
+            ui.add_space(20.0);
+            ui.separator();
+            if self.queue.is_empty() {
+                ui.label("The queue is empty. Add a video URL and a save location above.");
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let last = self.queue.len().saturating_sub(1);
+                for (i, job) in self.queue.iter().enumerate() {
+                    let (state, progress, message) = {
+                        let status = job.status.lock().unwrap();
+                        (status.state, status.progress, status.message.clone())
+                    };
+
+                    ui.group(|ui| {
+                        ui.label(format!("{}. {}", i + 1, job.url));
+                        ui.label(format!("Save to: {}", job.save_path.display()));
+                        ui.add(
+                            egui::ProgressBar::new(progress)
+                                .text(format!("{:.0}%  {}", progress * 100.0, message))
+                                .desired_width(ui.available_width())
+                        );
 
-                                match result {
-                                    Ok(_) => {
-                                        *status_for_completion.lock().unwrap() = "Download complete!".to_string();
-                                        *download_complete.lock().unwrap() = true;
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(i > 0, egui::Button::new("Up")).clicked() {
+                                actions.push(QueueAction::MoveUp(i));
+                            }
+                            if ui.add_enabled(i < last, egui::Button::new("Down")).clicked() {
+                                actions.push(QueueAction::MoveDown(i));
+                            }
+                            if ui.add_enabled(!job.removing, egui::Button::new("Remove")).clicked() {
+                                actions.push(QueueAction::Remove(i));
+                            }
+                            match (state, &job.control) {
+                                (JobState::Queued, _) => {
+                                    if ui.button("Change location").clicked() {
+                                        actions.push(QueueAction::ChangeDestination(i));
+                                    }
+                                }
+                                (JobState::Running, Some(control)) => {
+                                    let paused = control.is_paused();
+                                    let cancelled = control.is_cancelled();
+                                    if ui.add_enabled(!cancelled, egui::Button::new(if paused { "Resume" } else { "Pause" })).clicked() {
+                                        if paused {
+                                            control.resume();
+                                            job.status.lock().unwrap().message = "Resuming...".to_string();
+                                        } else {
+                                            control.pause();
+                                            job.status.lock().unwrap().message = "Pausing after the segments in flight...".to_string();
+                                        }
                                     }
-                                    Err(e) => {
-                                        *status_for_completion.lock().unwrap() = format!("Error: {}", e);
-                                        *download_complete.lock().unwrap() = false;
+                                    if ui.add_enabled(!cancelled, egui::Button::new("Cancel")).clicked() {
+                                        job.cancel();
                                     }
                                 }
+                                (JobState::Done, _) => {
+                                    if ui.button("Open Video").clicked() {
+                                        open_video(&job.save_path);
+                                    }
+                                }
+                                (JobState::Failed | JobState::Cancelled, _) => {
+                                    if ui.button("Retry").clicked() {
+                                        actions.push(QueueAction::Retry(i));
+                                    }
+                                }
+                                (JobState::Running, None) | (JobState::Cancelling, _) => {}
                             }
-                            Err(e) => {
-                                *status_for_completion.lock().unwrap() = format!("Failed to initialize downloader: {}", e);
-                                *download_complete.lock().unwrap() = false;
-                            }
-                        }
-                        *is_downloading.lock().unwrap() = false;
+                        });
                     });
                 }
-
-                // Progress bar
-                ui.add_space(20.0);
-                let progress = *self.progress.lock().unwrap();
-                let status = self.status.lock().unwrap().clone();
-
-                ui.add(
-                    egui::ProgressBar::new(progress)
-                        .show_percentage()
-                        .desired_width(ui.available_width())
-                );
-
-                // Status message
-                ui.add_space(10.0);
-                ui.label(&status);
-
-                // Open Video button - only show when download is complete
-                if *self.download_complete.lock().unwrap() {
-                    ui.add_space(10.0);
-                    if ui.button("Open Video").clicked() {
-                        self.open_video();
-                    }
-                }
             });
         });
 
-        // Request repaint if downloading
-        if *self.is_downloading.lock().unwrap() {
+        for action in actions {
+            self.apply(action);
+        }
+
+        // Request repaint while anything is downloading
+        if self.queue.iter().any(Job::is_active) {
             ctx.request_repaint();
         }
     }
@@ -191,8 +427,8 @@ impl eframe::App for HotmartGui {
 pub fn run_gui() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([600.0, 400.0])
-            .with_min_inner_size([400.0, 300.0])
+            .with_inner_size([700.0, 600.0])
+            .with_min_inner_size([500.0, 400.0])
             .with_resizable(true),
         ..Default::default()
     };
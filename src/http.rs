@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+use reqwest::{Client, Response, StatusCode, header};
+
+use crate::config::RetryPolicy;
+use crate::control::DownloadControl;
+use crate::error::{Error, Result};
+use crate::progress::ProgressEvent;
+
+/// Longest `Retry-After` we are willing to sleep for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// 401, 403 and 410 are what the CDN and the API answer once a signature has expired.
+pub(crate) fn means_link_expired(status: u16) -> bool {
+    matches!(status, 401 | 403 | 410)
+}
+
+/// Turns error statuses into typed errors.
+pub(crate) fn check_status(url: &str, response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let url = url.to_string();
+    let retry_after = retry_after(&response);
+    let status = status.as_u16();
+    match status {
+        status if means_link_expired(status) => Err(Error::LinkExpired { url, status }),
+        _ => Err(Error::HttpStatus { url, status, retry_after }),
+    }
+}
+
+/// Parses `Retry-After`, given either as delay-seconds or as an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => httpdate::parse_http_date(value).ok()?
+            .duration_since(SystemTime::now())
+            .unwrap_or_default(),
+    };
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Runs `request` until it succeeds, fails with a non-retryable error or
+/// `policy` runs out of retries. Each retry is reported through `on_event`.
+///
+/// Every attempt waits while `control` is paused, and cancelling it also
+/// cuts the wait between attempts short.
+pub(crate) async fn with_retry<T, R, Fut, F>(
+    policy: &RetryPolicy,
+    control: &DownloadControl,
+    url: &str,
+    on_event: &F,
+    mut request: R,
+) -> Result<T>
+where
+    R: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+    F: Fn(ProgressEvent) + ?Sized,
+{
+    let mut attempt = 0;
+    loop {
+        control.proceed().await?;
+        match request().await {
+            Err(e) if e.is_retryable() && attempt < policy.max_retries => {
+                attempt += 1;
+                let delay = e.retry_after().unwrap_or_else(|| policy.backoff(attempt));
+                on_event(ProgressEvent::Retry {
+                    url: url.to_string(),
+                    attempt,
+                    max_retries: policy.max_retries,
+                    delay,
+                    reason: e.to_string(),
+                });
+                control.run(async {
+                    tokio::time::sleep(delay).await;
+                    Ok(())
+                }).await?;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Fetches `url`, or only `range` of it using an HTTP `Range` request.
+pub(crate) async fn fetch_range(client: &Client, url: &str, range: Option<Range<u64>>) -> Result<Vec<u8>> {
+    if range.as_ref().is_some_and(|range| range.is_empty()) {
+        return Ok(Vec::new());
+    }
+
+    let mut request = client.get(url);
+    if let Some(range) = &range {
+        request = request.header(header::RANGE, format!("bytes={}-{}", range.start, range.end - 1));
+    }
+    let response = request.send().await.map_err(|e| Error::request(url, e))?;
+    let response = check_status(url, response)?;
+    let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+    let bytes = response.bytes().await.map_err(|e| Error::request(url, e))?;
+
+    // Servers that ignore `Range` send the whole resource; cut the range out ourselves.
+    match range {
+        Some(range) if !partial => {
+            let start = usize::try_from(range.start).unwrap_or(usize::MAX).min(bytes.len());
+            let end = usize::try_from(range.end).unwrap_or(usize::MAX).min(bytes.len());
+            Ok(bytes[start..end].to_vec())
+        }
+        _ => Ok(bytes.to_vec()),
+    }
+}
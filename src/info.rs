@@ -0,0 +1,100 @@
+use serde_json::{json, Value};
+
+use crate::m3u8::KeyMethod;
+use crate::metadata::VideoMetadata;
+use crate::playlist::{Rendition, Variant};
+
+/// What a video offers, gathered without downloading any segments.
+///
+/// Returned by [`HotmartDownloader::info`](crate::HotmartDownloader::info).
+/// Duration, segment count and encryption come from the media playlist of
+/// the selected variant; the variants of one video share them in practice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoInfo {
+    pub video_id: String,
+    /// Master playlist of the media asset that was inspected.
+    pub master_url: String,
+    /// Index into `metadata.assets` of that asset.
+    pub asset: usize,
+    /// Variant streams in playlist order.
+    pub variants: Vec<Variant>,
+    /// Audio, subtitle and video renditions in playlist order.
+    pub renditions: Vec<Rendition>,
+    /// Index into `variants` of the one the configured quality picks, or
+    /// `None` if it matches none, in which case the best variant was inspected.
+    pub selected: Option<usize>,
+    /// Total duration in seconds.
+    pub duration: f64,
+    pub segment_count: usize,
+    /// Method the segments are encrypted with; `None` when they are not.
+    pub encryption: Option<KeyMethod>,
+    /// Title, producer and the like, as the embed page or the API gives them.
+    pub metadata: VideoMetadata,
+}
+
+impl VideoInfo {
+    /// Expected download size of `variant`, in bytes.
+    pub fn estimated_size(&self, variant: &Variant) -> u64 {
+        variant.estimated_size(self.duration)
+    }
+
+    /// The info as JSON, for scripts.
+    pub fn to_json(&self) -> Value {
+        let variants: Vec<Value> = self.variants.iter().enumerate()
+            .map(|(i, variant)| json!({
+                "url": variant.url,
+                "resolution": variant.resolution.map(|r| r.to_string()),
+                "bandwidth": variant.bandwidth,
+                "average_bandwidth": variant.average_bandwidth,
+                "codecs": variant.codecs,
+                "frame_rate": variant.frame_rate,
+                "audio": variant.audio,
+                "subtitles": variant.subtitles,
+                "estimated_size": self.estimated_size(variant),
+                "selected": self.selected == Some(i),
+            }))
+            .collect();
+        let renditions: Vec<Value> = self.renditions.iter()
+            .map(|rendition| json!({
+                "type": rendition.media_type.to_string(),
+                "group_id": rendition.group_id,
+                "name": rendition.name,
+                "language": rendition.language,
+                "default": rendition.default,
+                "channels": rendition.channels,
+                "url": rendition.url,
+            }))
+            .collect();
+
+        let metadata = &self.metadata;
+        let assets: Vec<Value> = metadata.assets.iter().enumerate()
+            .map(|(i, asset)| json!({
+                "url": asset.url,
+                "type": asset.kind,
+                "mime_type": asset.mime_type,
+                "language": asset.language,
+                "format": asset.format().to_string(),
+                "selected": self.asset == i,
+            }))
+            .collect();
+
+        json!({
+            "video_id": self.video_id,
+            "title": metadata.title,
+            "course": metadata.course,
+            "module": metadata.module,
+            "lesson": metadata.lesson,
+            "producer": metadata.producer,
+            "locale": metadata.locale,
+            "upload_date": metadata.upload_date,
+            "thumbnail_url": metadata.thumbnail_url,
+            "assets": assets,
+            "master_url": self.master_url,
+            "duration": self.duration,
+            "segments": self.segment_count,
+            "encryption": self.encryption.as_ref().map(|method| method.to_string()),
+            "variants": variants,
+            "renditions": renditions,
+        })
+    }
+}
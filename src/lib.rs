@@ -0,0 +1,58 @@
+//! Download library behind the Hotmart Video Ripper.
+//!
+//! The entry point is [`HotmartDownloader`], created either with
+//! [`HotmartDownloader::new`] or through [`HotmartDownloader::builder`]:
+//!
+//! ```no_run
+//! # async fn run() -> hotmart_ripper::Result<()> {
+//! use hotmart_ripper::{HotmartDownloader, ProgressEvent};
+//!
+//! let downloader = HotmartDownloader::builder()
+//!     .timeout(std::time::Duration::from_secs(60))
+//!     .build()?;
+//!
+//! downloader.download("https://player.hotmart.com/embed/...", "lesson.mp4", |event| {
+//!     if let ProgressEvent::Segment { current, total } = event {
+//!         println!("{}/{}", current, total);
+//!     }
+//! }).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The individual pipeline stages are public as well, so callers that only
+//! need part of the work (e.g. listing segments) can drive them directly.
+
+pub mod batch;
+mod checkpoint;
+pub mod config;
+pub mod control;
+pub mod crypto;
+pub mod downloader;
+pub mod error;
+mod fetcher;
+mod http;
+pub mod info;
+pub mod m3u8;
+pub mod metadata;
+pub mod playlist;
+pub mod progress;
+pub mod remux;
+pub mod select;
+pub mod subtitles;
+pub mod template;
+pub mod verify;
+
+pub use batch::BatchEntry;
+pub use config::{DownloaderBuilder, DownloaderConfig, OutputFormat, RetryPolicy, SubtitleFormat, SubtitleSelection};
+pub use control::DownloadControl;
+pub use crypto::decrypt_segment;
+pub use downloader::{EmbedUrl, HotmartDownloader};
+pub use error::{DecryptError, Error, Result};
+pub use info::VideoInfo;
+pub use metadata::{MediaAsset, VideoMetadata};
+pub use playlist::{resolve_renditions, resolve_segments, resolve_variants, InitSegment, MediaSegment, Rendition, SegmentKey, Variant};
+pub use progress::ProgressEvent;
+pub use select::{select_rendition, select_subtitles, VariantSelector};
+pub use template::OutputTemplate;
+pub use verify::{verify, TrackSummary, Verified};
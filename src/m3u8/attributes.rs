@@ -0,0 +1,171 @@
+//! Attribute lists as defined in RFC 8216 section 4.2.
+
+use std::fmt::Write;
+
+use super::{ByteRange, Resolution};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Quoted(String),
+    Unquoted(String),
+}
+
+/// A parsed `NAME=VALUE,NAME="VALUE"` attribute list.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct AttributeList {
+    attrs: Vec<(String, Value)>,
+}
+
+impl AttributeList {
+    pub(crate) fn parse(input: &str) -> Result<Self, String> {
+        let mut attrs = Vec::new();
+        let mut rest = input.trim();
+
+        while !rest.is_empty() {
+            let eq = rest.find('=')
+                .ok_or_else(|| format!("attribute without value in {:?}", input))?;
+            let name = rest[..eq].trim();
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-') {
+                return Err(format!("invalid attribute name {:?}", name));
+            }
+            rest = rest[eq + 1..].trim_start();
+
+            let value = if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"')
+                    .ok_or_else(|| format!("unterminated quoted string for {}", name))?;
+                let value = Value::Quoted(quoted[..end].to_string());
+                rest = quoted[end + 1..].trim_start();
+                value
+            } else {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let value = rest[..end].trim();
+                // Only quoted strings may contain quotes.
+                if value.contains('"') {
+                    return Err(format!("stray '\"' in the value of {}", name));
+                }
+                let value = Value::Unquoted(value.to_string());
+                rest = &rest[end..];
+                value
+            };
+
+            if let Some(after_comma) = rest.strip_prefix(',') {
+                rest = after_comma.trim_start();
+            } else if !rest.is_empty() {
+                return Err(format!("expected ',' after {} but found {:?}", name, rest));
+            }
+
+            attrs.push((name.to_string(), value));
+        }
+
+        Ok(Self { attrs })
+    }
+
+    /// Raw value of `name`, whether it was quoted or not.
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.attrs.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| match value {
+                Value::Quoted(s) | Value::Unquoted(s) => s.as_str(),
+            })
+    }
+
+    pub(crate) fn string(&self, name: &str) -> Option<String> {
+        self.get(name).map(str::to_string)
+    }
+
+    pub(crate) fn required(&self, name: &str) -> Result<&str, String> {
+        self.get(name).ok_or_else(|| format!("missing required attribute {}", name))
+    }
+
+    /// Value of an enumerated-string attribute, which must not be quoted.
+    pub(crate) fn enumerated(&self, name: &str) -> Result<Option<&str>, String> {
+        match self.attrs.iter().find(|(n, _)| n == name).map(|(_, value)| value) {
+            Some(Value::Unquoted(s)) => Ok(Some(s.as_str())),
+            Some(Value::Quoted(s)) => Err(format!("{}=\"{}\" must not be quoted", name, s)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn decimal(&self, name: &str) -> Result<Option<u64>, String> {
+        self.get(name)
+            .map(|v| v.parse().map_err(|_| format!("{}={} is not a decimal integer", name, v)))
+            .transpose()
+    }
+
+    /// A non-negative, finite decimal number.
+    pub(crate) fn float(&self, name: &str) -> Result<Option<f64>, String> {
+        self.get(name)
+            .map(|v| v.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+                .ok_or_else(|| format!("{}={} is not a non-negative decimal number", name, v)))
+            .transpose()
+    }
+
+    pub(crate) fn resolution(&self, name: &str) -> Result<Option<Resolution>, String> {
+        self.get(name).map(Resolution::parse).transpose()
+    }
+
+    pub(crate) fn byte_range(&self, name: &str) -> Result<Option<ByteRange>, String> {
+        self.get(name).map(ByteRange::parse).transpose()
+    }
+
+    pub(crate) fn yes_no(&self, name: &str) -> Result<bool, String> {
+        match self.get(name) {
+            None | Some("NO") => Ok(false),
+            Some("YES") => Ok(true),
+            Some(other) => Err(format!("{}={} must be YES or NO", name, other)),
+        }
+    }
+}
+
+/// Builds an attribute list for serialization.
+#[derive(Default)]
+pub(crate) struct AttributeWriter {
+    out: String,
+}
+
+impl AttributeWriter {
+    fn separator(&mut self) {
+        if !self.out.is_empty() {
+            self.out.push(',');
+        }
+    }
+
+    pub(crate) fn quoted(&mut self, name: &str, value: &str) -> &mut Self {
+        self.separator();
+        let _ = write!(self.out, "{}=\"{}\"", name, value);
+        self
+    }
+
+    pub(crate) fn unquoted(&mut self, name: &str, value: impl std::fmt::Display) -> &mut Self {
+        self.separator();
+        let _ = write!(self.out, "{}={}", name, value);
+        self
+    }
+
+    pub(crate) fn opt_quoted(&mut self, name: &str, value: Option<&str>) -> &mut Self {
+        if let Some(value) = value {
+            self.quoted(name, value);
+        }
+        self
+    }
+
+    pub(crate) fn opt_unquoted(&mut self, name: &str, value: Option<impl std::fmt::Display>) -> &mut Self {
+        if let Some(value) = value {
+            self.unquoted(name, value);
+        }
+        self
+    }
+
+    pub(crate) fn flag(&mut self, name: &str, value: bool) -> &mut Self {
+        if value {
+            self.unquoted(name, "YES");
+        }
+        self
+    }
+
+    pub(crate) fn finish(&mut self) -> String {
+        std::mem::take(&mut self.out)
+    }
+}
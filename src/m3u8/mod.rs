@@ -0,0 +1,273 @@
+//! HLS playlist parsing and serialization (RFC 8216).
+//!
+//! [`parse`] detects whether the input is a master or a media playlist and
+//! returns the matching typed model. Both models implement `Display`, which
+//! writes a playlist that parses back to an equal value.
+
+mod attributes;
+mod parser;
+mod writer;
+
+use std::fmt;
+
+pub use parser::{parse, parse_master, parse_media};
+
+/// Error produced when a playlist does not follow RFC 8216.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number the problem was found on, or 0 for whole-document problems.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Either kind of playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+/// A multivariant playlist listing the available streams.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MasterPlaylist {
+    pub version: Option<u64>,
+    pub independent_segments: bool,
+    /// `#EXT-X-STREAM-INF` entries in playlist order.
+    pub variants: Vec<VariantStream>,
+    /// `#EXT-X-I-FRAME-STREAM-INF` entries in playlist order.
+    pub i_frame_streams: Vec<VariantStream>,
+    /// `#EXT-X-MEDIA` entries in playlist order.
+    pub renditions: Vec<Rendition>,
+}
+
+/// A variant stream from `#EXT-X-STREAM-INF` or `#EXT-X-I-FRAME-STREAM-INF`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VariantStream {
+    /// URI of the variant's media playlist, as written in the playlist.
+    pub uri: String,
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub codecs: Option<String>,
+    pub resolution: Option<Resolution>,
+    pub frame_rate: Option<f64>,
+    pub hdcp_level: Option<String>,
+    /// `GROUP-ID` of the audio renditions to play with this variant.
+    pub audio: Option<String>,
+    pub video: Option<String>,
+    pub subtitles: Option<String>,
+    /// Either a `GROUP-ID` or the literal `NONE`.
+    pub closed_captions: Option<String>,
+}
+
+/// Frame size from the `RESOLUTION` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        let (width, height) = value.split_once(['x', 'X'])
+            .ok_or_else(|| format!("invalid resolution {:?}", value))?;
+        Ok(Self {
+            width: width.parse().map_err(|_| format!("invalid resolution {:?}", value))?,
+            height: height.parse().map_err(|_| format!("invalid resolution {:?}", value))?,
+        })
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// `TYPE` of an `#EXT-X-MEDIA` rendition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaType {
+    Audio,
+    Video,
+    Subtitles,
+    ClosedCaptions,
+}
+
+impl MediaType {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "AUDIO" => Ok(Self::Audio),
+            "VIDEO" => Ok(Self::Video),
+            "SUBTITLES" => Ok(Self::Subtitles),
+            "CLOSED-CAPTIONS" => Ok(Self::ClosedCaptions),
+            other => Err(format!("unknown media TYPE {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Audio => "AUDIO",
+            Self::Video => "VIDEO",
+            Self::Subtitles => "SUBTITLES",
+            Self::ClosedCaptions => "CLOSED-CAPTIONS",
+        })
+    }
+}
+
+/// An alternative rendition from `#EXT-X-MEDIA`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    pub media_type: MediaType,
+    /// Media playlist URI. Absent when the rendition is muxed into the variant.
+    pub uri: Option<String>,
+    pub group_id: String,
+    pub language: Option<String>,
+    pub assoc_language: Option<String>,
+    pub name: String,
+    pub default: bool,
+    pub autoselect: bool,
+    pub forced: bool,
+    pub instream_id: Option<String>,
+    pub characteristics: Option<String>,
+    pub channels: Option<String>,
+}
+
+/// A playlist of media segments.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaPlaylist {
+    pub version: Option<u64>,
+    pub target_duration: u64,
+    /// Sequence number of the first segment (`#EXT-X-MEDIA-SEQUENCE`).
+    pub media_sequence: u64,
+    pub discontinuity_sequence: u64,
+    pub playlist_type: Option<PlaylistType>,
+    pub i_frames_only: bool,
+    pub independent_segments: bool,
+    /// Whether `#EXT-X-ENDLIST` was present.
+    pub end_list: bool,
+    pub segments: Vec<Segment>,
+}
+
+impl MediaPlaylist {
+    /// Sum of all segment durations, in seconds.
+    pub fn duration(&self) -> f64 {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+}
+
+/// `#EXT-X-PLAYLIST-TYPE` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistType {
+    Event,
+    Vod,
+}
+
+/// A media segment together with the key and map that apply to it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Segment {
+    /// Segment URI, as written in the playlist.
+    pub uri: String,
+    /// Media sequence number: `#EXT-X-MEDIA-SEQUENCE` plus the segment's position.
+    pub sequence: u64,
+    /// Duration from `#EXTINF`, in seconds.
+    pub duration: f64,
+    pub title: Option<String>,
+    pub byte_range: Option<ByteRange>,
+    /// Whether `#EXT-X-DISCONTINUITY` preceded the segment.
+    pub discontinuity: bool,
+    /// Encryption in effect for the segment; `None` when unencrypted.
+    pub key: Option<Key>,
+    /// Media initialization section in effect for the segment.
+    pub map: Option<Map>,
+    pub program_date_time: Option<String>,
+}
+
+/// `<length>[@<offset>]` from `#EXT-X-BYTERANGE` or a `BYTERANGE` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub length: u64,
+    /// Start offset; when absent the range continues from the previous one.
+    pub offset: Option<u64>,
+}
+
+impl ByteRange {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid byte range {:?}", value);
+        let (length, offset) = match value.split_once('@') {
+            Some((length, offset)) => (length, Some(offset.trim().parse().map_err(|_| invalid())?)),
+            None => (value, None),
+        };
+        Ok(Self { length: length.trim().parse().map_err(|_| invalid())?, offset })
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{}@{}", self.length, offset),
+            None => write!(f, "{}", self.length),
+        }
+    }
+}
+
+/// Encryption method of an `#EXT-X-KEY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMethod {
+    Aes128,
+    SampleAes,
+    Other(String),
+}
+
+impl fmt::Display for KeyMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Aes128 => f.write_str("AES-128"),
+            Self::SampleAes => f.write_str("SAMPLE-AES"),
+            Self::Other(method) => f.write_str(method),
+        }
+    }
+}
+
+/// An `#EXT-X-KEY` with a method other than `NONE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    pub method: KeyMethod,
+    pub uri: String,
+    /// Explicit IV; when absent the segment's media sequence number is used.
+    pub iv: Option<[u8; 16]>,
+    pub key_format: Option<String>,
+    pub key_format_versions: Option<String>,
+}
+
+impl Key {
+    /// IV to decrypt the segment with sequence number `sequence`.
+    ///
+    /// RFC 8216 section 5.2: without an `IV` attribute the media sequence number,
+    /// as a 128-bit big-endian integer, is the IV.
+    pub fn iv_for(&self, sequence: u64) -> [u8; 16] {
+        self.iv.unwrap_or_else(|| u128::from(sequence).to_be_bytes())
+    }
+}
+
+/// `#EXT-X-MAP` media initialization section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Map {
+    pub uri: String,
+    /// Sub-range of `uri`; a missing offset means the range starts at byte 0.
+    pub byte_range: Option<ByteRange>,
+    /// Key in effect where the `#EXT-X-MAP` tag appeared, which encrypts the section.
+    pub key: Option<Key>,
+}
@@ -0,0 +1,266 @@
+use super::attributes::AttributeList;
+use super::{
+    ByteRange, Key, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, ParseError, Playlist,
+    PlaylistType, Rendition, Segment, VariantStream,
+};
+
+/// Tags that only appear in master playlists.
+/// `#EXT-X-SESSION-*` tags are not kept, so they do not count: a playlist with
+/// nothing else would be written back without anything marking it as a master.
+const MASTER_TAGS: &[&str] = &["#EXT-X-STREAM-INF", "#EXT-X-I-FRAME-STREAM-INF", "#EXT-X-MEDIA"];
+
+/// Parses a playlist, deciding from its tags whether it is a master or a media playlist.
+pub fn parse(input: &str) -> Result<Playlist, ParseError> {
+    let is_master = lines(input).any(|(_, line)| MASTER_TAGS.contains(&split_tag(line).0));
+    if is_master {
+        parse_master(input).map(Playlist::Master)
+    } else {
+        parse_media(input).map(Playlist::Media)
+    }
+}
+
+/// Parses a master (multivariant) playlist.
+pub fn parse_master(input: &str) -> Result<MasterPlaylist, ParseError> {
+    let mut playlist = MasterPlaylist::default();
+    let mut pending_variant: Option<(usize, VariantStream)> = None;
+
+    for (number, line) in check_header(input)? {
+        let err = |message: String| ParseError { line: number, message };
+
+        if !line.starts_with('#') {
+            let (_, mut variant) = pending_variant.take()
+                .ok_or_else(|| err(format!("URI {:?} without #EXT-X-STREAM-INF", line)))?;
+            variant.uri = line.to_string();
+            playlist.variants.push(variant);
+            continue;
+        }
+        let (tag, value) = split_tag(line);
+        if let Some((tag_line, _)) = &pending_variant {
+            if tag == "#EXT-X-STREAM-INF" {
+                return Err(ParseError {
+                    line: *tag_line,
+                    message: "#EXT-X-STREAM-INF is not followed by a URI".to_string(),
+                });
+            }
+        }
+
+        match tag {
+            "#EXT-X-VERSION" => playlist.version = Some(parse_number(value).map_err(err)?),
+            "#EXT-X-INDEPENDENT-SEGMENTS" => playlist.independent_segments = true,
+            "#EXT-X-STREAM-INF" => {
+                let attrs = AttributeList::parse(value).map_err(err)?;
+                pending_variant = Some((number, variant_stream(&attrs).map_err(err)?));
+            }
+            "#EXT-X-I-FRAME-STREAM-INF" => {
+                let attrs = AttributeList::parse(value).map_err(err)?;
+                let mut stream = variant_stream(&attrs).map_err(err)?;
+                stream.uri = attrs.required("URI").map_err(err)?.to_string();
+                playlist.i_frame_streams.push(stream);
+            }
+            "#EXT-X-MEDIA" => {
+                let attrs = AttributeList::parse(value).map_err(err)?;
+                playlist.renditions.push(rendition(&attrs).map_err(err)?);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((line, _)) = pending_variant {
+        return Err(ParseError { line, message: "#EXT-X-STREAM-INF is not followed by a URI".to_string() });
+    }
+
+    Ok(playlist)
+}
+
+/// Parses a media playlist. Each segment carries the key and map in effect for it.
+pub fn parse_media(input: &str) -> Result<MediaPlaylist, ParseError> {
+    let mut playlist = MediaPlaylist::default();
+    let mut has_target_duration = false;
+    let mut key: Option<Key> = None;
+    let mut map: Option<Map> = None;
+    let mut pending: Option<(usize, Segment)> = None;
+    let mut byte_range: Option<ByteRange> = None;
+    let mut discontinuity = false;
+    let mut program_date_time: Option<String> = None;
+
+    for (number, line) in check_header(input)? {
+        let err = |message: String| ParseError { line: number, message };
+
+        if !line.starts_with('#') {
+            let (_, mut segment) = pending.take()
+                .ok_or_else(|| err(format!("segment URI {:?} without #EXTINF", line)))?;
+            segment.uri = line.to_string();
+            segment.sequence = playlist.media_sequence.saturating_add(playlist.segments.len() as u64);
+            segment.byte_range = byte_range.take();
+            segment.discontinuity = std::mem::take(&mut discontinuity);
+            segment.program_date_time = program_date_time.take();
+            segment.key = key.clone();
+            segment.map = map.clone();
+            playlist.segments.push(segment);
+            continue;
+        }
+
+        let (tag, value) = split_tag(line);
+        if let Some((tag_line, _)) = &pending {
+            if tag == "#EXTINF" {
+                return Err(ParseError { line: *tag_line, message: "#EXTINF is not followed by a segment URI".to_string() });
+            }
+        }
+        match tag {
+            "#EXT-X-VERSION" => playlist.version = Some(parse_number(value).map_err(err)?),
+            "#EXT-X-TARGETDURATION" => {
+                playlist.target_duration = parse_number(value).map_err(err)?;
+                has_target_duration = true;
+            }
+            // Segment sequence numbers are counted from these.
+            "#EXT-X-MEDIA-SEQUENCE" | "#EXT-X-DISCONTINUITY-SEQUENCE" if !playlist.segments.is_empty() => {
+                return Err(err(format!("{} after the first segment", tag)));
+            }
+            "#EXT-X-MEDIA-SEQUENCE" => playlist.media_sequence = parse_number(value).map_err(err)?,
+            "#EXT-X-DISCONTINUITY-SEQUENCE" => playlist.discontinuity_sequence = parse_number(value).map_err(err)?,
+            "#EXT-X-PLAYLIST-TYPE" => {
+                playlist.playlist_type = Some(match value {
+                    "EVENT" => PlaylistType::Event,
+                    "VOD" => PlaylistType::Vod,
+                    other => return Err(err(format!("unknown playlist type {}", other))),
+                });
+            }
+            "#EXT-X-I-FRAMES-ONLY" => playlist.i_frames_only = true,
+            "#EXT-X-INDEPENDENT-SEGMENTS" => playlist.independent_segments = true,
+            "#EXT-X-ENDLIST" => playlist.end_list = true,
+            "#EXTINF" => {
+                let (duration, title) = match value.split_once(',') {
+                    Some((duration, title)) => (duration, Some(title)),
+                    None => (value, None),
+                };
+                let duration = duration.trim().parse::<f64>()
+                    .ok()
+                    .filter(|d| d.is_finite() && *d >= 0.0)
+                    .ok_or_else(|| err(format!("invalid #EXTINF duration {:?}", duration)))?;
+                pending = Some((number, Segment {
+                    duration,
+                    title: title.filter(|t| !t.is_empty()).map(str::to_string),
+                    ..Segment::default()
+                }));
+            }
+            "#EXT-X-BYTERANGE" => byte_range = Some(ByteRange::parse(value).map_err(err)?),
+            "#EXT-X-DISCONTINUITY" => discontinuity = true,
+            "#EXT-X-PROGRAM-DATE-TIME" => program_date_time = Some(value.to_string()),
+            "#EXT-X-KEY" => key = parse_key(value).map_err(err)?,
+            "#EXT-X-MAP" => {
+                let attrs = AttributeList::parse(value).map_err(err)?;
+                map = Some(Map {
+                    uri: attrs.required("URI").map_err(err)?.to_string(),
+                    byte_range: attrs.byte_range("BYTERANGE").map_err(err)?,
+                    key: key.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if pending.is_some() {
+        return Err(ParseError { line: 0, message: "#EXTINF at end of playlist without a segment URI".to_string() });
+    }
+    if !has_target_duration {
+        return Err(ParseError { line: 0, message: "missing #EXT-X-TARGETDURATION".to_string() });
+    }
+
+    Ok(playlist)
+}
+
+/// Non-empty lines with their 1-based numbers, trimmed of whitespace and line endings.
+fn lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input.trim_start_matches('\u{feff}')
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+/// Verifies the `#EXTM3U` header and returns the remaining lines.
+fn check_header(input: &str) -> Result<impl Iterator<Item = (usize, &str)>, ParseError> {
+    let mut lines = lines(input);
+    match lines.next() {
+        Some((_, "#EXTM3U")) => Ok(lines),
+        Some((line, _)) => Err(ParseError { line, message: "playlist does not start with #EXTM3U".to_string() }),
+        None => Err(ParseError { line: 0, message: "empty playlist".to_string() }),
+    }
+}
+
+fn split_tag(line: &str) -> (&str, &str) {
+    line.split_once(':').unwrap_or((line, ""))
+}
+
+fn parse_number(value: &str) -> Result<u64, String> {
+    value.trim().parse().map_err(|_| format!("{:?} is not a decimal integer", value))
+}
+
+fn variant_stream(attrs: &AttributeList) -> Result<VariantStream, String> {
+    Ok(VariantStream {
+        uri: String::new(),
+        bandwidth: attrs.decimal("BANDWIDTH")?.ok_or("missing required attribute BANDWIDTH")?,
+        average_bandwidth: attrs.decimal("AVERAGE-BANDWIDTH")?,
+        codecs: attrs.string("CODECS"),
+        resolution: attrs.resolution("RESOLUTION")?,
+        frame_rate: attrs.float("FRAME-RATE")?,
+        hdcp_level: attrs.enumerated("HDCP-LEVEL")?.map(str::to_string),
+        audio: attrs.string("AUDIO"),
+        video: attrs.string("VIDEO"),
+        subtitles: attrs.string("SUBTITLES"),
+        closed_captions: attrs.string("CLOSED-CAPTIONS"),
+    })
+}
+
+fn rendition(attrs: &AttributeList) -> Result<Rendition, String> {
+    Ok(Rendition {
+        media_type: MediaType::parse(attrs.required("TYPE")?)?,
+        uri: attrs.string("URI"),
+        group_id: attrs.required("GROUP-ID")?.to_string(),
+        language: attrs.string("LANGUAGE"),
+        assoc_language: attrs.string("ASSOC-LANGUAGE"),
+        name: attrs.required("NAME")?.to_string(),
+        default: attrs.yes_no("DEFAULT")?,
+        autoselect: attrs.yes_no("AUTOSELECT")?,
+        forced: attrs.yes_no("FORCED")?,
+        instream_id: attrs.string("INSTREAM-ID"),
+        characteristics: attrs.string("CHARACTERISTICS"),
+        channels: attrs.string("CHANNELS"),
+    })
+}
+
+/// Parses an `#EXT-X-KEY` value. `METHOD=NONE` yields `None`.
+fn parse_key(value: &str) -> Result<Option<Key>, String> {
+    let attrs = AttributeList::parse(value)?;
+    let method = match attrs.enumerated("METHOD")?.ok_or("missing required attribute METHOD")? {
+        "NONE" => return Ok(None),
+        "AES-128" => KeyMethod::Aes128,
+        "SAMPLE-AES" => KeyMethod::SampleAes,
+        other => KeyMethod::Other(other.to_string()),
+    };
+
+    Ok(Some(Key {
+        method,
+        uri: attrs.required("URI")?.to_string(),
+        iv: attrs.get("IV").map(parse_iv).transpose()?,
+        key_format: attrs.string("KEYFORMAT"),
+        key_format_versions: attrs.string("KEYFORMATVERSIONS"),
+    }))
+}
+
+/// Parses a `0x`-prefixed hexadecimal IV into 16 big-endian bytes.
+fn parse_iv(value: &str) -> Result<[u8; 16], String> {
+    let invalid = || format!("invalid IV {:?}", value);
+    let digits = value.strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .ok_or_else(invalid)?;
+    if digits.is_empty() || digits.len() > 32 {
+        return Err(invalid());
+    }
+
+    // Shorter values are still 128-bit integers; pad them on the left.
+    let padded = format!("{:0>32}", digits);
+    let mut iv = [0u8; 16];
+    hex::decode_to_slice(&padded, &mut iv).map_err(|_| invalid())?;
+    Ok(iv)
+}
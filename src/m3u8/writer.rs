@@ -0,0 +1,155 @@
+use std::fmt;
+
+use super::attributes::AttributeWriter;
+use super::{Key, Map, MasterPlaylist, MediaPlaylist, Playlist, PlaylistType, Rendition, VariantStream};
+
+impl fmt::Display for Playlist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Playlist::Master(master) => master.fmt(f),
+            Playlist::Media(media) => media.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for MasterPlaylist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "#EXTM3U")?;
+        if let Some(version) = self.version {
+            writeln!(f, "#EXT-X-VERSION:{}", version)?;
+        }
+        if self.independent_segments {
+            writeln!(f, "#EXT-X-INDEPENDENT-SEGMENTS")?;
+        }
+        for rendition in &self.renditions {
+            writeln!(f, "#EXT-X-MEDIA:{}", rendition_attributes(rendition))?;
+        }
+        for variant in &self.variants {
+            writeln!(f, "#EXT-X-STREAM-INF:{}", variant_attributes(variant).finish())?;
+            writeln!(f, "{}", variant.uri)?;
+        }
+        for stream in &self.i_frame_streams {
+            let attrs = variant_attributes(stream).quoted("URI", &stream.uri).finish();
+            writeln!(f, "#EXT-X-I-FRAME-STREAM-INF:{}", attrs)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MediaPlaylist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "#EXTM3U")?;
+        if let Some(version) = self.version {
+            writeln!(f, "#EXT-X-VERSION:{}", version)?;
+        }
+        writeln!(f, "#EXT-X-TARGETDURATION:{}", self.target_duration)?;
+        if self.media_sequence != 0 {
+            writeln!(f, "#EXT-X-MEDIA-SEQUENCE:{}", self.media_sequence)?;
+        }
+        if self.discontinuity_sequence != 0 {
+            writeln!(f, "#EXT-X-DISCONTINUITY-SEQUENCE:{}", self.discontinuity_sequence)?;
+        }
+        match self.playlist_type {
+            Some(PlaylistType::Event) => writeln!(f, "#EXT-X-PLAYLIST-TYPE:EVENT")?,
+            Some(PlaylistType::Vod) => writeln!(f, "#EXT-X-PLAYLIST-TYPE:VOD")?,
+            None => {}
+        }
+        if self.i_frames_only {
+            writeln!(f, "#EXT-X-I-FRAMES-ONLY")?;
+        }
+        if self.independent_segments {
+            writeln!(f, "#EXT-X-INDEPENDENT-SEGMENTS")?;
+        }
+
+        // Keys and maps are stored per segment, so only emit them where they change.
+        // A map is written while its own key is in effect, then the segment's key follows.
+        let mut key: Option<&Key> = None;
+        let mut map: Option<&Map> = None;
+        for segment in &self.segments {
+            if segment.map.as_ref() != map {
+                if let Some(new_map) = &segment.map {
+                    write_key_change(f, &mut key, new_map.key.as_ref())?;
+                    let attrs = AttributeWriter::default()
+                        .quoted("URI", &new_map.uri)
+                        .opt_quoted("BYTERANGE", new_map.byte_range.map(|r| r.to_string()).as_deref())
+                        .finish();
+                    writeln!(f, "#EXT-X-MAP:{}", attrs)?;
+                }
+                map = segment.map.as_ref();
+            }
+            write_key_change(f, &mut key, segment.key.as_ref())?;
+            if segment.discontinuity {
+                writeln!(f, "#EXT-X-DISCONTINUITY")?;
+            }
+            if let Some(date_time) = &segment.program_date_time {
+                writeln!(f, "#EXT-X-PROGRAM-DATE-TIME:{}", date_time)?;
+            }
+            writeln!(f, "#EXTINF:{},{}", segment.duration, segment.title.as_deref().unwrap_or(""))?;
+            if let Some(byte_range) = segment.byte_range {
+                writeln!(f, "#EXT-X-BYTERANGE:{}", byte_range)?;
+            }
+            writeln!(f, "{}", segment.uri)?;
+        }
+
+        if self.end_list {
+            writeln!(f, "#EXT-X-ENDLIST")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_key_change<'a>(f: &mut fmt::Formatter<'_>, current: &mut Option<&'a Key>, new: Option<&'a Key>) -> fmt::Result {
+    if *current == new {
+        return Ok(());
+    }
+    *current = new;
+    match new {
+        Some(key) => writeln!(f, "#EXT-X-KEY:{}", key_attributes(key)),
+        None => writeln!(f, "#EXT-X-KEY:METHOD=NONE"),
+    }
+}
+
+fn variant_attributes(variant: &VariantStream) -> AttributeWriter {
+    let mut attrs = AttributeWriter::default();
+    attrs.unquoted("BANDWIDTH", variant.bandwidth)
+        .opt_unquoted("AVERAGE-BANDWIDTH", variant.average_bandwidth)
+        .opt_quoted("CODECS", variant.codecs.as_deref())
+        .opt_unquoted("RESOLUTION", variant.resolution)
+        .opt_unquoted("FRAME-RATE", variant.frame_rate)
+        .opt_unquoted("HDCP-LEVEL", variant.hdcp_level.as_deref())
+        .opt_quoted("AUDIO", variant.audio.as_deref())
+        .opt_quoted("VIDEO", variant.video.as_deref())
+        .opt_quoted("SUBTITLES", variant.subtitles.as_deref());
+    match variant.closed_captions.as_deref() {
+        Some("NONE") => { attrs.unquoted("CLOSED-CAPTIONS", "NONE"); }
+        other => { attrs.opt_quoted("CLOSED-CAPTIONS", other); }
+    }
+    attrs
+}
+
+fn rendition_attributes(rendition: &Rendition) -> String {
+    AttributeWriter::default()
+        .unquoted("TYPE", rendition.media_type)
+        .opt_quoted("URI", rendition.uri.as_deref())
+        .quoted("GROUP-ID", &rendition.group_id)
+        .opt_quoted("LANGUAGE", rendition.language.as_deref())
+        .opt_quoted("ASSOC-LANGUAGE", rendition.assoc_language.as_deref())
+        .quoted("NAME", &rendition.name)
+        .flag("DEFAULT", rendition.default)
+        .flag("AUTOSELECT", rendition.autoselect)
+        .flag("FORCED", rendition.forced)
+        .opt_quoted("INSTREAM-ID", rendition.instream_id.as_deref())
+        .opt_quoted("CHARACTERISTICS", rendition.characteristics.as_deref())
+        .opt_quoted("CHANNELS", rendition.channels.as_deref())
+        .finish()
+}
+
+fn key_attributes(key: &Key) -> String {
+    AttributeWriter::default()
+        .unquoted("METHOD", &key.method)
+        .quoted("URI", &key.uri)
+        .opt_unquoted("IV", key.iv.map(|iv| format!("0x{}", hex::encode_upper(iv))))
+        .opt_quoted("KEYFORMAT", key.key_format.as_deref())
+        .opt_quoted("KEYFORMATVERSIONS", key.key_format_versions.as_deref())
+        .finish()
+}
@@ -1,459 +1,565 @@
 mod gui;
 
-use anyhow::{anyhow, Result};
-use reqwest::{Client, header};
-use serde_json::Value;
-use std::{io::Write, time::Duration, collections::HashMap};
-use tokio::{fs::File, io::AsyncWriteExt};
-use url::Url;
-use base64::Engine;
-use aes::Aes128;
-use cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
-use cbc::Decryptor;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use hotmart_ripper::config::{DEFAULT_JOBS, DEFAULT_RETRIES};
+use hotmart_ripper::{
+    batch, verify, DownloadControl, DownloaderBuilder, EmbedUrl, Error, HotmartDownloader, OutputFormat, OutputTemplate, ProgressEvent,
+    Result, SubtitleFormat, SubtitleSelection, Variant, VariantSelector, Verified, VideoInfo,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Exit code of a batch in which at least one video failed.
+const BATCH_FAILED: u8 = 1;
+
+/// Exit code after Ctrl+C, as shells report for SIGINT.
+const CANCELLED: u8 = 130;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-
-    match args.len() {
-        1 => {
-            // No arguments, run GUI mode
-            if let Err(e) = gui::run_gui() {
-                eprintln!("Failed to run GUI: {}", e);
-            }
-            Ok(())
-        }
-        2 => {
-            // One argument (URL), run CLI mode
-            let embed_url = &args[1];
-            let downloader = HotmartDownloader::new()?;
-            downloader.download_video(embed_url).await
-        }
-        _ => {
-            println!("Usage:");
-            println!("  {} [url]   - Download video from URL (CLI mode)", args[0]);
-            println!("  {}         - Launch GUI", args[0]);
-            Ok(())
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("\nError: {}", e);
+            ExitCode::from(exit_code(&e))
         }
     }
 }
 
-struct HotmartDownloader {
-    client: Client,
-}
+/// Download Hotmart videos. Launches the GUI when run without arguments.
+///
+/// A bare URL is shorthand for `download <URL>`.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-impl HotmartDownloader {
+    /// Player embed URL of the video to download
+    url: Option<String>,
 
-    fn new() -> Result<Self> {
-        let mut headers = header::HeaderMap::new();
-        headers.insert("User-Agent", header::HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:131.0) Gecko/20100101 Firefox/131.0"));
-        headers.insert("Accept", header::HeaderValue::from_static("*/*"));
-        headers.insert("Accept-Language", header::HeaderValue::from_static("en-US,en;q=0.5"));
-        headers.insert("Origin", header::HeaderValue::from_static("https://player.hotmart.com"));
-        headers.insert("Referer", header::HeaderValue::from_static("https://player.hotmart.com/"));
-        headers.insert("Connection", header::HeaderValue::from_static("keep-alive"));
+    #[command(flatten)]
+    download: DownloadOptions,
 
-        let client = Client::builder()
-            .default_headers(headers)
-            .timeout(Duration::from_secs(30))
-            .build()?;
+    #[command(flatten)]
+    network: NetworkOptions,
+}
 
-        Ok(Self { client })
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Download one video
+    Download {
+        /// Player embed URL of the video
+        url: String,
+
+        #[command(flatten)]
+        download: DownloadOptions,
+    },
+
+    /// Show the streams a video offers without downloading it
+    Info {
+        /// Player embed URL of the video
+        url: String,
+
+        /// Variant to inspect and mark as selected, as for downloads
+        #[arg(short, long, default_value = "best")]
+        quality: VariantSelector,
+
+        /// Print the info as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Download every video listed in a file: one URL per line, optionally followed by the output path
+    Batch {
+        /// The batch file
+        file: PathBuf,
+
+        /// Directory the videos are saved in, unless their path is absolute
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        /// Number of videos downloaded at the same time
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+        parallel: u16,
+
+        #[command(flatten)]
+        download: DownloadOptions,
+    },
+
+    /// Check that downloaded MP4 or MPEG-TS files are complete
+    Verify {
+        /// Files to check
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Open the graphical interface
+    Gui,
+}
 
-    pub async fn download_video_with_progress_and_path<F, P>(
-        &self,
-        embed_url: &str,
-        save_path: P,
-        progress_callback: F
-    ) -> Result<()>
-    where
-        F: Fn(usize, usize) + Send + Sync + 'static,
-        P: AsRef<std::path::Path>,
-    {
-        let url = Url::parse(embed_url)?;
-        let video_id = url.path_segments()
-            .ok_or_else(|| anyhow!("Invalid URL path"))?
-            .last()
-            .ok_or_else(|| anyhow!("No video ID found"))?;
-
-        let token = url.query_pairs()
-            .find(|(key, _)| key == "token")
-            .map(|(_, value)| value.to_string())
-            .unwrap_or_default();
-
-        let signature = url.query_pairs()
-            .find(|(key, _)| key == "signature")
-            .map(|(_, value)| value.to_string())
-            .unwrap_or_default();
-
-        let master_playlist_url = match self.get_master_playlist_url(embed_url).await {
-            Ok(url) => url,
-            Err(e) => {
-                progress_callback(0, 100);
-                self.get_api_playlist_url(video_id, &token, &signature).await?
-            }
-        };
-
-        let best_quality_url = self.get_best_quality_stream(&master_playlist_url).await?;
-        let media_segments = self.get_media_segments(&best_quality_url).await?;
-        let total_segments = media_segments.len();
-
-        let mut output_file = File::create(save_path).await?;
-        let mut key_cache: HashMap<String, Vec<u8>> = HashMap::new();
-
-        for (i, (segment_url, encryption_info)) in media_segments.iter().enumerate() {
-            progress_callback(i + 1, total_segments);
-
-            let mut segment_data = self.client.get(segment_url)
-                .send()
-                .await?
-                .bytes()
-                .await?
-                .to_vec();
-
-            if let Some((key_url, iv)) = encryption_info {
-                let decryption_key = if let Some(cached_key) = key_cache.get(key_url) {
-                    cached_key.clone()
-                } else {
-                    let key_data = self.client.get(key_url)
-                        .send()
-                        .await?
-                        .bytes()
-                        .await?
-                        .to_vec();
-                    key_cache.insert(key_url.clone(), key_data.clone());
-                    key_data
-                };
-
-                segment_data = self.decrypt_segment(&segment_data, &decryption_key, &iv).await?;
-            }
+/// What to download and how to save it.
+#[derive(Args)]
+struct DownloadOptions {
+    /// Where to save the video, e.g. "{course}/{module:02}-{lesson:02} {title}.{ext}"; fields: id, title, course, module, lesson, producer, date, ext. A directory keeps the default "{id}.{ext}" name
+    #[arg(short, long, value_name = "TEMPLATE", value_parser = parse_output)]
+    output: Option<OutputTemplate>,
 
-            output_file.write_all(&segment_data).await?;
-        }
+    /// Number of segments to download in parallel
+    #[arg(short, long, default_value_t = DEFAULT_JOBS as u16, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
 
-        Ok(())
-    }
+    /// Keep the raw MPEG-TS stream instead of remuxing it into MP4
+    #[arg(long)]
+    keep_ts: bool,
 
-    pub async fn download_video_with_progress<F>(&self, embed_url: &str, progress_callback: F) -> Result<()>
-    where
-        F: Fn(usize, usize) + Send + Sync + 'static,
-    {
-        let url = Url::parse(embed_url)?;
-        let video_id = url.path_segments()
-            .ok_or_else(|| anyhow!("Invalid URL path"))?
-            .last()
-            .ok_or_else(|| anyhow!("No video ID found"))?;
-
-        // Get both token and signature from URL
-        let token = url.query_pairs()
-            .find(|(key, _)| key == "token")
-            .map(|(_, value)| value.to_string())
-            .unwrap_or_default();
-
-        let signature = url.query_pairs()
-            .find(|(key, _)| key == "signature")
-            .map(|(_, value)| value.to_string())
-            .unwrap_or_default();
-
-        // Fetch master playlist URL either directly or via API
-        let master_playlist_url = match self.get_master_playlist_url(embed_url).await {
-            Ok(url) => url,
-            Err(e) => {
-                progress_callback(0, 100); // Initial progress indication
-                self.get_api_playlist_url(video_id, &token, &signature).await?
-            }
-        };
-
-        // Get best quality stream URL
-        let best_quality_url = self.get_best_quality_stream(&master_playlist_url).await?;
-
-        // Fetch list of segments
-        let media_segments = self.get_media_segments(&best_quality_url).await?;
-        let total_segments = media_segments.len();
-
-        let output_path = format!("{}.mp4", video_id);
-        let mut output_file = File::create(&output_path).await?;
-
-        // Cache for decryption keys
-        let mut key_cache: HashMap<String, Vec<u8>> = HashMap::new();
-
-        for (i, (segment_url, encryption_info)) in media_segments.iter().enumerate() {
-            // Update progress
-            progress_callback(i + 1, total_segments);
-
-            let mut segment_data = self.client.get(segment_url)
-                .send()
-                .await?
-                .bytes()
-                .await?
-                .to_vec();
-
-            // If segment is encrypted, decrypt it
-            if let Some((key_url, iv)) = encryption_info {
-                let decryption_key = if let Some(cached_key) = key_cache.get(key_url) {
-                    cached_key.clone()
-                } else {
-                    let key_data = self.client.get(key_url)
-                        .send()
-                        .await?
-                        .bytes()
-                        .await?
-                        .to_vec();
-                    key_cache.insert(key_url.clone(), key_data.clone());
-                    key_data
-                };
-
-                segment_data = self.decrypt_segment(&segment_data, &decryption_key, &iv).await?;
-            }
+    /// Variant to download: best, worst, <=720, 720p, 1280x720, bw<=3M, codec=avc1 (comma-separated)
+    #[arg(short, long, default_value = "best")]
+    quality: VariantSelector,
 
-            output_file.write_all(&segment_data).await?;
-        }
+    /// Language of the audio track to download when it is a separate rendition, e.g. pt-BR
+    #[arg(long, value_name = "LANG")]
+    audio_lang: Option<String>,
+
+    /// Subtitles to download: all, or comma-separated languages such as pt-BR,en
+    #[arg(long, value_name = "LANGS", default_value = "none")]
+    subs: SubtitleSelection,
 
-        // Final progress update
-        progress_callback(total_segments, total_segments);
+    /// How to save subtitles: vtt or srt next to the video, or embed into the MP4
+    #[arg(long, value_name = "FORMAT", default_value = "vtt")]
+    sub_format: SubtitleFormat,
+}
 
-        Ok(())
+impl DownloadOptions {
+    fn format(&self) -> OutputFormat {
+        if self.keep_ts { OutputFormat::Ts } else { OutputFormat::Mp4 }
     }
+}
 
-    async fn download_video(&self, embed_url: &str) -> Result<()> {
-        let url = Url::parse(embed_url)?;
-        let video_id = url.path_segments()
-            .ok_or_else(|| anyhow!("Invalid URL path"))?
-            .last()
-            .ok_or_else(|| anyhow!("No video ID found"))?;
-
-        println!("Extracting video info for ID: {}", video_id);
-
-        // Get both token and signature from URL
-        let token = url.query_pairs()
-            .find(|(key, _)| key == "token")
-            .map(|(_, value)| value.to_string())
-            .unwrap_or_default();
-
-        let signature = url.query_pairs()
-            .find(|(key, _)| key == "signature")
-            .map(|(_, value)| value.to_string())
-            .unwrap_or_default();
-
-        // Fetch master playlist URL either directly or via API
-        let master_playlist_url = match self.get_master_playlist_url(embed_url).await {
-            Ok(url) => url,
-            Err(e) => {
-                println!("Page parsing failed: {}. Trying API...", e);
-                self.get_api_playlist_url(video_id, &token, &signature).await?
-            }
-        };
+/// HTTP and logging settings shared by every command.
+#[derive(Args)]
+struct NetworkOptions {
+    /// Number of times a failed playlist, key or segment request is retried
+    #[arg(long, global = true, default_value_t = DEFAULT_RETRIES)]
+    retries: u32,
 
-        println!("Found master playlist: {}", master_playlist_url);
+    /// Seconds after which a request is abandoned
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    timeout: u64,
 
-        // Get best quality stream URL
-        let best_quality_url = self.get_best_quality_stream(&master_playlist_url).await?;
-        println!("Selected best quality stream: {}", best_quality_url);
+    /// Proxy for every request, e.g. http://127.0.0.1:8080
+    #[arg(long, global = true, value_name = "URL")]
+    proxy: Option<String>,
 
-        // Fetch list of segments
-        let media_segments = self.get_media_segments(&best_quality_url).await?;
-        println!("Found {} segments to download", media_segments.len());
+    /// Extra request header, e.g. "Cookie: ..."; may be repeated
+    #[arg(short = 'H', long = "header", global = true, value_name = "NAME: VALUE", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
 
-        let output_path = format!("{}.mp4", video_id);
-        let mut output_file = File::create(&output_path).await?;
+    /// How much to print: error, warn, info or debug
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Info)]
+    log_level: LogLevel,
+}
 
-        // Cache for decryption keys
-        let mut key_cache: std::collections::HashMap<String, Vec<u8>> = std::collections::HashMap::new();
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum LogLevel {
+    /// Only the final error
+    Error,
+    /// Also retries and fallbacks
+    Warn,
+    /// Also progress
+    Info,
+    /// Also the URLs of separate audio and subtitle tracks, and every segment on its own line
+    Debug,
+}
 
-        for (i, (segment_url, encryption_info)) in media_segments.iter().enumerate() {
-            print!("\rDownloading segment {}/{}", i + 1, media_segments.len());
-            let _ = std::io::stdout().flush();
+/// An output template, or a directory to save `{id}.{ext}` in.
+fn parse_output(value: &str) -> std::result::Result<OutputTemplate, String> {
+    if value.ends_with(['/', '\\']) || Path::new(value).is_dir() {
+        Ok(OutputTemplate::default().under(value))
+    } else {
+        value.parse()
+    }
+}
+
+fn parse_header(value: &str) -> std::result::Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.trim().to_string())),
+        _ => Err(format!("expected \"Name: value\", got {:?}", value)),
+    }
+}
 
-            let mut segment_data = self.client.get(segment_url)
-                .send()
-                .await?
-                .bytes()
-                .await?
-                .to_vec();
-
-            // If segment is encrypted, decrypt it
-            if let Some((key_url, iv)) = encryption_info {
-                let decryption_key = if let Some(cached_key) = key_cache.get(key_url) {
-                    cached_key.clone()
-                } else {
-                    let key_data = self.client.get(key_url)
-                        .send()
-                        .await?
-                        .bytes()
-                        .await?
-                        .to_vec();
-                    key_cache.insert(key_url.clone(), key_data.clone());
-                    key_data
-                };
-
-                segment_data = self.decrypt_segment(&segment_data, &decryption_key, &iv).await?;
+async fn run() -> Result<ExitCode> {
+    let cli = Cli::parse();
+    let network = &cli.network;
+
+    let command = match cli.command {
+        Some(command) => command,
+        // A bare URL, as accepted before there were subcommands
+        None => match cli.url {
+            Some(url) => Command::Download { url, download: cli.download },
+            None => Command::Gui,
+        },
+    };
+
+    match command {
+        Command::Download { url, download } => {
+            let embed = EmbedUrl::parse(&url)?;
+            let level = network.log_level;
+            if level >= LogLevel::Info {
+                println!("Extracting video info for ID: {}", embed.video_id);
             }
 
-            output_file.write_all(&segment_data).await?;
+            let output = download.output.clone().unwrap_or_default();
+            let downloader = build_downloader(network, &download)?;
+            cancel_on_ctrl_c(downloader.control().clone());
+            downloader.download_to(&url, &output, move |event| print_progress(level, event)).await?;
+            Ok(ExitCode::SUCCESS)
         }
-
-        println!("\nDownload complete: {}", output_path);
-        Ok(())
-    }
-
-    async fn fetch_and_decrypt_segment(&self, segment_url: &str) -> Result<Vec<u8>> {
-        let response = self.client.get(segment_url).send().await?.text().await?;
-        let decoded_data = base64::engine::general_purpose::STANDARD
-            .decode(response.trim())
-            .map_err(|e| anyhow!("Failed to decode segment: {}", e))?;
-
-        // Check for encryption
-        if let Some((key_url, iv)) = self.get_encryption_details(segment_url).await? {
-            let key = self.fetch_decryption_key(&key_url).await?;
-            let decrypted_data = self.decrypt_segment(&decoded_data, &key, &iv).await?;  // Add await here
-            Ok(decrypted_data)
-        } else {
-            // If not encrypted, return the decoded data directly
-            Ok(decoded_data)
+        Command::Info { url, quality, json } => {
+            let downloader = builder(network).quality(quality).build()?;
+            let level = network.log_level;
+            let info = downloader.info(&url, &move |event| report_problem(level, event)).await?;
+            if json {
+                println!("{:#}", info.to_json());
+            } else {
+                print_info(&info);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Batch { file, output_dir, parallel, download } => {
+            run_batch(network, &download, &file, output_dir.as_deref(), parallel).await
+        }
+        Command::Verify { files } => Ok(run_verify(&files)),
+        Command::Gui => {
+            match gui::run_gui() {
+                Ok(()) => Ok(ExitCode::SUCCESS),
+                Err(e) => {
+                    eprintln!("Failed to run GUI: {}", e);
+                    Ok(ExitCode::FAILURE)
+                }
+            }
         }
     }
+}
 
-    async fn get_encryption_details(&self, _segment_url: &str) -> Result<Option<(String, Vec<u8>)>> {
-        // Placeholder function to fetch key_url and IV from `.m3u8` playlist or segment headers
-        Ok(None)
+/// Downloads every video of a batch file and prints a summary. Fails only
+/// if the file cannot be read; failed videos are reported in the exit code.
+async fn run_batch(
+    network: &NetworkOptions,
+    download: &DownloadOptions,
+    batch_file: &Path,
+    output_dir: Option<&Path>,
+    parallel: u16,
+) -> Result<ExitCode> {
+    let entries = batch::parse(&std::fs::read_to_string(batch_file)?)?;
+    let output_dir = output_dir.unwrap_or(Path::new(""));
+    let videos: Vec<(String, OutputTemplate)> = entries.iter()
+        .map(|entry| {
+            let output = match entry.output_path(download.format()) {
+                Some(output) => OutputTemplate::literal(output),
+                None => download.output.clone().unwrap_or_default(),
+            };
+            (entry.url.clone(), output.under(output_dir))
+        })
+        .collect();
+
+    let level = network.log_level;
+    if level >= LogLevel::Info {
+        println!("Downloading {} videos, {} at a time", videos.len(), parallel);
     }
 
-    async fn fetch_decryption_key(&self, key_url: &str) -> Result<Vec<u8>> {
-        let response = self.client.get(key_url).send().await?.text().await?;
-        let key = base64::engine::general_purpose::STANDARD
-            .decode(response.trim())
-            .map_err(|e| anyhow!("Failed to decode key: {}", e))?;
-        Ok(key)
+    let total = videos.len();
+    let downloader = build_downloader(network, download)?;
+    cancel_on_ctrl_c(downloader.control().clone());
+    let results = downloader
+        .download_batch(&videos, parallel.into(), move |index, event| print_batch_progress(level, index, total, event))
+        .await;
+
+    println!("\n  #  LINE  STATUS  OUTPUT");
+    for (i, ((entry, (_, output)), result)) in entries.iter().zip(&videos).zip(&results).enumerate() {
+        match result {
+            Ok(path) => println!("{:>3}  {:>4}  ok      {}", i + 1, entry.line, path.display()),
+            Err(e) => println!("{:>3}  {:>4}  FAILED  {}: {}", i + 1, entry.line, output, e),
+        }
     }
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    println!("\n{} of {} videos downloaded", total - failed, total);
+
+    Ok(match failed {
+        _ if downloader.control().is_cancelled() => ExitCode::from(CANCELLED),
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(BATCH_FAILED),
+    })
+}
 
-    async fn decrypt_segment(&self, data: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
-        let decryptor = Decryptor::<Aes128>::new_from_slices(key, iv)
-            .map_err(|e| anyhow!("Failed to create cipher: {}", e))?;
+/// Cancels the downloads on Ctrl+C. They stop after the segments being
+/// written, with their checkpoints saved; a second Ctrl+C exits at once.
+fn cancel_on_ctrl_c(control: DownloadControl) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("\nCancelling; run the same command again to resume. Press Ctrl+C again to quit now.");
+        control.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(CANCELLED.into());
+        }
+    });
+}
 
-        let mut buf = data.to_vec();
-        let decrypted_data = decryptor.decrypt_padded_vec_mut::<Pkcs7>(&mut buf)
-            .map_err(|e| anyhow!("Failed to decrypt: {}", e))?;
+/// Checks every file and prints one line each. The exit code is that of an
+/// output check failure if any file fails.
+fn run_verify(files: &[PathBuf]) -> ExitCode {
+    let mut failed = None;
+    for file in files {
+        match verify(file) {
+            Ok(Verified::Mp4 { tracks, fragments }) => {
+                let tracks: Vec<String> = tracks.iter()
+                    .map(|track| format!("{} {} samples, {}", track.handler, track.samples, format_duration(track.duration)))
+                    .collect();
+                let fragments = if fragments > 0 { format!(", {} fragments", fragments) } else { String::new() };
+                println!("ok      {}: MP4, {}{}", file.display(), tracks.join("; "), fragments);
+            }
+            Ok(Verified::Ts { packets }) => println!("ok      {}: MPEG-TS, {} packets", file.display(), packets),
+            Err(e) => {
+                println!("FAILED  {}: {}", file.display(), e);
+                failed = Some(exit_code(&e));
+            }
+        }
+    }
+    failed.map_or(ExitCode::SUCCESS, ExitCode::from)
+}
 
-        Ok(decrypted_data.to_vec())
+/// A downloader builder with the HTTP settings shared by every command.
+fn builder(network: &NetworkOptions) -> DownloaderBuilder {
+    let mut builder = HotmartDownloader::builder()
+        .retries(network.retries)
+        .timeout(Duration::from_secs(network.timeout));
+    if let Some(proxy) = &network.proxy {
+        builder = builder.proxy(proxy);
+    }
+    for (name, value) in &network.headers {
+        builder = builder.header(name, value);
     }
+    builder
+}
 
-    async fn get_master_playlist_url(&self, embed_url: &str) -> Result<String> {
-        let response = self.client.get(embed_url).send().await?;
-        let page_html = response.text().await?;
+fn build_downloader(network: &NetworkOptions, download: &DownloadOptions) -> Result<HotmartDownloader> {
+    let mut builder = builder(network)
+        .jobs(download.jobs.into())
+        .output_format(download.format())
+        .quality(download.quality.clone())
+        .subtitles(download.subs.clone())
+        .subtitle_format(download.sub_format);
+    if let Some(language) = &download.audio_lang {
+        builder = builder.audio_language(language);
+    }
+    builder.build()
+}
 
-        if let Some(start) = page_html.find(r#"<script id="__NEXT_DATA__" type="application/json">"#) {
-            let json_start = start + r#"<script id="__NEXT_DATA__" type="application/json">"#.len();
-            if let Some(end) = page_html[json_start..].find("</script>") {
-                let json_str = &page_html[json_start..json_start + end];
-                let data: Value = serde_json::from_str(json_str)?;
+/// Process exit code for each failure kind, so scripts can tell them apart.
+fn exit_code(error: &Error) -> u8 {
+    // Clap exits with 2 on usage errors, so the codes start at 3.
+    match error {
+        Error::UrlParse(_) => 3,
+        Error::LinkExpired { .. } => 4,
+        Error::KeyFetch { .. } if error.is_link_expired() => 4,
+        Error::PlaylistNotFound { .. } => 5,
+        Error::NoVariants | Error::NoMatchingVariant { .. } => 6,
+        Error::InvalidPlaylist { .. } => 7,
+        Error::HttpStatus { .. } => 8,
+        Error::Timeout { .. } => 9,
+        Error::Network { .. } => 10,
+        Error::KeyFetch { .. } => 11,
+        Error::Decrypt { .. } => 12,
+        Error::Io(_) => 13,
+        Error::InvalidHeader(_) | Error::Client(_) => 14,
+        Error::OutputCheck { .. } => 15,
+        Error::Remux { .. } => 16,
+        Error::Subtitles { .. } => 17,
+        Error::UnsupportedAudio { .. } => 18,
+        Error::OutputConflict { .. } => 19,
+        Error::Cancelled => CANCELLED,
+    }
+}
 
-                if let Some(media_assets) = data.pointer("/props/pageProps/applicationData/mediaAssets") {
-                    if let Some(url) = media_assets[0].get("url").and_then(|v| v.as_str()) {
-                        return Ok(url.to_string());
-                    }
-                }
-            }
+/// Prints the info as tables, marking the variant `--quality` picks.
+fn print_info(info: &VideoInfo) {
+    let metadata = &info.metadata;
+    println!("Video:           {}", info.video_id);
+    let fields = [
+        ("Title:          ", metadata.title.clone()),
+        ("Course:         ", metadata.course.clone()),
+        ("Module:         ", metadata.module.map(|n| n.to_string())),
+        ("Lesson:         ", metadata.lesson.map(|n| n.to_string())),
+        ("Producer:       ", metadata.producer.clone()),
+        ("Locale:         ", metadata.locale.clone()),
+        ("Uploaded:       ", metadata.upload_date.clone()),
+        ("Thumbnail:      ", metadata.thumbnail_url.clone()),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            println!("{} {}", label, value);
         }
+    }
+    println!("Master playlist: {}", info.master_url);
+    println!("Duration:        {} ({} segments)", format_duration(info.duration), info.segment_count);
+    match &info.encryption {
+        Some(method) => println!("Encryption:      {}", method),
+        None => println!("Encryption:      none"),
+    }
 
-        Err(anyhow!("Failed to extract master playlist URL"))
+    println!("\nMedia assets:");
+    println!("   FORMAT    LANGUAGE  TYPE      URL");
+    for (i, asset) in metadata.assets.iter().enumerate() {
+        let marker = if info.asset == i { '*' } else { ' ' };
+        let format = if asset.format().is_supported() { asset.format().to_string() } else { format!("{} (unsupported)", asset.format()) };
+        println!(
+            " {} {:<9} {:<9} {:<9} {}",
+            marker,
+            format,
+            asset.language.as_deref().unwrap_or("-"),
+            asset.kind.as_deref().unwrap_or("-"),
+            asset.url
+        );
     }
 
-    async fn get_best_quality_stream(&self, master_url: &str) -> Result<String> {
-        let master_playlist = self.client.get(master_url).send().await?.text().await?;
-        let mut best_bandwidth = 0;
-        let mut best_url: Option<String> = None;
-
-        for line in master_playlist.lines() {
-            if line.starts_with("#EXT-X-STREAM-INF") {
-                if let Some(bandwidth) = line.split(',')
-                    .find(|s| s.contains("BANDWIDTH="))
-                    .and_then(|s| s.split('=').nth(1))
-                    .and_then(|s| s.parse::<u32>().ok())
-                {
-                    if bandwidth > best_bandwidth {
-                        best_bandwidth = bandwidth;
-                        best_url = None; // Clear any previous best_url for the next URL line
-                    }
-                }
-            } else if best_url.is_none() && !line.starts_with('#') {
-                // Assign the line (URL) to best_url
-                best_url = Some(line.to_string());
-            }
-        }
+    println!("\nVariants:");
+    println!("   RESOLUTION   BANDWIDTH    FPS   EST. SIZE  CODECS");
+    for (i, variant) in info.variants.iter().enumerate() {
+        let marker = if info.selected == Some(i) { '*' } else { ' ' };
+        let resolution = variant.resolution.map_or("-".to_string(), |r| r.to_string());
+        let frame_rate = variant.frame_rate.map_or("-".to_string(), |f| format!("{:.2}", f));
+        println!(
+            " {} {:<11} {:>10} {:>6} {:>11}  {}",
+            marker,
+            resolution,
+            format_bandwidth(variant.bandwidth),
+            frame_rate,
+            format_size(info.estimated_size(variant)),
+            variant.codecs.as_deref().unwrap_or("-")
+        );
+    }
 
-        let stream_path = best_url.ok_or_else(|| anyhow!("No streams found"))?;
-        let base_url = Url::parse(master_url)?;
-        let stream_url = base_url.join(&stream_path)?;
+    if !info.renditions.is_empty() {
+        println!("\nRenditions:");
+        println!("   TYPE        GROUP        LANGUAGE  NAME");
+        for rendition in &info.renditions {
+            println!(
+                " {} {:<11} {:<12} {:<9} {}",
+                if rendition.default { '*' } else { ' ' },
+                rendition.media_type.to_string(),
+                rendition.group_id,
+                rendition.language.as_deref().unwrap_or("-"),
+                rendition.name
+            );
+        }
+    }
+}
 
-        Ok(stream_url.to_string())
+fn describe_variant(variant: &Variant) -> String {
+    match variant.resolution {
+        Some(resolution) => format!("{} ({})", resolution, format_bandwidth(variant.bandwidth)),
+        None => format_bandwidth(variant.bandwidth),
     }
+}
 
+fn format_bandwidth(bits_per_second: u64) -> String {
+    format!("{:.2} Mbps", bits_per_second as f64 / 1_000_000.0)
+}
 
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=999_999 => format!("{:.0} kB", bytes as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.1} MB", bytes as f64 / 1e6),
+        _ => format!("{:.2} GB", bytes as f64 / 1e9),
+    }
+}
 
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
 
-    async fn get_media_segments(&self, playlist_url: &str) -> Result<Vec<(String, Option<(String, Vec<u8>)>)>> {
-        let playlist = self.client.get(playlist_url).send().await?.text().await?;
-        let base_url = Url::parse(playlist_url)?;
-        let mut segments = Vec::new();
-        let mut current_key_url: Option<String> = None;
-        let mut current_iv: Option<Vec<u8>> = None;
+/// Progress of one video out of a batch. Several videos download at once,
+/// so only milestones are printed, each on a line of its own.
+fn print_batch_progress(level: LogLevel, index: usize, total: usize, event: ProgressEvent) {
+    if severity(&event) > level {
+        return;
+    }
+    let prefix = format!("[{}/{}]", index + 1, total);
+    match event {
+        ProgressEvent::VariantSelected { variant } => println!("{} Selected {} stream", prefix, describe_variant(&variant)),
+        ProgressEvent::SegmentsFound { total } => println!("{} Downloading {} segments", prefix, total),
+        ProgressEvent::Resumed { completed, total } => println!("{} Resuming after segment {}/{}", prefix, completed, total),
+        ProgressEvent::Segment { current, total } if current * 4 / total > (current - 1) * 4 / total => {
+            println!("{} {}%", prefix, current * 100 / total);
+        }
+        ProgressEvent::Retry { url, attempt, max_retries, reason, .. } => {
+            eprintln!("{} {}. Retrying {} ({}/{})", prefix, reason, url, attempt, max_retries);
+        }
+        ProgressEvent::AssetFallback { url, reason } => eprintln!("{} Skipping media asset {}: {}", prefix, url, reason),
+        ProgressEvent::Finished { path } => println!("{} Done: {}", prefix, path.display()),
+        _ => {}
+    }
+}
 
-        for line in playlist.lines() {
-            if line.starts_with("#EXT-X-KEY:") {
-                // Parse encryption info
-                if line.contains("METHOD=AES-128") {
-                    let uri = line.split("URI=\"").nth(1)
-                        .and_then(|s| s.split("\"").next())
-                        .ok_or_else(|| anyhow!("Invalid key URI"))?;
-                    let iv = line.split("IV=0x").nth(1)
-                        .and_then(|s| s.split(",").next())
-                        .map(|hex| hex::decode(hex).unwrap_or_default());
+/// The lowest `--log-level` at which `event` is printed.
+fn severity(event: &ProgressEvent) -> LogLevel {
+    match event {
+        ProgressEvent::PageFallback { .. }
+        | ProgressEvent::AssetFallback { .. }
+        | ProgressEvent::Retry { .. }
+        | ProgressEvent::ResumeDiscarded { .. } => LogLevel::Warn,
+        _ => LogLevel::Info,
+    }
+}
 
-                    current_key_url = Some(base_url.join(uri)?.to_string());
-                    current_iv = iv;
-                }
-            } else if !line.starts_with("#") && !line.is_empty() {
-                let segment_url = base_url.join(line)?;
-                segments.push((
-                    segment_url.to_string(),
-                    current_key_url.as_ref().map(|key_url| (
-                        key_url.clone(),
-                        current_iv.clone().unwrap_or_else(|| vec![0; 16])
-                    ))
-                ));
-            }
+/// Progress callback for commands whose stdout is their result: only
+/// problems are reported, on stderr.
+fn report_problem(level: LogLevel, event: ProgressEvent) {
+    if severity(&event) > level {
+        return;
+    }
+    match event {
+        ProgressEvent::PageFallback { reason } => eprintln!("Page parsing failed: {}. Trying API...", reason),
+        ProgressEvent::AssetFallback { url, reason } => eprintln!("Skipping media asset {}: {}", url, reason),
+        ProgressEvent::Retry { url, attempt, max_retries, delay, reason } => {
+            eprintln!("{}. Retrying {} in {:.1}s ({}/{})", reason, url, delay.as_secs_f64(), attempt, max_retries);
         }
-
-        Ok(segments)
+        _ => {}
     }
+}
 
-    async fn get_api_playlist_url(&self, video_id: &str, token: &str, signature: &str) -> Result<String> {
-        let api_url = "https://contentplayer.hotmart.com/video/content";
-        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_millis();
-
-        let response = self.client.post(api_url)
-            .header("Content-Type", "application/json")
-            .header("Origin", "https://player.hotmart.com")
-            .header("Referer", "https://player.hotmart.com/")
-            .header("Accept", "application/json, text/plain, */*")
-            .header("x-hotmart-app", "web-player")
-            .header("x-hotmart-key", token)
-            .json(&serde_json::json!({
-                "videoId": video_id,
-                "token": token,
-                "timestamp": timestamp,
-                "signature": signature,
-                "captcha": serde_json::Value::Null,
-                "locale": "en"
-            }))
-            .send()
-            .await?;
-
-        let body = response.text().await?;
-        let config: Value = serde_json::from_str(&body)?;
-
-        let master_url = config.pointer("/streaming/hls/url").or_else(|| config.pointer("/response/streaming/hls/url")).or_else(|| config.pointer("/data/streaming/hls/url")).and_then(|v| v.as_str()).ok_or_else(|| anyhow!("Could not find HLS URL in API response"))?;
-        Ok(master_url.to_string())
+fn print_progress(level: LogLevel, event: ProgressEvent) {
+    if severity(&event) > level {
+        return;
+    }
+    match event {
+        ProgressEvent::PageFallback { reason } => println!("Page parsing failed: {}. Trying API...", reason),
+        ProgressEvent::AssetFallback { url, reason } => eprintln!("Skipping media asset {}: {}", url, reason),
+        ProgressEvent::MasterPlaylist { url } => println!("Found master playlist: {}", url),
+        ProgressEvent::VariantSelected { variant } => println!("Selected {} stream: {}", describe_variant(&variant), variant.url),
+        ProgressEvent::RenditionSelected { rendition } => {
+            println!("Selected {} track: {} ({})", rendition.media_type.to_string().to_lowercase(), rendition.name, rendition.language.as_deref().unwrap_or("unknown language"));
+            if let (LogLevel::Debug, Some(url)) = (level, &rendition.url) {
+                println!("  {}", url);
+            }
+        }
+        ProgressEvent::SegmentsFound { total } => println!("Found {} segments to download", total),
+        ProgressEvent::Retry { url, attempt, max_retries, delay, reason } => {
+            eprintln!("\n{}. Retrying {} in {:.1}s ({}/{})", reason, url, delay.as_secs_f64(), attempt, max_retries);
+        }
+        ProgressEvent::Resumed { completed, total } => println!("Resuming after segment {}/{}", completed, total),
+        ProgressEvent::ResumeDiscarded { reason } => println!("Ignoring checkpoint because {}. Starting over", reason),
+        ProgressEvent::Segment { current, total } if level == LogLevel::Debug => println!("Downloaded segment {}/{}", current, total),
+        ProgressEvent::Segment { current, total } => {
+            print!("\rDownloading segment {}/{}", current, total);
+            let _ = std::io::stdout().flush();
+        }
+        ProgressEvent::Remuxing => println!("\nRemuxing into MP4..."),
+        ProgressEvent::Finished { path } => println!("\nDownload complete: {}", path.display()),
     }
 }
@@ -0,0 +1,251 @@
+//! Video metadata found while resolving the master playlist.
+//!
+//! The embed page's `__NEXT_DATA__` JSON and the content player API describe
+//! the video with different field names. Each field is looked up under the
+//! names it appears as in either; the first one present wins.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+/// What the embed page or the content API says about a video.
+///
+/// Returned by [`HotmartDownloader::resolve`](crate::HotmartDownloader::resolve);
+/// fields the source does not provide are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoMetadata {
+    pub video_id: String,
+    pub title: Option<String>,
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    pub thumbnail_url: Option<String>,
+    /// Name of the producer who published the course.
+    pub producer: Option<String>,
+    pub course: Option<String>,
+    /// 1-based position of the module within the course.
+    pub module: Option<u32>,
+    /// 1-based position of the lesson within its module.
+    pub lesson: Option<u32>,
+    /// Locale of the page, e.g. `pt-BR`.
+    pub locale: Option<String>,
+    /// Upload date as given by the source, e.g. `2024-03-18T14:02:11Z`, or a
+    /// `YYYY-MM-DD` date when the source gives a timestamp.
+    pub upload_date: Option<String>,
+    /// Media assets in the order the source lists them. At least one of them
+    /// is [supported](AssetFormat::is_supported) when returned by the resolve step.
+    pub assets: Vec<MediaAsset>,
+}
+
+/// One entry of the page's `mediaAssets`, or the stream the API points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaAsset {
+    pub url: String,
+    /// Asset type as given by the source, e.g. `VIDEO`.
+    pub kind: Option<String>,
+    /// MIME type, e.g. `application/x-mpegURL`.
+    pub mime_type: Option<String>,
+    /// Language of this version of the video, when the page offers several.
+    pub language: Option<String>,
+}
+
+/// Delivery format of a [`MediaAsset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssetFormat {
+    Hls,
+    /// Neither the MIME type nor the URL tell. Tried as HLS after the known HLS assets.
+    Unknown,
+    Dash,
+    /// A single progressive file, e.g. MP4.
+    File,
+}
+
+impl AssetFormat {
+    /// Whether the downloader can fetch assets of this format.
+    pub fn is_supported(self) -> bool {
+        matches!(self, Self::Hls | Self::Unknown)
+    }
+}
+
+impl fmt::Display for AssetFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Hls => "HLS",
+            Self::Unknown => "unknown",
+            Self::Dash => "DASH",
+            Self::File => "file",
+        })
+    }
+}
+
+impl MediaAsset {
+    /// The format, from the MIME type or else the extension of the URL path.
+    pub fn format(&self) -> AssetFormat {
+        match self.mime_type.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            Some("application/x-mpegurl" | "application/vnd.apple.mpegurl" | "audio/mpegurl") => return AssetFormat::Hls,
+            Some("application/dash+xml") => return AssetFormat::Dash,
+            Some(mime) if mime.starts_with("video/") || mime.starts_with("audio/") => return AssetFormat::File,
+            _ => {}
+        }
+        let path = self.url.split(['?', '#']).next().unwrap_or_default().to_ascii_lowercase();
+        match path.rsplit_once('.').map(|(_, extension)| extension) {
+            Some("m3u8") => AssetFormat::Hls,
+            Some("mpd") => AssetFormat::Dash,
+            Some("mp4" | "m4v" | "webm" | "mov") => AssetFormat::File,
+            _ => AssetFormat::Unknown,
+        }
+    }
+}
+
+impl VideoMetadata {
+    /// Reads the `applicationData` object of the embed page.
+    pub fn from_page(video_id: &str, application_data: &Value) -> Result<Self> {
+        let assets: Vec<MediaAsset> = application_data.get("mediaAssets")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|asset| Some(MediaAsset {
+                url: asset.get("url")?.as_str()?.to_string(),
+                kind: text(asset, &["/type"]),
+                mime_type: text(asset, &["/contentType"]),
+                language: text(asset, &["/language"]),
+            }))
+            .collect();
+        if assets.is_empty() {
+            return Err(Error::PlaylistNotFound { reason: "no media asset URL in the embed page".to_string() });
+        }
+        if !assets.iter().any(|asset| asset.format().is_supported()) {
+            let formats: Vec<String> = assets.iter().map(|asset| asset.format().to_string()).collect();
+            return Err(Error::PlaylistNotFound {
+                reason: format!("no HLS media asset in the embed page, only {}", formats.join(", ")),
+            });
+        }
+        Ok(Self { assets, ..Self::from_json(video_id, application_data) })
+    }
+
+    /// Reads a content API response, which may be wrapped in `response` or `data`.
+    pub fn from_api(video_id: &str, config: &Value) -> Result<Self> {
+        let body = ["/response", "/data"].iter()
+            .filter_map(|pointer| config.pointer(pointer))
+            .find(|body| body.is_object())
+            .unwrap_or(config);
+        let url = ["/streaming/hls/url", "/response/streaming/hls/url", "/data/streaming/hls/url"].iter()
+            .find_map(|pointer| config.pointer(pointer)?.as_str())
+            .ok_or_else(|| Error::PlaylistNotFound { reason: "could not find HLS URL in API response".to_string() })?;
+        let asset = MediaAsset {
+            url: url.to_string(),
+            kind: Some("VIDEO".to_string()),
+            mime_type: Some("application/x-mpegURL".to_string()),
+            language: None,
+        };
+        Ok(Self { assets: vec![asset], ..Self::from_json(video_id, body) })
+    }
+
+    /// Indices into `assets` of the supported assets in the order they are
+    /// tried: HLS first, then those of unknown format, each in source order.
+    pub fn preferred_assets(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.assets.len())
+            .filter(|&i| self.assets[i].format().is_supported())
+            .collect();
+        order.sort_by_key(|&i| self.assets[i].format());
+        order
+    }
+
+    /// URL of the asset tried first.
+    pub fn master_url(&self) -> Option<&str> {
+        self.preferred_assets().first().map(|&i| self.assets[i].url.as_str())
+    }
+
+    /// The fields both sources share, without assets.
+    fn from_json(video_id: &str, data: &Value) -> Self {
+        Self {
+            video_id: video_id.to_string(),
+            title: text(data, &["/mediaTitle", "/title"]),
+            duration: number(data, &["/mediaDuration"]),
+            thumbnail_url: text(data, &["/thumbnailUrl"]),
+            producer: text(data, &["/producer/name"]),
+            course: text(data, &["/course/name"]),
+            module: number(data, &["/moduleOrder"]).and_then(position),
+            lesson: number(data, &["/lesson/order"]).and_then(position),
+            locale: text(data, &["/locale"]),
+            upload_date: date(data, &["/uploadDate", "/createdAt"]),
+            assets: Vec::new(),
+        }
+    }
+}
+
+/// Opening tag of the script holding the embed page's Next.js data.
+const NEXT_DATA_TAG: &str = r#"<script id="__NEXT_DATA__" type="application/json">"#;
+
+/// Extracts the `applicationData` object from the `__NEXT_DATA__` JSON of an
+/// embed page's HTML.
+pub fn application_data(html: &str) -> Result<Value> {
+    let json = html.find(NEXT_DATA_TAG)
+        .map(|start| &html[start + NEXT_DATA_TAG.len()..])
+        .and_then(|rest| rest.find("</script>").map(|end| &rest[..end]))
+        .ok_or_else(|| Error::PlaylistNotFound { reason: "no __NEXT_DATA__ script in the embed page".to_string() })?;
+    let mut data: Value = serde_json::from_str(json).map_err(|e| Error::PlaylistNotFound {
+        reason: format!("invalid __NEXT_DATA__ JSON: {}", e),
+    })?;
+    data.pointer_mut("/props/pageProps/applicationData")
+        .map(Value::take)
+        .ok_or_else(|| Error::PlaylistNotFound { reason: "no media asset URL in the embed page".to_string() })
+}
+
+/// The first non-empty string at any of `pointers`.
+fn text(data: &Value, pointers: &[&str]) -> Option<String> {
+    pointers.iter()
+        .filter_map(|pointer| data.pointer(pointer))
+        .find_map(|value| value.as_str().map(str::trim).filter(|s| !s.is_empty()))
+        .map(String::from)
+}
+
+/// The first number, or string holding one, at any of `pointers`.
+fn number(data: &Value, pointers: &[&str]) -> Option<f64> {
+    pointers.iter()
+        .filter_map(|pointer| data.pointer(pointer))
+        .find_map(|value| value.as_f64().or_else(|| value.as_str()?.trim().parse().ok()))
+}
+
+/// The first date at any of `pointers`: a timestamp, as a number or a string
+/// holding one, becomes `YYYY-MM-DD`; other strings are kept as they are.
+fn date(data: &Value, pointers: &[&str]) -> Option<String> {
+    pointers.iter()
+        .filter_map(|pointer| data.pointer(pointer))
+        .find_map(|value| match value {
+            Value::Number(n) => date_from_timestamp(n.as_f64()?),
+            Value::String(s) => match s.trim() {
+                "" => None,
+                s => s.parse().map_or_else(|_| Some(s.to_string()), date_from_timestamp),
+            },
+            _ => None,
+        })
+}
+
+fn position(n: f64) -> Option<u32> {
+    (n.fract() == 0.0 && (0.0..=f64::from(u32::MAX)).contains(&n)).then_some(n as u32)
+}
+
+/// Formats a Unix timestamp, in seconds or milliseconds, as `YYYY-MM-DD`.
+/// Timestamps before 1970 or past the year 9999 give `None`.
+fn date_from_timestamp(timestamp: f64) -> Option<String> {
+    // Seconds would not reach 10^11 until the year 5138.
+    let seconds = if timestamp >= 1e11 { timestamp / 1000.0 } else { timestamp };
+    if !(0.0..253_402_300_800.0).contains(&seconds) {
+        return None;
+    }
+    let days = (seconds / 86_400.0).floor() as i64;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
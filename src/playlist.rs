@@ -0,0 +1,204 @@
+use std::ops::Range;
+
+use url::Url;
+
+use crate::error::{Error, Result};
+use crate::m3u8::{self, Key, KeyMethod, MediaType, Resolution};
+
+/// A variant stream advertised by a master playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    /// Absolute URL of the variant's media playlist.
+    pub url: String,
+    /// Peak bit rate from `BANDWIDTH=`, in bits per second.
+    pub bandwidth: u64,
+    /// Average bit rate from `AVERAGE-BANDWIDTH=`, in bits per second.
+    pub average_bandwidth: Option<u64>,
+    pub resolution: Option<Resolution>,
+    /// `CODECS=` as written, e.g. `avc1.4d401f,mp4a.40.2`.
+    pub codecs: Option<String>,
+    pub frame_rate: Option<f64>,
+    /// `GROUP-ID` of the audio renditions that go with the variant.
+    pub audio: Option<String>,
+    /// `GROUP-ID` of the subtitle renditions that go with the variant.
+    pub subtitles: Option<String>,
+}
+
+impl Variant {
+    /// Stand-in for a media playlist that was given where a master playlist was expected.
+    pub(crate) fn media_playlist(url: &str) -> Self {
+        Self { url: url.to_string(), bandwidth: 0, average_bandwidth: None, resolution: None, codecs: None, frame_rate: None, audio: None, subtitles: None }
+    }
+
+    /// Expected size of `duration` seconds of the stream, preferring the
+    /// average bit rate over the peak one.
+    pub fn estimated_size(&self, duration: f64) -> u64 {
+        (self.average_bandwidth.unwrap_or(self.bandwidth) as f64 * duration / 8.0) as u64
+    }
+}
+
+/// An alternative audio, subtitle or video rendition from `#EXT-X-MEDIA`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    pub media_type: MediaType,
+    /// `GROUP-ID` the variants refer to the rendition by.
+    pub group_id: String,
+    pub name: String,
+    /// RFC 5646 language tag, e.g. `pt-BR`.
+    pub language: Option<String>,
+    pub default: bool,
+    /// Absolute URL of the rendition's media playlist; `None` when it is
+    /// muxed into the variant streams.
+    pub url: Option<String>,
+    /// `CHANNELS=` of audio renditions, e.g. `2`.
+    pub channels: Option<String>,
+}
+
+/// The AES-128 key that applies to a segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentKey {
+    /// Absolute URL the key is fetched from.
+    pub url: String,
+    /// Initialization vector, either explicit or derived from the media sequence number.
+    pub iv: [u8; 16],
+}
+
+/// One entry of a media playlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaSegment {
+    /// Absolute URL of the segment.
+    pub url: String,
+    /// Media sequence number of the segment.
+    pub sequence: u64,
+    /// Byte range of the segment within `url`; the whole resource when `None`.
+    pub byte_range: Option<Range<u64>>,
+    /// Key to decrypt the segment with, if it is encrypted.
+    pub key: Option<SegmentKey>,
+    /// Initialization section (`#EXT-X-MAP`) for fragmented MP4 streams.
+    pub map: Option<InitSegment>,
+}
+
+/// The media initialization section that precedes fragmented MP4 segments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitSegment {
+    /// Absolute URL of the resource holding the section.
+    pub url: String,
+    /// Byte range of the section within `url`; the whole resource when `None`.
+    pub byte_range: Option<Range<u64>>,
+    /// Key the section is encrypted with, if any.
+    pub key: Option<SegmentKey>,
+}
+
+/// Resolves the variant streams of a parsed master playlist, joining their
+/// URIs onto `master_url`. The order of the playlist is kept.
+pub fn resolve_variants(master_url: &str, playlist: &m3u8::MasterPlaylist) -> Result<Vec<Variant>> {
+    playlist.variants.iter()
+        .map(|stream| Ok(Variant {
+            url: join_url(master_url, &stream.uri)?,
+            bandwidth: stream.bandwidth,
+            average_bandwidth: stream.average_bandwidth,
+            resolution: stream.resolution,
+            codecs: stream.codecs.clone(),
+            frame_rate: stream.frame_rate,
+            audio: stream.audio.clone(),
+            subtitles: stream.subtitles.clone(),
+        }))
+        .collect()
+}
+
+/// Resolves the `#EXT-X-MEDIA` renditions of a parsed master playlist,
+/// joining their URIs onto `master_url`. The order of the playlist is kept.
+pub fn resolve_renditions(master_url: &str, playlist: &m3u8::MasterPlaylist) -> Result<Vec<Rendition>> {
+    playlist.renditions.iter()
+        .map(|rendition| Ok(Rendition {
+            media_type: rendition.media_type,
+            group_id: rendition.group_id.clone(),
+            name: rendition.name.clone(),
+            language: rendition.language.clone(),
+            default: rendition.default,
+            url: rendition.uri.as_deref().map(|uri| join_url(master_url, uri)).transpose()?,
+            channels: rendition.channels.clone(),
+        }))
+        .collect()
+}
+
+/// Resolves a parsed media playlist into absolute, downloadable segments.
+///
+/// Relative URIs are joined onto `playlist_url`, implicit IVs are derived from
+/// the media sequence number and `#EXT-X-BYTERANGE` tags without an offset
+/// continue where the previous segment's range ended.
+pub fn resolve_segments(playlist_url: &str, playlist: &m3u8::MediaPlaylist) -> Result<Vec<MediaSegment>> {
+    let mut segments = Vec::with_capacity(playlist.segments.len());
+    // URI and end offset of the previous segment, if it was a sub-range.
+    let mut previous_range: Option<(&str, u64)> = None;
+
+    for segment in &playlist.segments {
+        let byte_range = match segment.byte_range {
+            None => None,
+            Some(range) => {
+                let start = match (range.offset, previous_range) {
+                    (Some(offset), _) => offset,
+                    (None, Some((uri, end))) if uri == segment.uri => end,
+                    (None, _) => {
+                        return Err(Error::invalid_playlist(format!(
+                            "#EXT-X-BYTERANGE for {} has no offset and does not follow a sub-range of the same resource",
+                            segment.uri,
+                        )));
+                    }
+                };
+                Some(byte_span(start, range.length)?)
+            }
+        };
+        previous_range = byte_range.as_ref().map(|range| (segment.uri.as_str(), range.end));
+
+        let map = segment.map.as_ref()
+            .map(|map| -> Result<InitSegment> {
+                if map.key.as_ref().is_some_and(|key| key.iv.is_none()) {
+                    return Err(Error::invalid_playlist(format!("encrypted #EXT-X-MAP {} has no IV", map.uri)));
+                }
+                Ok(InitSegment {
+                    url: join_url(playlist_url, &map.uri)?,
+                    byte_range: map.byte_range
+                        .map(|range| byte_span(range.offset.unwrap_or(0), range.length))
+                        .transpose()?,
+                    key: resolve_key(playlist_url, map.key.as_ref(), segment.sequence)?,
+                })
+            })
+            .transpose()?;
+
+        segments.push(MediaSegment {
+            url: join_url(playlist_url, &segment.uri)?,
+            sequence: segment.sequence,
+            byte_range,
+            key: resolve_key(playlist_url, segment.key.as_ref(), segment.sequence)?,
+            map,
+        });
+    }
+
+    Ok(segments)
+}
+
+fn byte_span(start: u64, length: u64) -> Result<Range<u64>> {
+    start.checked_add(length)
+        .map(|end| start..end)
+        .ok_or_else(|| Error::invalid_playlist(format!("byte range {}@{} overflows", length, start)))
+}
+
+/// Turns a playlist key into an absolute key URL and concrete IV.
+fn resolve_key(playlist_url: &str, key: Option<&Key>, sequence: u64) -> Result<Option<SegmentKey>> {
+    match key {
+        None => Ok(None),
+        Some(key) if key.method == KeyMethod::Aes128 => Ok(Some(SegmentKey {
+            url: join_url(playlist_url, &key.uri)?,
+            iv: key.iv_for(sequence),
+        })),
+        Some(key) => Err(Error::invalid_playlist(format!("unsupported encryption method {}", key.method))),
+    }
+}
+
+pub(crate) fn join_url(base: &str, reference: &str) -> Result<String> {
+    Url::parse(base)
+        .and_then(|base| base.join(reference))
+        .map(|url| url.to_string())
+        .map_err(|e| Error::UrlParse(format!("{} relative to {}: {}", reference, base, e)))
+}
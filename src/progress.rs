@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::playlist::{Rendition, Variant};
+
+/// Events emitted while a download moves through the pipeline.
+///
+/// Both the CLI and the GUI consume this single stream, so anything a frontend
+/// needs to display should be reported here rather than printed directly.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// The embed page could not be parsed and the content API is being tried instead.
+    PageFallback { reason: String },
+    /// The master playlist URL has been resolved.
+    MasterPlaylist { url: String },
+    /// The master playlist of a media asset could not be loaded and the next
+    /// asset is being tried, if there is one.
+    AssetFallback { url: String, reason: String },
+    /// A variant stream has been picked from the master playlist.
+    VariantSelected { variant: Variant },
+    /// An alternate rendition, e.g. separate audio, will be downloaded with the variant.
+    RenditionSelected { rendition: Rendition },
+    /// The media playlist has been parsed.
+    SegmentsFound { total: usize },
+    /// A request failed with a transient error and will be retried after `delay`.
+    Retry { url: String, attempt: u32, max_retries: u32, delay: Duration, reason: String },
+    /// A checkpoint was found and the first `completed` segments are already on disk.
+    Resumed { completed: usize, total: usize },
+    /// A checkpoint was found but does not match this download, which starts over.
+    ResumeDiscarded { reason: String },
+    /// Segment `current` (1-based) of `total` has been written to disk.
+    Segment { current: usize, total: usize },
+    /// All segments have been written and the MPEG-TS stream is being remuxed into MP4.
+    Remuxing,
+    /// All segments have been written.
+    Finished { path: PathBuf },
+}
+
+/// Shared event callback handed to the download workers.
+pub(crate) type EventSink = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
@@ -0,0 +1,69 @@
+use crate::error::{Error, Result};
+
+/// Samples per channel in every AAC frame.
+pub(super) const SAMPLES_PER_FRAME: u32 = 1024;
+
+const SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+/// Stream parameters carried in every ADTS header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct AudioConfig {
+    /// MPEG-4 audio object type, e.g. 2 for AAC-LC.
+    pub object_type: u8,
+    pub frequency_index: u8,
+    pub channels: u8,
+}
+
+impl AudioConfig {
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATES[usize::from(self.frequency_index)]
+    }
+
+    /// The two-byte `AudioSpecificConfig` that goes into the `esds` box.
+    pub fn audio_specific_config(&self) -> [u8; 2] {
+        [
+            self.object_type << 3 | self.frequency_index >> 1,
+            (self.frequency_index & 1) << 7 | self.channels << 3,
+        ]
+    }
+}
+
+/// One ADTS frame found at the start of a buffer.
+pub(super) struct AdtsFrame {
+    pub config: AudioConfig,
+    /// Length of the ADTS header; the raw AAC data follows it.
+    pub header_len: usize,
+    /// Length of the whole frame, header included.
+    pub frame_len: usize,
+}
+
+/// Parses the ADTS frame at the start of `data`. Returns `None` if `data` does
+/// not yet hold the whole frame.
+pub(super) fn parse_frame(data: &[u8]) -> Result<Option<AdtsFrame>> {
+    if data.len() < 7 {
+        return Ok(None);
+    }
+    if data[0] != 0xff || data[1] & 0xf6 != 0xf0 {
+        return Err(Error::remux("lost ADTS sync in the AAC stream"));
+    }
+
+    let protection_absent = data[1] & 0x01 != 0;
+    let profile = data[2] >> 6;
+    let frequency_index = (data[2] >> 2) & 0x0f;
+    let channels = (data[2] & 0x01) << 2 | data[3] >> 6;
+    let frame_len = usize::from(data[3] & 0x03) << 11 | usize::from(data[4]) << 3 | usize::from(data[5] >> 5);
+    let header_len = if protection_absent { 7 } else { 9 };
+
+    if usize::from(frequency_index) >= SAMPLE_RATES.len() {
+        return Err(Error::remux(format!("invalid AAC sampling frequency index {}", frequency_index)));
+    }
+    if frame_len < header_len {
+        return Err(Error::remux("ADTS frame shorter than its header"));
+    }
+    if frame_len > data.len() {
+        return Ok(None);
+    }
+
+    let config = AudioConfig { object_type: profile + 1, frequency_index, channels };
+    Ok(Some(AdtsFrame { config, header_len, frame_len }))
+}
@@ -0,0 +1,198 @@
+use crate::error::{Error, Result};
+
+pub(super) const NAL_IDR: u8 = 5;
+pub(super) const NAL_SPS: u8 = 7;
+pub(super) const NAL_PPS: u8 = 8;
+pub(super) const NAL_AUD: u8 = 9;
+pub(super) const NAL_FILLER: u8 = 12;
+
+pub(super) fn nal_type(nal: &[u8]) -> u8 {
+    nal[0] & 0x1f
+}
+
+/// Splits an Annex B byte stream on its `00 00 01` / `00 00 00 01` start codes.
+pub(super) fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = match find_start_code(data) {
+        Some((_, end)) => &data[end..],
+        None => &[][..],
+    };
+    std::iter::from_fn(move || loop {
+        if rest.is_empty() {
+            return None;
+        }
+        let (nal, next) = match find_start_code(rest) {
+            Some((start, end)) => (&rest[..start], &rest[end..]),
+            None => (rest, &[][..]),
+        };
+        rest = next;
+
+        // Trailing zero bytes belong to the next start code, not the NAL unit.
+        let len = nal.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        if len > 0 {
+            return Some(&nal[..len]);
+        }
+    })
+}
+
+/// Position of the first start code as `(start, end)`.
+fn find_start_code(data: &[u8]) -> Option<(usize, usize)> {
+    data.windows(3)
+        .position(|w| w == [0, 0, 1])
+        .map(|i| (i, i + 3))
+}
+
+/// The parts of a sequence parameter set the MP4 sample entry needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct SpsInfo {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Reads the cropped frame size from an SPS NAL unit (H.264 section 7.3.2.1.1).
+pub(super) fn parse_sps(nal: &[u8]) -> Result<SpsInfo> {
+    let rbsp = remove_emulation_prevention(nal.get(1..).unwrap_or_default());
+    parse_sps_rbsp(&rbsp).ok_or_else(|| Error::remux("malformed H.264 sequence parameter set"))
+}
+
+fn parse_sps_rbsp(rbsp: &[u8]) -> Option<SpsInfo> {
+    let mut bits = BitReader::new(rbsp);
+    let profile_idc = bits.bits(8)?;
+    bits.bits(16)?; // constraint flags and level_idc
+    bits.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(profile_idc, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+        chroma_format_idc = bits.ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = bits.flag()?;
+        }
+        bits.ue()?; // bit_depth_luma_minus8
+        bits.ue()?; // bit_depth_chroma_minus8
+        bits.flag()?; // qpprime_y_zero_transform_bypass_flag
+        if bits.flag()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if bits.flag()? {
+                    skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    bits.ue()?; // log2_max_frame_num_minus4
+    match bits.ue()? {
+        0 => {
+            bits.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            bits.flag()?; // delta_pic_order_always_zero_flag
+            bits.se()?; // offset_for_non_ref_pic
+            bits.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..bits.ue()? {
+                bits.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    bits.ue()?; // max_num_ref_frames
+    bits.flag()?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = bits.ue()? + 1;
+    let height_in_map_units = bits.ue()? + 1;
+    let frame_mbs_only = bits.flag()?;
+    if !frame_mbs_only {
+        bits.flag()?; // mb_adaptive_frame_field_flag
+    }
+    bits.flag()?; // direct_8x8_inference_flag
+
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let mut width = width_in_mbs.checked_mul(16)?;
+    let mut height = height_in_map_units.checked_mul(16 * field_factor)?;
+
+    if bits.flag()? {
+        let (left, right, top, bottom) = (bits.ue()?, bits.ue()?, bits.ue()?, bits.ue()?);
+        let chroma_array_type = if separate_colour_plane { 0 } else { chroma_format_idc };
+        let (crop_x, crop_y) = match chroma_array_type {
+            0 => (1, field_factor),
+            1 => (2, 2 * field_factor),
+            2 => (2, field_factor),
+            _ => (1, field_factor),
+        };
+        width = width.checked_sub(left.checked_add(right)?.checked_mul(crop_x)?)?;
+        height = height.checked_sub(top.checked_add(bottom)?.checked_mul(crop_y)?)?;
+    }
+
+    Some(SpsInfo { width, height })
+}
+
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale = 8i64;
+    let mut next_scale = 8i64;
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + bits.se()? + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+/// Drops the `03` in every `00 00 03` sequence.
+fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        out.push(byte);
+    }
+    out
+}
+
+/// MSB-first bit reader with Exp-Golomb support.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        let byte = *self.data.get(self.position / 8)?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Some(bit == 1)
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        (0..count).try_fold(0, |value, _| Some(value << 1 | u32::from(self.flag()?)))
+    }
+
+    /// Unsigned Exp-Golomb code.
+    fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.flag()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        let suffix = self.bits(leading_zeros)?;
+        ((1u64 << leading_zeros) - 1 + u64::from(suffix)).try_into().ok()
+    }
+
+    /// Signed Exp-Golomb code.
+    fn se(&mut self) -> Option<i64> {
+        let code = i64::from(self.ue()?);
+        Some(if code % 2 == 1 { (code + 1) / 2 } else { -(code / 2) })
+    }
+}
@@ -0,0 +1,490 @@
+//! MPEG-TS to MP4 remuxing.
+//!
+//! HLS streams without `#EXT-X-MAP` deliver MPEG-TS segments, and
+//! concatenating them gives a `.ts` file that many players and editors
+//! refuse. [`ts_to_mp4`] demuxes the H.264 and AAC elementary streams and
+//! rewrites them as an ISO-BMFF file with `moov` ahead of `mdat`, so playback
+//! can start before the whole file has been read. No re-encoding takes place.
+//!
+//! Audio from an alternate rendition (`#EXT-X-MEDIA:TYPE=AUDIO`) can be muxed
+//! in from a second file, and subtitles added as `mov_text` tracks, with
+//! [`ts_to_mp4_with`].
+
+mod aac;
+mod h264;
+mod mp4;
+mod packed;
+mod ts;
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::subtitles::{self, SubtitleTrack};
+use ts::{Pes, StreamType, PACKET_SIZE, SYNC_BYTE};
+
+pub(crate) use packed::is_packed_audio;
+
+/// The MPEG clock all PES timestamps are expressed in.
+const MPEG_TIMESCALE: u32 = 90_000;
+
+/// PTS and DTS are 33-bit counters that wrap around after about 26.5 hours.
+const TIMESTAMP_WRAP: i64 = 1 << 33;
+
+/// Remuxes the MPEG-TS file at `input` into an MP4 file at `output`.
+///
+/// Sample data is staged in a temporary file next to `output` while the
+/// sample tables are built, then copied behind the finished `moov` box, so
+/// memory use does not grow with the length of the video.
+pub fn ts_to_mp4(input: &Path, output: &Path) -> Result<()> {
+    ts_to_mp4_with(input, None, &[], output)
+}
+
+/// Remuxes the video of the MPEG-TS file at `video` and the audio of the file
+/// at `audio` into an MP4 file at `output`. Any audio muxed into `video` is
+/// dropped.
+///
+/// `audio` may be MPEG-TS or packed audio, i.e. ADTS frames with ID3
+/// timestamps. Both files must use the same 90 kHz clock, as HLS renditions
+/// of one presentation do, so the tracks stay in sync.
+pub fn ts_to_mp4_with_audio(video: &Path, audio: &Path, output: &Path) -> Result<()> {
+    ts_to_mp4_with(video, Some(audio), &[], output)
+}
+
+/// Remuxes `video`, with the audio of `audio` if given (see
+/// [`ts_to_mp4_with_audio`]), and adds each of `subtitles` as a `mov_text`
+/// track. Cue times count from the first video frame.
+pub fn ts_to_mp4_with(video: &Path, audio: Option<&Path>, subtitles: &[SubtitleTrack], output: &Path) -> Result<()> {
+    let mut payload_name = output.file_name().unwrap_or_default().to_os_string();
+    payload_name.push(".mdat");
+    let payload_path = output.with_file_name(payload_name);
+
+    let result = remux(video, audio, subtitles, output, &payload_path);
+    let _ = fs::remove_file(&payload_path);
+    result
+}
+
+/// Presentation timestamp of the first video frame (or, without video, the
+/// first audio frame) of the MPEG-TS file at `input`, on the 90 kHz clock.
+///
+/// This is the time [`ts_to_mp4_with`] counts subtitle cues from.
+pub fn start_time(input: &Path) -> Result<Option<u64>> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut demuxer = ts::Demuxer::default();
+    let mut packet = [0u8; PACKET_SIZE];
+    let mut audio_start = None;
+
+    while ts::read_packet(&mut reader, &mut packet)? {
+        match demuxer.push(&packet)? {
+            Some(Pes { stream: StreamType::H264, pts: Some(pts), .. }) => return Ok(Some(pts)),
+            Some(Pes { stream: StreamType::Aac, pts: Some(pts), .. }) => {
+                audio_start.get_or_insert(pts);
+            }
+            _ => {}
+        }
+        // A stream without video still has its audio timestamps early on.
+        if audio_start.is_some() && !demuxer.has_video() {
+            break;
+        }
+    }
+    Ok(audio_start)
+}
+
+fn remux(video: &Path, audio: Option<&Path>, subtitles: &[SubtitleTrack], output: &Path, payload_path: &Path) -> Result<()> {
+    let mut muxer = Muxer::new(BufWriter::new(File::create(payload_path)?));
+    match audio {
+        None => demux_ts(video, None, &mut muxer)?,
+        Some(audio) => {
+            demux_ts(video, Some(StreamType::H264), &mut muxer)?;
+
+            let mut reader = BufReader::new(File::open(audio)?);
+            if packed::is_packed_audio(reader.fill_buf()?) {
+                let mut frames = packed::PackedAudio::new(reader);
+                while let Some(pes) = frames.next_frame()? {
+                    muxer.push(pes)?;
+                }
+            } else {
+                demux_ts(audio, Some(StreamType::Aac), &mut muxer)?;
+            }
+        }
+    }
+    for track in subtitles {
+        muxer.push_text(track)?;
+    }
+
+    let (tracks, payload_len) = muxer.finish()?;
+    mp4::write(output, &tracks, payload_path, payload_len)
+}
+
+/// Feeds the elementary streams of the MPEG-TS file at `input` to `muxer`,
+/// or only the one of type `only` if given.
+fn demux_ts<W: Write>(input: &Path, only: Option<StreamType>, muxer: &mut Muxer<W>) -> Result<()> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut first_byte = [0u8; 1];
+    if reader.read(&mut first_byte)? == 0 || first_byte[0] != SYNC_BYTE {
+        return Err(Error::remux(format!("{} is not an MPEG-TS stream", input.display())));
+    }
+
+    let mut demuxer = ts::Demuxer::default();
+    let mut packet = [0u8; PACKET_SIZE];
+    let mut reader = (&first_byte[..]).chain(reader);
+    let wanted = |pes: &Pes| only.is_none_or(|stream| pes.stream == stream);
+
+    while ts::read_packet(&mut reader, &mut packet)? {
+        if let Some(pes) = demuxer.push(&packet)?.filter(wanted) {
+            muxer.push(pes)?;
+        }
+    }
+    if !demuxer.has_streams() {
+        return Err(Error::remux(format!("no H.264 or AAC stream found in {}", input.display())));
+    }
+    for pes in demuxer.finish()?.into_iter().filter(wanted) {
+        muxer.push(pes)?;
+    }
+    Ok(())
+}
+
+/// One sample's place in the staged payload and on its track's timeline.
+struct Sample {
+    /// Offset of the sample in the payload, i.e. relative to the `mdat` body.
+    offset: u64,
+    size: u32,
+    /// Decode time in the track's timescale.
+    dts: i64,
+    /// Presentation time minus decode time.
+    composition_offset: u32,
+    sync: bool,
+}
+
+enum Media {
+    Video { width: u32, height: u32, sps: Vec<u8>, pps: Vec<u8> },
+    Audio(aac::AudioConfig),
+    /// `mov_text` subtitles, with an ISO 639-2 language code.
+    Text { language: [u8; 3] },
+}
+
+/// Timescale of subtitle tracks, i.e. milliseconds.
+const TEXT_TIMESCALE: u32 = 1000;
+
+struct Track {
+    media: Media,
+    timescale: u32,
+    samples: Vec<Sample>,
+    /// Presentation time of the first sample on the 90 kHz clock, used to
+    /// line the tracks up with each other.
+    start: i64,
+}
+
+impl Track {
+    /// Duration of each sample in the track's timescale.
+    fn durations(&self) -> Vec<u32> {
+        match self.media {
+            // Frames last until the next, which is longer across a gap in the audio.
+            Media::Audio(_) => {
+                let mut durations: Vec<u32> = self.samples.windows(2)
+                    .map(|pair| u32::try_from(pair[1].dts - pair[0].dts).unwrap_or(u32::MAX))
+                    .collect();
+                if !self.samples.is_empty() {
+                    durations.push(aac::SAMPLES_PER_FRAME);
+                }
+                durations
+            }
+            // Each sample lasts until the next; the last one is an empty
+            // sample that only marks where the final cue ends.
+            Media::Text { .. } => {
+                let mut durations: Vec<u32> = self.samples.windows(2)
+                    .map(|pair| u32::try_from(pair[1].dts - pair[0].dts).unwrap_or(u32::MAX))
+                    .collect();
+                if !self.samples.is_empty() {
+                    durations.push(0);
+                }
+                durations
+            }
+            Media::Video { .. } => {
+                let mut durations: Vec<u32> = self.samples.windows(2)
+                    .map(|pair| u32::try_from(pair[1].dts - pair[0].dts).unwrap_or(u32::MAX))
+                    .collect();
+                // The last frame is assumed to last as long as the one before it.
+                let last = durations.last().copied().unwrap_or(MPEG_TIMESCALE / 30);
+                if !self.samples.is_empty() {
+                    durations.push(last);
+                }
+                durations
+            }
+        }
+    }
+}
+
+/// Maps raw 33-bit decode timestamps onto a strictly increasing timeline.
+///
+/// Wrap-around is unwrapped, and a jump backwards or a gap of more than
+/// [`Timeline::MAX_GAP`] (what a discontinuity looks like after the segments
+/// are concatenated) is closed by continuing from the previous frame.
+#[derive(Default)]
+struct Timeline {
+    offset: i64,
+    last: Option<i64>,
+    step: i64,
+}
+
+impl Timeline {
+    const MAX_GAP: i64 = 10 * MPEG_TIMESCALE as i64;
+
+    fn map(&mut self, raw: u64) -> i64 {
+        let mut time = raw as i64 + self.offset;
+        if let Some(last) = self.last {
+            if last - time > TIMESTAMP_WRAP / 2 {
+                self.offset += TIMESTAMP_WRAP;
+                time += TIMESTAMP_WRAP;
+            }
+            if time <= last || time - last > Self::MAX_GAP {
+                let step = if self.step > 0 { self.step } else { i64::from(MPEG_TIMESCALE / 30) };
+                self.offset += last + step - time;
+                time = last + step;
+            }
+            self.step = time - last;
+        }
+        self.last = Some(time);
+        time
+    }
+}
+
+#[derive(Default)]
+struct VideoState {
+    timeline: Timeline,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    samples: Vec<Sample>,
+    start: i64,
+}
+
+#[derive(Default)]
+struct AudioState {
+    timeline: Timeline,
+    config: Option<aac::AudioConfig>,
+    /// Start of an ADTS frame cut off at the end of the previous PES.
+    partial: Vec<u8>,
+    samples: Vec<Sample>,
+    start: i64,
+    /// Decode time of the next frame if none are missing, in the track's timescale.
+    next: i64,
+}
+
+/// Turns PES packets into MP4 samples, appending their data to the payload.
+struct Muxer<W> {
+    payload: W,
+    written: u64,
+    video: VideoState,
+    audio: AudioState,
+    text: Vec<Track>,
+}
+
+impl<W: Write> Muxer<W> {
+    fn new(payload: W) -> Self {
+        Self { payload, written: 0, video: VideoState::default(), audio: AudioState::default(), text: Vec::new() }
+    }
+
+    fn push(&mut self, pes: Pes) -> Result<()> {
+        match pes.stream {
+            StreamType::H264 => self.push_video(pes),
+            StreamType::Aac => self.push_audio(pes),
+        }
+    }
+
+    /// Writes `data` to the payload and returns its offset and size.
+    fn append(&mut self, data: &[u8]) -> Result<(u64, u32)> {
+        let size = u32::try_from(data.len()).map_err(|_| Error::remux("sample larger than 4 GiB"))?;
+        self.payload.write_all(data)?;
+        let offset = self.written;
+        self.written += u64::from(size);
+        Ok((offset, size))
+    }
+
+    /// One PES is one access unit. Its Annex B NAL units are rewritten with
+    /// 4-byte length prefixes; parameter sets move into the sample entry.
+    fn push_video(&mut self, pes: Pes) -> Result<()> {
+        let Some(pts) = pes.pts else {
+            return Err(Error::remux("H.264 access unit without a timestamp"));
+        };
+        let dts = pes.dts.unwrap_or(pts);
+
+        let mut sample = Vec::with_capacity(pes.data.len() + 16);
+        let mut sync = false;
+        for nal in h264::nal_units(&pes.data) {
+            let parameter_set = match h264::nal_type(nal) {
+                h264::NAL_SPS => Some(&mut self.video.sps),
+                h264::NAL_PPS => Some(&mut self.video.pps),
+                h264::NAL_AUD | h264::NAL_FILLER => continue,
+                h264::NAL_IDR => {
+                    sync = true;
+                    None
+                }
+                _ => None,
+            };
+            // Parameter sets that differ from the first ones stay in-band.
+            if let Some(stored) = parameter_set {
+                if stored.get_or_insert_with(|| nal.to_vec()) == nal {
+                    continue;
+                }
+            }
+            sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            sample.extend_from_slice(nal);
+        }
+        if sample.is_empty() {
+            return Ok(());
+        }
+
+        // PTS is never earlier than DTS; anything else is a broken stream.
+        let composition_offset = match (pts.wrapping_sub(dts)) & (TIMESTAMP_WRAP as u64 - 1) {
+            offset if offset < u64::from(MPEG_TIMESCALE) * 10 => offset as u32,
+            _ => 0,
+        };
+        let dts = self.video.timeline.map(dts);
+        if self.video.samples.is_empty() {
+            self.video.start = dts + i64::from(composition_offset);
+        }
+
+        let (offset, size) = self.append(&sample)?;
+        self.video.samples.push(Sample { offset, size, dts, composition_offset, sync });
+        Ok(())
+    }
+
+    /// A PES holds one or more ADTS frames; each raw frame becomes a sample.
+    ///
+    /// Frames follow each other without gaps, except where the PES timestamp
+    /// is at least a frame ahead of them: frames that were lost or cut out
+    /// leave a gap of the same length instead of pulling the audio forward.
+    fn push_audio(&mut self, pes: Pes) -> Result<()> {
+        let first = self.audio.timeline.last.is_none();
+        let mut pts = pes.pts.map(|pts| self.audio.timeline.map(pts));
+        if let (true, Some(pts)) = (first, pts) {
+            self.audio.start = pts;
+        }
+
+        // The timestamp belongs to the first frame that starts in this PES,
+        // not to the rest of a frame carried over from the previous one.
+        let carried = self.audio.partial.len();
+        let mut data = std::mem::take(&mut self.audio.partial);
+        data.extend_from_slice(&pes.data);
+
+        let mut rest = &data[..];
+        while let Some(frame) = aac::parse_frame(rest)? {
+            let config = *self.audio.config.get_or_insert(frame.config);
+            if config.frequency_index != frame.config.frequency_index {
+                return Err(Error::remux("AAC sample rate changes mid-stream"));
+            }
+
+            let mut dts = self.audio.next;
+            if data.len() - rest.len() >= carried {
+                if let Some(pts) = pts.take() {
+                    let expected = (pts - self.audio.start) * i64::from(config.sample_rate()) / i64::from(MPEG_TIMESCALE);
+                    if expected - dts >= i64::from(aac::SAMPLES_PER_FRAME) {
+                        dts = expected;
+                    }
+                }
+            }
+            let (offset, size) = self.append(&rest[frame.header_len..frame.frame_len])?;
+            self.audio.samples.push(Sample { offset, size, dts, composition_offset: 0, sync: true });
+            self.audio.next = dts + i64::from(aac::SAMPLES_PER_FRAME);
+            rest = &rest[frame.frame_len..];
+        }
+        self.audio.partial = rest.to_vec();
+        Ok(())
+    }
+
+    /// Adds a subtitle track. Must be called after all audio and video has
+    /// been pushed, since cue times count from the start of the video.
+    ///
+    /// `mov_text` shows one sample at a time, so gaps between cues become
+    /// empty samples and overlapping cues are cut short.
+    fn push_text(&mut self, track: &SubtitleTrack) -> Result<()> {
+        if track.cues.is_empty() {
+            return Ok(());
+        }
+
+        let mut samples = Vec::new();
+        let mut cursor = 0u64;
+        for cue in &track.cues {
+            let start = (cue.start.as_millis() as u64).max(cursor);
+            let end = cue.end.as_millis() as u64;
+            if end <= start {
+                continue;
+            }
+            if start > cursor {
+                let (offset, size) = self.append(&[0, 0])?;
+                samples.push(Sample { offset, size, dts: cursor as i64, composition_offset: 0, sync: true });
+            }
+
+            let text = subtitles::plain_text(cue);
+            let mut text = text.as_bytes();
+            text = &text[..text.len().min(usize::from(u16::MAX))];
+            let mut sample = Vec::with_capacity(text.len() + 2);
+            sample.extend_from_slice(&(text.len() as u16).to_be_bytes());
+            sample.extend_from_slice(text);
+            let (offset, size) = self.append(&sample)?;
+            samples.push(Sample { offset, size, dts: start as i64, composition_offset: 0, sync: true });
+            cursor = end;
+        }
+        let (offset, size) = self.append(&[0, 0])?;
+        samples.push(Sample { offset, size, dts: cursor as i64, composition_offset: 0, sync: true });
+
+        let start = if self.video.samples.is_empty() { self.audio.start } else { self.video.start };
+        self.text.push(Track {
+            media: Media::Text { language: iso_639_2(track.language.as_deref()) },
+            timescale: TEXT_TIMESCALE,
+            samples,
+            start,
+        });
+        Ok(())
+    }
+
+    /// Flushes the payload and returns the finished tracks with its length.
+    fn finish(mut self) -> Result<(Vec<Track>, u64)> {
+        self.payload.flush()?;
+
+        let mut tracks = Vec::new();
+        if !self.video.samples.is_empty() {
+            let (Some(sps), Some(pps)) = (self.video.sps, self.video.pps) else {
+                return Err(Error::remux("H.264 stream without SPS or PPS"));
+            };
+            let info = h264::parse_sps(&sps)?;
+            tracks.push(Track {
+                media: Media::Video { width: info.width, height: info.height, sps, pps },
+                timescale: MPEG_TIMESCALE,
+                samples: self.video.samples,
+                start: self.video.start,
+            });
+        }
+        if let Some(config) = self.audio.config {
+            tracks.push(Track {
+                timescale: config.sample_rate(),
+                media: Media::Audio(config),
+                samples: self.audio.samples,
+                start: self.audio.start,
+            });
+        }
+
+        if tracks.is_empty() {
+            return Err(Error::remux("the stream contains no samples"));
+        }
+        tracks.extend(self.text);
+        Ok((tracks, self.written))
+    }
+}
+
+/// ISO 639-2/T code for an RFC 5646 language tag, as `mdhd` wants it.
+/// Tags whose primary language is not in the table become `und`.
+fn iso_639_2(tag: Option<&str>) -> [u8; 3] {
+    const CODES: [(&str, &[u8; 3]); 16] = [
+        ("ar", b"ara"), ("de", b"deu"), ("en", b"eng"), ("es", b"spa"),
+        ("fr", b"fra"), ("hi", b"hin"), ("it", b"ita"), ("ja", b"jpn"),
+        ("ko", b"kor"), ("nl", b"nld"), ("pl", b"pol"), ("pt", b"por"),
+        ("ru", b"rus"), ("tr", b"tur"), ("uk", b"ukr"), ("zh", b"zho"),
+    ];
+    let primary = tag.and_then(|tag| tag.split('-').next()).unwrap_or_default().to_ascii_lowercase();
+    match primary.as_bytes() {
+        code @ [_, _, _] if code.iter().all(u8::is_ascii_lowercase) => [code[0], code[1], code[2]],
+        _ => CODES.iter().find(|(short, _)| *short == primary).map_or(*b"und", |(_, code)| **code),
+    }
+}
@@ -0,0 +1,455 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{Media, Track, MPEG_TIMESCALE};
+use crate::error::Result;
+
+/// Timescale of `mvhd`, `tkhd` and edit lists.
+const MOVIE_TIMESCALE: u32 = 1000;
+
+const IDENTITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+/// Writes `ftyp`, `moov` and an `mdat` holding the staged payload to `output`.
+pub(super) fn write(output: &Path, tracks: &[Track], payload_path: &Path, payload_len: u64) -> Result<()> {
+    let ftyp = ftyp();
+    let mdat_header_len: u64 = if payload_len + 8 > u64::from(u32::MAX) { 16 } else { 8 };
+
+    // Chunk offsets point past `moov`, but the size of `moov` only depends on
+    // whether they need 32 or 64 bits, not on their values.
+    let mut large_offsets = false;
+    let mut moov_len = moov(tracks, 0, false).len() as u64;
+    if ftyp.len() as u64 + moov_len + mdat_header_len + payload_len > u64::from(u32::MAX) {
+        large_offsets = true;
+        moov_len = moov(tracks, 0, true).len() as u64;
+    }
+    let mdat_start = ftyp.len() as u64 + moov_len + mdat_header_len;
+    let moov = moov(tracks, mdat_start, large_offsets);
+
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(&ftyp)?;
+    out.write_all(&moov)?;
+    if mdat_header_len == 16 {
+        out.write_all(&1u32.to_be_bytes())?;
+        out.write_all(b"mdat")?;
+        out.write_all(&(payload_len + 16).to_be_bytes())?;
+    } else {
+        out.write_all(&((payload_len + 8) as u32).to_be_bytes())?;
+        out.write_all(b"mdat")?;
+    }
+    io::copy(&mut File::open(payload_path)?, &mut out)?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+fn ftyp() -> Vec<u8> {
+    let mut b = Boxes::default();
+    b.boxed(b"ftyp", |b| {
+        b.bytes(b"isom");
+        b.u32(0x200);
+        for brand in [b"isom", b"iso2", b"avc1", b"mp41"] {
+            b.bytes(brand);
+        }
+    });
+    b.0
+}
+
+fn moov(tracks: &[Track], mdat_start: u64, large_offsets: bool) -> Vec<u8> {
+    // Tracks are shifted so the earliest one starts at zero; the others get
+    // an empty edit for the time before their first sample.
+    let movie_start = tracks.iter().map(|t| t.start).min().unwrap_or(0);
+    let layouts: Vec<_> = tracks.iter().map(|track| TrackLayout::new(track, movie_start)).collect();
+    let movie_duration = layouts.iter().map(|l| l.delay + l.duration).max().unwrap_or(0);
+
+    let mut b = Boxes::default();
+    b.boxed(b"moov", |b| {
+        b.full_box(b"mvhd", version_for(movie_duration), 0, |b| {
+            b.times(movie_duration, MOVIE_TIMESCALE);
+            b.u32(0x0001_0000); // rate 1.0
+            b.u16(0x0100); // volume 1.0
+            b.zeros(10);
+            b.matrix();
+            b.zeros(24);
+            b.u32(tracks.len() as u32 + 1);
+        });
+        for (index, (track, layout)) in tracks.iter().zip(&layouts).enumerate() {
+            trak(b, index as u32 + 1, track, layout, mdat_start, large_offsets);
+        }
+    });
+    b.0
+}
+
+/// Where a track sits on the movie timeline, in movie timescale units.
+struct TrackLayout {
+    delay: u64,
+    duration: u64,
+    durations: Vec<u32>,
+    media_duration: u64,
+}
+
+impl TrackLayout {
+    fn new(track: &Track, movie_start: i64) -> Self {
+        let durations = track.durations();
+        let media_duration = durations.iter().map(|&d| u64::from(d)).sum();
+        let delay = u64::try_from(track.start - movie_start).unwrap_or(0);
+        Self {
+            delay: scale(delay, MPEG_TIMESCALE, MOVIE_TIMESCALE),
+            duration: scale(media_duration, track.timescale, MOVIE_TIMESCALE),
+            durations,
+            media_duration,
+        }
+    }
+}
+
+fn trak(b: &mut Boxes, track_id: u32, track: &Track, layout: &TrackLayout, mdat_start: u64, large_offsets: bool) {
+    let (width, height) = match track.media {
+        Media::Video { width, height, .. } => (width, height),
+        Media::Audio(_) | Media::Text { .. } => (0, 0),
+    };
+    let is_audio = matches!(track.media, Media::Audio(_));
+    let language = match track.media {
+        Media::Text { language } => language,
+        _ => *b"und",
+    };
+    let media_time = track.samples.first().map_or(0, |s| s.composition_offset);
+
+    b.boxed(b"trak", |b| {
+        let duration = layout.delay + layout.duration;
+        let version = version_for(duration);
+        // Flags: track enabled, in movie, in preview.
+        b.full_box(b"tkhd", version, 0x7, |b| {
+            if version == 1 {
+                b.zeros(16);
+                b.u32(track_id);
+                b.u32(0);
+                b.u64(duration);
+            } else {
+                b.zeros(8);
+                b.u32(track_id);
+                b.u32(0);
+                b.u32(duration as u32);
+            }
+            b.zeros(8);
+            b.u16(0); // layer
+            // Subtitle tracks are alternatives to each other.
+            b.u16(u16::from(matches!(track.media, Media::Text { .. })));
+            b.u16(if is_audio { 0x0100 } else { 0 });
+            b.u16(0);
+            b.matrix();
+            b.u32(width << 16);
+            b.u32(height << 16);
+        });
+
+        if layout.delay > 0 || media_time > 0 {
+            b.boxed(b"edts", |b| {
+                let version = version_for(layout.delay.max(layout.duration));
+                let mut edits = Vec::new();
+                if layout.delay > 0 {
+                    edits.push((layout.delay, -1)); // an empty edit
+                }
+                edits.push((layout.duration, i64::from(media_time)));
+
+                b.full_box(b"elst", version, 0, |b| {
+                    b.u32(edits.len() as u32);
+                    for (duration, media_time) in edits {
+                        if version == 1 {
+                            b.u64(duration);
+                            b.u64(media_time as u64);
+                        } else {
+                            b.u32(duration as u32);
+                            b.u32(media_time as u32);
+                        }
+                        b.u32(0x0001_0000); // rate 1.0
+                    }
+                });
+            });
+        }
+
+        b.boxed(b"mdia", |b| {
+            b.full_box(b"mdhd", version_for(layout.media_duration), 0, |b| {
+                b.times(layout.media_duration, track.timescale);
+                // Three 5-bit letters, each offset by 0x60.
+                b.u16(language.iter().fold(0, |packed, &c| packed << 5 | u16::from(c - 0x60)));
+                b.u16(0);
+            });
+            let (handler, name): (&[u8; 4], &[u8]) = match track.media {
+                Media::Video { .. } => (b"vide", b"VideoHandler\0"),
+                Media::Audio(_) => (b"soun", b"SoundHandler\0"),
+                Media::Text { .. } => (b"sbtl", b"SubtitleHandler\0"),
+            };
+            b.full_box(b"hdlr", 0, 0, |b| {
+                b.u32(0);
+                b.bytes(handler);
+                b.zeros(12);
+                b.bytes(name);
+            });
+            b.boxed(b"minf", |b| {
+                match track.media {
+                    Media::Video { .. } => b.full_box(b"vmhd", 0, 1, |b| b.zeros(8)),
+                    Media::Audio(_) => b.full_box(b"smhd", 0, 0, |b| b.zeros(4)),
+                    Media::Text { .. } => b.full_box(b"nmhd", 0, 0, |_| {}),
+                }
+                b.boxed(b"dinf", |b| {
+                    b.full_box(b"dref", 0, 0, |b| {
+                        b.u32(1);
+                        b.full_box(b"url ", 0, 1, |_| {});
+                    });
+                });
+                stbl(b, track, &layout.durations, mdat_start, large_offsets);
+            });
+        });
+    });
+}
+
+fn stbl(b: &mut Boxes, track: &Track, durations: &[u32], mdat_start: u64, large_offsets: bool) {
+    b.boxed(b"stbl", |b| {
+        b.full_box(b"stsd", 0, 0, |b| {
+            b.u32(1);
+            sample_entry(b, &track.media);
+        });
+
+        let stts = runs(durations.iter().copied());
+        b.full_box(b"stts", 0, 0, |b| {
+            b.u32(stts.len() as u32);
+            for (count, delta) in stts {
+                b.u32(count);
+                b.u32(delta);
+            }
+        });
+
+        if track.samples.iter().any(|s| s.composition_offset != 0) {
+            let ctts = runs(track.samples.iter().map(|s| s.composition_offset));
+            b.full_box(b"ctts", 0, 0, |b| {
+                b.u32(ctts.len() as u32);
+                for (count, offset) in ctts {
+                    b.u32(count);
+                    b.u32(offset);
+                }
+            });
+        }
+
+        // Without `stss` every sample counts as a sync sample.
+        let sync_samples: Vec<u32> = track.samples.iter()
+            .enumerate()
+            .filter(|(_, s)| s.sync)
+            .map(|(i, _)| i as u32 + 1)
+            .collect();
+        if !sync_samples.is_empty() && sync_samples.len() < track.samples.len() {
+            b.full_box(b"stss", 0, 0, |b| {
+                b.u32(sync_samples.len() as u32);
+                for number in sync_samples {
+                    b.u32(number);
+                }
+            });
+        }
+
+        // Every sample is its own chunk, so `stco` doubles as a sample offset table.
+        b.full_box(b"stsc", 0, 0, |b| {
+            b.u32(1);
+            b.u32(1); // first chunk
+            b.u32(1); // samples per chunk
+            b.u32(1); // sample description index
+        });
+        b.full_box(b"stsz", 0, 0, |b| {
+            b.u32(0);
+            b.u32(track.samples.len() as u32);
+            for sample in &track.samples {
+                b.u32(sample.size);
+            }
+        });
+        if large_offsets {
+            b.full_box(b"co64", 0, 0, |b| {
+                b.u32(track.samples.len() as u32);
+                for sample in &track.samples {
+                    b.u64(mdat_start + sample.offset);
+                }
+            });
+        } else {
+            b.full_box(b"stco", 0, 0, |b| {
+                b.u32(track.samples.len() as u32);
+                for sample in &track.samples {
+                    b.u32((mdat_start + sample.offset) as u32);
+                }
+            });
+        }
+    });
+}
+
+fn sample_entry(b: &mut Boxes, media: &Media) {
+    match media {
+        Media::Video { width, height, sps, pps } => b.boxed(b"avc1", |b| {
+            b.zeros(6);
+            b.u16(1); // data reference index
+            b.zeros(16);
+            b.u16(*width as u16);
+            b.u16(*height as u16);
+            b.u32(0x0048_0000); // 72 dpi
+            b.u32(0x0048_0000);
+            b.u32(0);
+            b.u16(1); // frame count
+            b.zeros(32); // compressor name
+            b.u16(0x0018); // depth
+            b.u16(0xffff);
+            b.boxed(b"avcC", |b| {
+                b.u8(1);
+                b.bytes(&sps[1..4]); // profile, compatibility, level
+                b.u8(0xff); // 4-byte NAL unit lengths
+                b.u8(0xe1); // one SPS
+                b.u16(sps.len() as u16);
+                b.bytes(sps);
+                b.u8(1);
+                b.u16(pps.len() as u16);
+                b.bytes(pps);
+            });
+        }),
+        Media::Audio(config) => b.boxed(b"mp4a", |b| {
+            b.zeros(6);
+            b.u16(1); // data reference index
+            b.zeros(8);
+            b.u16(u16::from(config.channels));
+            b.u16(16); // sample size
+            b.zeros(4);
+            b.u32(config.sample_rate() << 16);
+            b.full_box(b"esds", 0, 0, |b| {
+                let asc = config.audio_specific_config();
+                b.descriptor(0x03, |b| {
+                    b.u16(0); // ES_ID
+                    b.u8(0);
+                    b.descriptor(0x04, |b| {
+                        b.u8(0x40); // MPEG-4 audio
+                        b.u8(0x15); // audio stream
+                        b.zeros(3); // buffer size
+                        b.u32(0); // max bitrate
+                        b.u32(0); // average bitrate
+                        b.descriptor(0x05, |b| b.bytes(&asc));
+                    });
+                    b.descriptor(0x06, |b| b.u8(0x02));
+                });
+            });
+        }),
+        // 3GPP TS 26.245 text sample entry: white sans-serif text, centred at
+        // the bottom of the video, on a transparent background.
+        Media::Text { .. } => b.boxed(b"tx3g", |b| {
+            b.zeros(6);
+            b.u16(1); // data reference index
+            b.u32(0); // display flags
+            b.u8(1); // horizontal justification: centre
+            b.u8(0xff); // vertical justification: bottom
+            b.zeros(4); // background colour
+            b.zeros(8); // default text box
+            b.u16(0); // style record: first character
+            b.u16(0); // last character
+            b.u16(1); // font ID
+            b.u8(0); // face style
+            b.u8(18); // font size
+            b.bytes(&[0xff; 4]); // text colour
+            b.boxed(b"ftab", |b| {
+                b.u16(1);
+                b.u16(1); // font ID
+                b.u8(10);
+                b.bytes(b"Sans-Serif");
+            });
+        }),
+    }
+}
+
+/// Run-length encodes `values` as `(count, value)` pairs.
+fn runs(values: impl Iterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == value => *count += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+    runs
+}
+
+fn scale(value: u64, from: u32, to: u32) -> u64 {
+    (u128::from(value) * u128::from(to) / u128::from(from)) as u64
+}
+
+/// `mvhd`, `tkhd` and `mdhd` need version 1 once a duration outgrows 32 bits.
+fn version_for(duration: u64) -> u8 {
+    u8::from(duration > u64::from(u32::MAX))
+}
+
+/// Big-endian box serializer. Boxes are written with a placeholder size that
+/// is filled in when their closure returns.
+#[derive(Default)]
+struct Boxes(Vec<u8>);
+
+impl Boxes {
+    fn boxed(&mut self, kind: &[u8; 4], body: impl FnOnce(&mut Self)) {
+        let start = self.0.len();
+        self.u32(0);
+        self.bytes(kind);
+        body(self);
+        let size = (self.0.len() - start) as u32;
+        self.0[start..start + 4].copy_from_slice(&size.to_be_bytes());
+    }
+
+    fn full_box(&mut self, kind: &[u8; 4], version: u8, flags: u32, body: impl FnOnce(&mut Self)) {
+        self.boxed(kind, |b| {
+            b.u32(u32::from(version) << 24 | flags);
+            body(b);
+        });
+    }
+
+    /// An MPEG-4 descriptor with a four-byte size field (ISO/IEC 14496-1).
+    fn descriptor(&mut self, tag: u8, body: impl FnOnce(&mut Self)) {
+        self.u8(tag);
+        let start = self.0.len();
+        self.zeros(4);
+        body(self);
+        let size = (self.0.len() - start - 4) as u32;
+        for (i, byte) in self.0[start..start + 4].iter_mut().enumerate() {
+            let shift = 7 * (3 - i);
+            *byte = ((size >> shift) & 0x7f) as u8 | if i < 3 { 0x80 } else { 0 };
+        }
+    }
+
+    /// Creation and modification time (always zero), timescale and duration
+    /// as laid out in `mvhd` and `mdhd` of the version [`version_for`] picks.
+    fn times(&mut self, duration: u64, timescale: u32) {
+        if version_for(duration) == 1 {
+            self.zeros(16);
+            self.u32(timescale);
+            self.u64(duration);
+        } else {
+            self.zeros(8);
+            self.u32(timescale);
+            self.u32(duration as u32);
+        }
+    }
+
+    fn matrix(&mut self) {
+        for value in IDENTITY_MATRIX {
+            self.u32(value);
+        }
+    }
+
+    fn zeros(&mut self, count: usize) {
+        self.0.resize(self.0.len() + count, 0);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+}
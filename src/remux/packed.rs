@@ -0,0 +1,91 @@
+//! Packed audio (RFC 8216 section 3.4): raw ADTS frames, with an ID3 tag at
+//! the start of every segment carrying the segment's MPEG-TS timestamp.
+
+use std::io::{self, BufRead};
+
+use crate::error::{Error, Result};
+use super::ts::{Pes, StreamType};
+
+/// `PRIV` owner identifier of the timestamp frame.
+const TIMESTAMP_OWNER: &[u8] = b"com.apple.streaming.transportStreamTimestamp\0";
+
+/// Whether `start` looks like the beginning of a packed audio stream.
+pub(crate) fn is_packed_audio(start: &[u8]) -> bool {
+    start.starts_with(b"ID3") || (start.len() >= 2 && start[0] == 0xff && start[1] & 0xf6 == 0xf0)
+}
+
+/// Reads ADTS frames one at a time, each as a PES of its own.
+pub(super) struct PackedAudio<R> {
+    reader: R,
+    /// Timestamp from the last ID3 tag, given to the next frame.
+    pts: Option<u64>,
+}
+
+impl<R: BufRead> PackedAudio<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, pts: None }
+    }
+
+    /// Returns the next frame, or `None` at the end of the input.
+    pub fn next_frame(&mut self) -> Result<Option<Pes>> {
+        loop {
+            if self.reader.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+
+            let mut header = [0u8; 10];
+            self.read_exact(&mut header[..3])?;
+            if &header[..3] == b"ID3" {
+                self.read_exact(&mut header[3..])?;
+                let mut size = syncsafe(&header[6..10]) as usize;
+                if header[5] & 0x10 != 0 {
+                    size += 10; // footer
+                }
+                let mut tag = vec![0u8; size];
+                self.read_exact(&mut tag)?;
+                if let Some(pts) = transport_stream_timestamp(&tag) {
+                    self.pts = Some(pts);
+                }
+                continue;
+            }
+
+            self.read_exact(&mut header[3..7])?;
+            let frame_len = usize::from(header[3] & 0x03) << 11 | usize::from(header[4]) << 3 | usize::from(header[5] >> 5);
+            if header[0] != 0xff || header[1] & 0xf6 != 0xf0 || frame_len < 7 {
+                return Err(Error::remux("lost ADTS sync in the packed audio stream"));
+            }
+            let mut data = vec![0u8; frame_len];
+            data[..7].copy_from_slice(&header[..7]);
+            self.read_exact(&mut data[7..])?;
+
+            return Ok(Some(Pes { stream: StreamType::Aac, pts: self.pts.take(), dts: None, data }));
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::remux("packed audio stream ends mid-frame"),
+            _ => e.into(),
+        })
+    }
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, &b| value << 7 | u32::from(b & 0x7f))
+}
+
+/// Finds the 33-bit timestamp in the `PRIV` frame of an ID3v2 tag body.
+fn transport_stream_timestamp(mut frames: &[u8]) -> Option<u64> {
+    while frames.len() >= 10 && frames[0] != 0 {
+        // Frame sizes are syncsafe in ID3v2.4 and plain in v2.3; the two agree
+        // for frames this small.
+        let size = syncsafe(&frames[4..8]) as usize;
+        let body = frames.get(10..10 + size)?;
+        if &frames[..4] == b"PRIV" && body.starts_with(TIMESTAMP_OWNER) {
+            let timestamp: [u8; 8] = body[TIMESTAMP_OWNER.len()..].get(..8)?.try_into().ok()?;
+            return Some(u64::from_be_bytes(timestamp) & ((1 << 33) - 1));
+        }
+        frames = &frames[10 + size..];
+    }
+    None
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+use crate::error::{Error, Result};
+
+pub(super) const PACKET_SIZE: usize = 188;
+pub(super) const SYNC_BYTE: u8 = 0x47;
+
+/// PMT `stream_type` values for the codecs HLS uses.
+const STREAM_TYPE_AAC: u8 = 0x0f;
+const STREAM_TYPE_H264: u8 = 0x1b;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum StreamType {
+    H264,
+    Aac,
+}
+
+/// A reassembled PES packet. Timestamps are on the 90 kHz MPEG clock.
+#[derive(Debug)]
+pub(super) struct Pes {
+    pub stream: StreamType,
+    pub pts: Option<u64>,
+    pub dts: Option<u64>,
+    pub data: Vec<u8>,
+}
+
+/// Reads the next 188-byte packet into `packet`, skipping garbage until a
+/// sync byte. Returns `false` at the end of the input.
+pub(super) fn read_packet(reader: &mut impl Read, packet: &mut [u8; PACKET_SIZE]) -> io::Result<bool> {
+    let mut filled = 0;
+    loop {
+        filled += read_full(reader, &mut packet[filled..])?;
+        if filled < PACKET_SIZE {
+            // A trailing partial packet carries nothing we could use.
+            return Ok(false);
+        }
+        match packet.iter().position(|&b| b == SYNC_BYTE) {
+            Some(0) => return Ok(true),
+            Some(start) => {
+                packet.copy_within(start.., 0);
+                filled = PACKET_SIZE - start;
+            }
+            None => filled = 0,
+        }
+    }
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Follows the PAT and PMT to find the H.264 and AAC streams of the first
+/// program, and reassembles their PES packets.
+///
+/// HLS repeats the PAT and PMT at the start of every segment; the stream
+/// layout found first is kept for the whole file.
+#[derive(Default)]
+pub(super) struct Demuxer {
+    pmt_pid: Option<u16>,
+    streams: HashMap<u16, StreamType>,
+    /// Payload collected so far for the PES currently open on each PID.
+    pending: HashMap<u16, Vec<u8>>,
+}
+
+impl Demuxer {
+    /// Whether the PMT has been seen and lists at least one supported stream.
+    pub fn has_streams(&self) -> bool {
+        !self.streams.is_empty()
+    }
+
+    /// Whether the PMT has been seen and lists an H.264 stream.
+    pub fn has_video(&self) -> bool {
+        self.streams.values().any(|&stream| stream == StreamType::H264)
+    }
+
+    /// Consumes one packet. Returns the PES it completed, if any.
+    pub fn push(&mut self, packet: &[u8; PACKET_SIZE]) -> Result<Option<Pes>> {
+        let transport_error = packet[1] & 0x80 != 0;
+        let payload_start = packet[1] & 0x40 != 0;
+        let pid = u16::from(packet[1] & 0x1f) << 8 | u16::from(packet[2]);
+        let adaptation_field_control = (packet[3] >> 4) & 0x03;
+        if transport_error || adaptation_field_control & 0x01 == 0 {
+            return Ok(None);
+        }
+
+        let mut start = 4;
+        if adaptation_field_control & 0x02 != 0 {
+            start += 1 + usize::from(packet[4]);
+        }
+        let Some(payload) = packet.get(start..).filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+
+        if pid == 0 {
+            if payload_start && self.pmt_pid.is_none() {
+                self.pmt_pid = parse_pat(payload);
+            }
+            return Ok(None);
+        }
+        if Some(pid) == self.pmt_pid {
+            if payload_start && self.streams.is_empty() {
+                self.streams = parse_pmt(payload);
+            }
+            return Ok(None);
+        }
+
+        let Some(&stream) = self.streams.get(&pid) else {
+            return Ok(None);
+        };
+        if payload_start {
+            let completed = self.pending.insert(pid, payload.to_vec());
+            return completed.map(|data| parse_pes(stream, data)).transpose();
+        }
+        // Continuation packets before the first payload start belong to a PES
+        // we never saw the beginning of.
+        if let Some(buffer) = self.pending.get_mut(&pid) {
+            buffer.extend_from_slice(payload);
+        }
+        Ok(None)
+    }
+
+    /// Returns the PES packets still open at the end of the input.
+    pub fn finish(mut self) -> Result<Vec<Pes>> {
+        let mut pids: Vec<_> = self.pending.keys().copied().collect();
+        pids.sort_unstable();
+        pids.into_iter()
+            .filter_map(|pid| Some((self.streams[&pid], self.pending.remove(&pid)?)))
+            .map(|(stream, data)| parse_pes(stream, data))
+            .collect()
+    }
+}
+
+/// Returns the body of the PSI section starting in `payload`, without the
+/// trailing CRC. Sections spanning several packets are not supported; HLS
+/// segments never need them for the PAT and PMT.
+fn section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = usize::from(*payload.first()?);
+    let section = payload.get(1 + pointer..)?;
+    let length = usize::from(*section.get(1)? & 0x0f) << 8 | usize::from(*section.get(2)?);
+    section.get(3..3 + length.checked_sub(4)?)
+}
+
+/// PID of the first program's PMT.
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    let body = section(payload)?;
+    body.get(5..)?
+        .chunks_exact(4)
+        .find(|entry| entry[0] != 0 || entry[1] != 0)
+        .map(|entry| u16::from(entry[2] & 0x1f) << 8 | u16::from(entry[3]))
+}
+
+/// Supported elementary streams of the program, by PID.
+fn parse_pmt(payload: &[u8]) -> HashMap<u16, StreamType> {
+    let mut streams = HashMap::new();
+    let Some(body) = section(payload) else {
+        return streams;
+    };
+    let Some(info_length) = body.get(7..9).map(|b| usize::from(b[0] & 0x0f) << 8 | usize::from(b[1])) else {
+        return streams;
+    };
+
+    let mut entries = body.get(9 + info_length..).unwrap_or_default();
+    while entries.len() >= 5 {
+        let pid = u16::from(entries[1] & 0x1f) << 8 | u16::from(entries[2]);
+        let es_info_length = usize::from(entries[3] & 0x0f) << 8 | usize::from(entries[4]);
+        match entries[0] {
+            STREAM_TYPE_H264 if !streams.values().any(|s| *s == StreamType::H264) => {
+                streams.insert(pid, StreamType::H264);
+            }
+            STREAM_TYPE_AAC if !streams.values().any(|s| *s == StreamType::Aac) => {
+                streams.insert(pid, StreamType::Aac);
+            }
+            _ => {}
+        }
+        entries = entries.get(5 + es_info_length..).unwrap_or_default();
+    }
+    streams
+}
+
+fn parse_pes(stream: StreamType, mut data: Vec<u8>) -> Result<Pes> {
+    if data.len() < 9 || data[..3] != [0, 0, 1] {
+        return Err(Error::remux("PES packet without a start code"));
+    }
+    let flags = data[7];
+    let header_end = 9 + usize::from(data[8]);
+    if header_end > data.len() {
+        return Err(Error::remux("truncated PES header"));
+    }
+
+    let pts = (flags & 0x80 != 0).then(|| data.get(9..14).map(timestamp)).flatten();
+    let dts = (flags & 0xc0 == 0xc0).then(|| data.get(14..19).map(timestamp)).flatten();
+    data.drain(..header_end);
+    Ok(Pes { stream, pts, dts, data })
+}
+
+/// Decodes a 33-bit PTS or DTS from its 5-byte PES encoding.
+fn timestamp(bytes: &[u8]) -> u64 {
+    (u64::from(bytes[0] >> 1) & 0x07) << 30
+        | u64::from(bytes[1]) << 22
+        | u64::from(bytes[2] >> 1) << 15
+        | u64::from(bytes[3]) << 7
+        | u64::from(bytes[4] >> 1)
+}
@@ -0,0 +1,225 @@
+//! Choosing a variant stream from a master playlist.
+//!
+//! A [`VariantSelector`] narrows the variants down with hard limits
+//! (resolution, height, bandwidth), prefers a codec if asked to, and then
+//! takes the best or worst of what is left. It parses from the string given
+//! to `--quality`, a comma-separated list of terms:
+//!
+//! | Term             | Meaning                                     |
+//! |------------------|---------------------------------------------|
+//! | `best`, `worst`  | highest or lowest quality (default `best`)  |
+//! | `<=720`          | height of at most 720 lines                 |
+//! | `720p`           | height of exactly 720 lines                 |
+//! | `1280x720`       | exactly this resolution                     |
+//! | `bw<=3M`         | `BANDWIDTH` of at most 3 Mbit/s (`k`, `M`)  |
+//! | `codec=avc1`     | prefer variants whose `CODECS` start so     |
+//!
+//! For example `<=720,codec=avc1` picks the best H.264 variant no taller than 720p.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::SubtitleSelection;
+use crate::m3u8::{MediaType, Resolution};
+use crate::playlist::{Rendition, Variant};
+
+/// Rules for picking one variant out of a master playlist.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VariantSelector {
+    /// Take the lowest quality match instead of the highest.
+    pub worst: bool,
+    /// Upper limit on the frame height.
+    pub max_height: Option<u32>,
+    /// Required frame height.
+    pub height: Option<u32>,
+    /// Required frame size.
+    pub resolution: Option<Resolution>,
+    /// Upper limit on `BANDWIDTH`, in bits per second.
+    pub max_bandwidth: Option<u64>,
+    /// Preferred codec, matched against the start of each entry in `CODECS`.
+    /// Variants with other codecs are only used if none match.
+    pub codec: Option<String>,
+}
+
+impl VariantSelector {
+    /// Highest resolution, then highest bandwidth.
+    pub fn best() -> Self {
+        Self::default()
+    }
+
+    /// Lowest resolution, then lowest bandwidth.
+    pub fn worst() -> Self {
+        Self { worst: true, ..Self::default() }
+    }
+
+    /// Picks a variant, or `None` if none satisfies the limits.
+    ///
+    /// Variants that do not advertise a resolution pass the height limit but
+    /// never match an exact height or resolution.
+    pub fn select<'a>(&self, variants: &'a [Variant]) -> Option<&'a Variant> {
+        self.position(variants).map(|i| &variants[i])
+    }
+
+    /// Like [`select`](Self::select), but returns the variant's index.
+    pub fn position(&self, variants: &[Variant]) -> Option<usize> {
+        let allowed: Vec<(usize, &Variant)> = variants.iter().enumerate().filter(|(_, v)| self.allows(v)).collect();
+
+        let preferred: Vec<(usize, &Variant)> = match &self.codec {
+            Some(codec) => allowed.iter().copied().filter(|(_, v)| has_codec(v, codec)).collect(),
+            None => Vec::new(),
+        };
+        let candidates = if preferred.is_empty() { allowed } else { preferred };
+
+        // Rank by pixel count first: BANDWIDTH alone favours high frame rates
+        // and audio-heavy variants over sharper ones.
+        let rank = |(_, v): &(usize, &Variant)| (v.resolution.map_or(0, |r| u64::from(r.width) * u64::from(r.height)), v.bandwidth);
+        let chosen = if self.worst {
+            candidates.into_iter().min_by_key(rank)
+        } else {
+            candidates.into_iter().max_by_key(rank)
+        };
+        chosen.map(|(i, _)| i)
+    }
+
+    fn allows(&self, variant: &Variant) -> bool {
+        let height = variant.resolution.map(|r| r.height);
+        self.max_height.is_none_or(|max| height.is_none_or(|h| h <= max))
+            && self.height.is_none_or(|wanted| height == Some(wanted))
+            && self.resolution.is_none_or(|wanted| variant.resolution == Some(wanted))
+            && self.max_bandwidth.is_none_or(|max| variant.bandwidth <= max)
+    }
+}
+
+fn has_codec(variant: &Variant, codec: &str) -> bool {
+    variant.codecs.as_deref().is_some_and(|codecs| {
+        codecs.split(',').any(|c| c.trim().to_ascii_lowercase().starts_with(&codec.to_ascii_lowercase()))
+    })
+}
+
+/// Picks the rendition of `media_type` in group `group_id` to use.
+///
+/// A rendition in `language` wins, matched exactly and then by primary
+/// subtag (`pt` matches `pt-BR`). Otherwise, or when no rendition is in that
+/// language, the group's `DEFAULT=YES` rendition is used, then its first one.
+pub fn select_rendition<'a>(
+    renditions: &'a [Rendition],
+    media_type: MediaType,
+    group_id: &str,
+    language: Option<&str>,
+) -> Option<&'a Rendition> {
+    let group: Vec<&Rendition> = renditions.iter()
+        .filter(|r| r.media_type == media_type && r.group_id == group_id)
+        .collect();
+    let in_language = |matches: &dyn Fn(&str) -> bool| group.iter().copied()
+        .find(|r| r.language.as_deref().is_some_and(matches));
+
+    language
+        .and_then(|wanted| {
+            in_language(&|tag| tag.eq_ignore_ascii_case(wanted))
+                .or_else(|| in_language(&|tag| primary_language(tag) == primary_language(wanted)))
+        })
+        .or_else(|| group.iter().copied().find(|r| r.default))
+        .or_else(|| group.first().copied())
+}
+
+/// Picks the subtitle renditions in group `group_id` that `selection` asks for.
+///
+/// Languages are matched exactly and then by primary subtag. Unlike audio
+/// there is no fallback: a language that is not offered is simply skipped. Renditions without a URI cannot be downloaded and are left out.
+pub fn select_subtitles<'a>(renditions: &'a [Rendition], group_id: &str, selection: &SubtitleSelection) -> Vec<&'a Rendition> {
+    let group: Vec<&Rendition> = renditions.iter()
+        .filter(|r| r.media_type == MediaType::Subtitles && r.group_id == group_id && r.url.is_some())
+        .collect();
+    let exact = |tag: &str, wanted: &str| tag.eq_ignore_ascii_case(wanted);
+    // Like `select_rendition`, a language only falls back to its primary
+    // subtag when no rendition has the exact tag, so `pt-BR` skips `pt-PT`.
+    let wants = |tag: &str, wanted: &str| {
+        exact(tag, wanted)
+            || (primary_language(tag) == primary_language(wanted)
+                && !group.iter().any(|r| r.language.as_deref().is_some_and(|other| exact(other, wanted))))
+    };
+
+    group.iter().copied()
+        .filter(|r| match selection {
+            SubtitleSelection::None => false,
+            SubtitleSelection::All => true,
+            SubtitleSelection::Languages(languages) => r.language.as_deref()
+                .is_some_and(|tag| languages.iter().any(|wanted| wants(tag, wanted))),
+        })
+        .collect()
+}
+
+/// `pt` for `pt-BR`, lowercased.
+fn primary_language(tag: &str) -> String {
+    tag.split('-').next().unwrap_or_default().to_ascii_lowercase()
+}
+
+impl FromStr for VariantSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut selector = Self::default();
+        for term in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let lower = term.to_ascii_lowercase();
+            if lower == "best" {
+                selector.worst = false;
+            } else if lower == "worst" {
+                selector.worst = true;
+            } else if let Some(bandwidth) = lower.strip_prefix("bw<=").or_else(|| lower.strip_prefix("bandwidth<=")) {
+                selector.max_bandwidth = Some(parse_bitrate(bandwidth).ok_or_else(|| invalid(term))?);
+            } else if let Some(height) = lower.strip_prefix("<=") {
+                selector.max_height = Some(parse_height(height).ok_or_else(|| invalid(term))?);
+            } else if let Some(codec) = lower.strip_prefix("codec=") {
+                selector.codec = Some(codec.to_string()).filter(|c| !c.is_empty());
+            } else if lower.contains('x') {
+                selector.resolution = Some(Resolution::parse(&lower).map_err(|_| invalid(term))?);
+            } else if lower.ends_with('p') {
+                selector.height = Some(parse_height(&lower).ok_or_else(|| invalid(term))?);
+            } else {
+                return Err(invalid(term));
+            }
+        }
+        Ok(selector)
+    }
+}
+
+impl fmt::Display for VariantSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.worst { "worst" } else { "best" })?;
+        if let Some(max) = self.max_height {
+            write!(f, ",<={}", max)?;
+        }
+        if let Some(height) = self.height {
+            write!(f, ",{}p", height)?;
+        }
+        if let Some(resolution) = self.resolution {
+            write!(f, ",{}", resolution)?;
+        }
+        if let Some(max) = self.max_bandwidth {
+            write!(f, ",bw<={}", max)?;
+        }
+        if let Some(codec) = &self.codec {
+            write!(f, ",codec={}", codec)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(term: &str) -> String {
+    format!("invalid quality {:?}; expected best, worst, <=720, 720p, 1280x720, bw<=3M or codec=avc1", term)
+}
+
+fn parse_height(value: &str) -> Option<u32> {
+    value.strip_suffix('p').unwrap_or(value).parse().ok()
+}
+
+/// Parses a bit rate such as `2500000`, `2500k` or `2.5M`.
+fn parse_bitrate(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.as_bytes().last()? {
+        b'k' => (&value[..value.len() - 1], 1e3),
+        b'm' => (&value[..value.len() - 1], 1e6),
+        _ => (value, 1.0),
+    };
+    let bits = number.parse::<f64>().ok()? * multiplier;
+    (bits.is_finite() && bits >= 0.0).then_some(bits as u64)
+}
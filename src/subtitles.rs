@@ -0,0 +1,266 @@
+//! WebVTT subtitle renditions.
+//!
+//! HLS splits subtitles into short WebVTT segments whose cue times are local
+//! to each file. An `X-TIMESTAMP-MAP=MPEGTS:<ticks>,LOCAL:<time>` header ties
+//! a local time to the 90 kHz clock of the media segments, so [`stitch`] can
+//! put every cue on one timeline relative to the start of the video. The
+//! result is written out as WebVTT or SubRip, or embedded into the MP4 by the
+//! remuxer.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+/// The 90 kHz clock MPEG-TS timestamps are expressed in, per millisecond.
+const TICKS_PER_MS: i64 = 90;
+
+/// MPEG-TS timestamps are 33-bit counters.
+const TIMESTAMP_WRAP: i64 = 1 << 33;
+
+/// One subtitle cue on the video's timeline.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    /// WebVTT cue settings such as `line:90% align:center`, possibly empty.
+    pub settings: String,
+    /// Cue payload, which may contain WebVTT markup such as `<i>`.
+    pub text: String,
+}
+
+/// A stitched subtitle track, ready to be embedded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleTrack {
+    /// RFC 5646 language tag from the rendition, e.g. `pt-BR`.
+    pub language: Option<String>,
+    pub cues: Vec<Cue>,
+}
+
+/// Joins the WebVTT `segments` of one rendition into a single list of cues.
+///
+/// `start` is the MPEG-TS timestamp at which the video starts; cue times are
+/// made relative to it. When it is unknown, the first segment's
+/// `X-TIMESTAMP-MAP` is taken as the start instead, which is where packagers
+/// put it. Segments without a map are assumed to be relative already.
+///
+/// Cues that span a segment boundary are repeated in both segments by most
+/// packagers; only the first copy is kept.
+pub fn stitch<S: AsRef<str>>(segments: &[S], start: Option<u64>) -> Result<Vec<Cue>> {
+    let mut reference = start.map(wrapped);
+    let mut seen = HashSet::new();
+    let mut cues = Vec::new();
+
+    for (index, segment) in segments.iter().enumerate() {
+        let file = parse(segment.as_ref())
+            .map_err(|reason| Error::Subtitles { reason: format!("segment {}: {}", index + 1, reason) })?;
+
+        let out_of_range = || Error::Subtitles { reason: format!("segment {}: timestamps out of range", index + 1) };
+        // Ticks to add to a local time, in milliseconds, to get the relative time.
+        let shift = match file.timestamp_map {
+            Some((mpegts, local)) => {
+                let origin = ticks(local).and_then(|local| wrapped(mpegts).checked_sub(local))
+                    .ok_or_else(out_of_range)?;
+                let reference = *reference.get_or_insert(origin);
+                unwrap_timestamp(origin.checked_sub(reference).ok_or_else(out_of_range)?)
+            }
+            None => 0,
+        };
+
+        for mut cue in file.cues {
+            let start = ticks(cue.start_ms).and_then(|t| t.checked_add(shift)).ok_or_else(out_of_range)?;
+            let end = ticks(cue.end_ms).and_then(|t| t.checked_add(shift)).ok_or_else(out_of_range)?;
+            if end <= 0 || end <= start {
+                continue;
+            }
+            let cue = Cue {
+                start: ticks_to_duration(start.max(0)),
+                end: ticks_to_duration(end),
+                settings: std::mem::take(&mut cue.settings),
+                text: std::mem::take(&mut cue.text),
+            };
+            if seen.insert(cue.clone()) {
+                cues.push(cue);
+            }
+        }
+    }
+
+    cues.sort_by_key(|cue| (cue.start, cue.end));
+    Ok(cues)
+}
+
+/// Serializes `cues` as a WebVTT file.
+pub fn to_webvtt(cues: &[Cue]) -> String {
+    let mut out = String::from("WEBVTT\n");
+    for cue in cues {
+        let _ = write!(out, "\n{} --> {}", timestamp(cue.start, '.'), timestamp(cue.end, '.'));
+        if !cue.settings.is_empty() {
+            let _ = write!(out, " {}", cue.settings);
+        }
+        let _ = writeln!(out, "\n{}", cue.text);
+    }
+    out
+}
+
+/// Serializes `cues` as a SubRip (`.srt`) file. Italic, bold and underline
+/// markup is kept; other WebVTT markup is removed.
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (index, cue) in cues.iter().enumerate() {
+        let _ = writeln!(out, "{}\n{} --> {}", index + 1, timestamp(cue.start, ','), timestamp(cue.end, ','));
+        let _ = writeln!(out, "{}\n", strip_markup(&cue.text, &["i", "b", "u"]));
+    }
+    out
+}
+
+/// The text of a cue without any markup, as `mov_text` samples carry it.
+pub fn plain_text(cue: &Cue) -> String {
+    strip_markup(&cue.text, &[])
+}
+
+/// A cue as read from one segment, with local times in milliseconds.
+struct LocalCue {
+    start_ms: i64,
+    end_ms: i64,
+    settings: String,
+    text: String,
+}
+
+struct WebVttFile {
+    /// `(MPEGTS, LOCAL in milliseconds)` from `X-TIMESTAMP-MAP`.
+    timestamp_map: Option<(u64, i64)>,
+    cues: Vec<LocalCue>,
+}
+
+fn parse(input: &str) -> std::result::Result<WebVttFile, String> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let lines: Vec<&str> = input.lines().map(|line| line.trim_end_matches('\r')).collect();
+    let mut blocks = lines.split(|line| line.trim().is_empty()).filter(|block| !block.is_empty());
+
+    let header = blocks.next().ok_or("empty file")?;
+    if !header[0].starts_with("WEBVTT") {
+        return Err("missing WEBVTT header".to_string());
+    }
+    let timestamp_map = header.iter()
+        .find_map(|line| line.strip_prefix("X-TIMESTAMP-MAP="))
+        .map(parse_timestamp_map)
+        .transpose()?;
+
+    let mut cues = Vec::new();
+    for block in blocks {
+        // The timing line is the first line, or the second after a cue identifier.
+        let Some(timing_index) = block.iter().take(2).position(|line| line.contains("-->")) else {
+            continue; // NOTE, STYLE and REGION blocks
+        };
+        let (times, settings) = block[timing_index].split_once("-->").expect("found above");
+        let settings = settings.trim_start();
+        let (end, settings) = settings.split_once(char::is_whitespace).unwrap_or((settings, ""));
+        cues.push(LocalCue {
+            start_ms: parse_time(times.trim())?,
+            end_ms: parse_time(end)?,
+            settings: settings.trim().to_string(),
+            text: block[timing_index + 1..].join("\n"),
+        });
+    }
+
+    Ok(WebVttFile { timestamp_map, cues })
+}
+
+/// Parses `MPEGTS:900000,LOCAL:00:00:00.000`, in either order.
+fn parse_timestamp_map(value: &str) -> std::result::Result<(u64, i64), String> {
+    let mut mpegts = None;
+    let mut local = None;
+    for part in value.split(',') {
+        match part.trim().split_once(':') {
+            Some(("MPEGTS", ticks)) => mpegts = ticks.parse().ok(),
+            Some(("LOCAL", time)) => local = parse_time(time).ok(),
+            _ => {}
+        }
+    }
+    match (mpegts, local) {
+        (Some(mpegts), Some(local)) => Ok((mpegts, local)),
+        _ => Err(format!("invalid X-TIMESTAMP-MAP {:?}", value)),
+    }
+}
+
+/// Parses `[hh:]mm:ss.ttt` into milliseconds.
+fn parse_time(value: &str) -> std::result::Result<i64, String> {
+    let invalid = || format!("invalid timestamp {:?}", value);
+    let (clock, millis) = value.split_once('.').ok_or_else(invalid)?;
+    let mut fields = clock.rsplit(':');
+    let seconds: i64 = fields.next().and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+    let minutes: i64 = fields.next().and_then(|m| m.parse().ok()).ok_or_else(invalid)?;
+    let hours: i64 = match fields.next() {
+        Some(hours) => hours.parse().map_err(|_| invalid())?,
+        None => 0,
+    };
+    if millis.len() != 3 || fields.next().is_some() || minutes > 59 || seconds > 59 {
+        return Err(invalid());
+    }
+    let millis: i64 = millis.parse().map_err(|_| invalid())?;
+    hours.checked_mul(60)
+        .and_then(|m| m.checked_add(minutes)?.checked_mul(60)?.checked_add(seconds)?.checked_mul(1000)?.checked_add(millis))
+        .ok_or_else(invalid)
+}
+
+/// A 33-bit timestamp given in more bits, which only add whole wraps.
+fn wrapped(ticks: u64) -> i64 {
+    (ticks % TIMESTAMP_WRAP as u64) as i64
+}
+
+/// Milliseconds on the 90 kHz clock, or `None` if that overflows.
+fn ticks(millis: i64) -> Option<i64> {
+    millis.checked_mul(TICKS_PER_MS)
+}
+
+/// Maps a difference of two 33-bit timestamps onto the shorter way round.
+fn unwrap_timestamp(ticks: i64) -> i64 {
+    let ticks = ticks.rem_euclid(TIMESTAMP_WRAP);
+    if ticks >= TIMESTAMP_WRAP / 2 { ticks - TIMESTAMP_WRAP } else { ticks }
+}
+
+fn ticks_to_duration(ticks: i64) -> Duration {
+    Duration::from_millis((ticks / TICKS_PER_MS) as u64)
+}
+
+/// Formats `hh:mm:ss<separator>ttt`.
+fn timestamp(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000,
+    )
+}
+
+/// Removes WebVTT tags other than those in `keep`, and decodes the character
+/// references WebVTT requires for `&`, `<` and `>`.
+fn strip_markup(text: &str, keep: &[&str]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('>') else {
+            rest = &rest[open..];
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        // `<c.yellow>` and `<v Speaker>` carry classes and annotations; only the
+        // name decides whether the tag is kept.
+        let name = tag.trim_start_matches('/').split(['.', ' ']).next().unwrap_or_default();
+        if keep.contains(&name) {
+            out.push('<');
+            out.push_str(if tag.starts_with('/') { "/" } else { "" });
+            out.push_str(name);
+            out.push('>');
+        }
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+
+    out.replace("&lt;", "<").replace("&gt;", ">").replace("&nbsp;", "\u{a0}").replace("&amp;", "&")
+}
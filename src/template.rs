@@ -0,0 +1,246 @@
+//! Output path templates.
+//!
+//! A template such as `{course}/{module:02}-{lesson:02} {title}.{ext}` names
+//! the output file after the video. Fields are written as `{name}`, or
+//! `{name:0N}` to pad numbers with zeros to `N` digits; `{{` and `}}` stand for
+//! literal braces. Field values are sanitized so that they form a single valid
+//! file name on Windows and Linux; the literal text of the template, including
+//! its `/` separators, is kept as written.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::metadata::VideoMetadata;
+
+/// Text substituted for fields the video has no value for.
+const MISSING: &str = "NA";
+
+/// Longest file name, in bytes, a field value is cut down to.
+const MAX_NAME_LEN: usize = 200;
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A field a template can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Title,
+    Course,
+    Module,
+    Lesson,
+    Producer,
+    Date,
+    Ext,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 8] = [
+        ("id", Field::Id),
+        ("title", Field::Title),
+        ("course", Field::Course),
+        ("module", Field::Module),
+        ("lesson", Field::Lesson),
+        ("producer", Field::Producer),
+        ("date", Field::Date),
+        ("ext", Field::Ext),
+    ];
+
+    fn name(self) -> &'static str {
+        Self::ALL.iter().find(|(_, field)| *field == self).map(|(name, _)| *name).expect("every field is listed")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+/// An output path with fields filled in from the video's metadata.
+///
+/// Parses from the template syntax described in the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+impl OutputTemplate {
+    /// A template that always yields `path`, braces included.
+    pub fn literal(path: impl AsRef<Path>) -> Self {
+        Self { parts: vec![Part::Literal(path.as_ref().to_string_lossy().into_owned())] }
+    }
+
+    /// The same template inside `dir`, unless it yields absolute paths.
+    pub fn under(mut self, dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_string_lossy();
+        let absolute = matches!(self.parts.first(), Some(Part::Literal(text)) if Path::new(text).has_root());
+        if !dir.is_empty() && !absolute {
+            let separator = if dir.ends_with(['/', '\\']) { "" } else { "/" };
+            self.parts.insert(0, Part::Literal(format!("{}{}", dir, separator)));
+        }
+        self
+    }
+
+    /// Whether the template names the extension itself, with `{ext}` or a
+    /// literal one at the end. Dots in field values do not count.
+    fn has_extension(&self) -> bool {
+        let literal_extension = match self.parts.last() {
+            Some(Part::Literal(text)) => Path::new(text).extension().is_some() && !text.ends_with(['/', '\\']),
+            _ => false,
+        };
+        literal_extension || self.parts.iter().any(|part| matches!(part, Part::Field { field: Field::Ext, .. }))
+    }
+
+    /// The path for the video described by `metadata`, saved with `extension`.
+    /// A template that neither uses `{ext}` nor ends in an extension gets one appended.
+    pub fn render(&self, metadata: &VideoMetadata, extension: &str) -> PathBuf {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field { field, width } => {
+                    let value = match field {
+                        Field::Id => Some(metadata.video_id.clone()),
+                        Field::Title => metadata.title.clone(),
+                        Field::Course => metadata.course.clone(),
+                        Field::Module => metadata.module.map(|n| n.to_string()),
+                        Field::Lesson => metadata.lesson.map(|n| n.to_string()),
+                        Field::Producer => metadata.producer.clone(),
+                        // The day part of ISO 8601 timestamps
+                        Field::Date => metadata.upload_date.as_ref().map(|date| date.chars().take(10).collect()),
+                        Field::Ext => Some(extension.to_string()),
+                    };
+                    let value = sanitize(value.as_deref().unwrap_or(MISSING));
+                    out.push_str(&format!("{:0>width$}", value, width = width));
+                }
+            }
+        }
+
+        if !self.has_extension() {
+            out.push('.');
+            out.push_str(extension);
+        }
+        PathBuf::from(out)
+    }
+}
+
+/// `{id}.{ext}`, the video ID with the extension of the output format.
+impl Default for OutputTemplate {
+    fn default() -> Self {
+        Self { parts: vec![Part::Field { field: Field::Id, width: 0 }, Part::Literal(".".to_string()), Part::Field { field: Field::Ext, width: 0 }] }
+    }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(format!("unmatched }} in {:?}; write }}}} for a literal brace", s)),
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(format!("unclosed {{ in {:?}", s)),
+                        }
+                    }
+                    let (name, format) = spec.split_once(':').unwrap_or((&spec, ""));
+                    let field = Field::ALL.iter()
+                        .find(|(known, _)| *known == name.trim())
+                        .map(|(_, field)| *field)
+                        .ok_or_else(|| format!(
+                            "unknown field {{{}}}; expected one of {}",
+                            name,
+                            Field::ALL.iter().map(|(name, _)| format!("{{{}}}", name)).collect::<Vec<_>>().join(", "),
+                        ))?;
+                    let width = match format {
+                        "" => 0,
+                        format => format.strip_prefix('0')
+                            .and_then(|width| width.parse().ok())
+                            .ok_or_else(|| format!("invalid format {:?} for {{{}}}; expected e.g. 02", format, name))?,
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field { field, width });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        if parts.is_empty() {
+            return Err("empty output template".to_string());
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl fmt::Display for OutputTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => write!(f, "{}", text.replace('{', "{{").replace('}', "}}"))?,
+                Part::Field { field, width: 0 } => write!(f, "{{{}}}", field.name())?,
+                Part::Field { field, width } => write!(f, "{{{}:0{}}}", field.name(), width)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Makes `name` usable as a single file name on Windows and Linux.
+///
+/// Path separators, characters Windows reserves and control characters become
+/// `_`, runs of whitespace (line breaks included) collapse into one space, trailing dots and spaces
+/// (which Windows drops) are removed, and device names such as `CON` get a
+/// `_` prepended. Long names are cut to 200 bytes.
+pub fn sanitize(name: &str) -> String {
+    let replaced: String = name.chars()
+        .map(|c| match c {
+            c if c.is_whitespace() => ' ',
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut name = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if name.len() > MAX_NAME_LEN {
+        let mut end = MAX_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+    let name = name.trim_end_matches(['.', ' ']);
+
+    let stem = name.split('.').next().unwrap_or_default();
+    if name.is_empty() {
+        "_".to_string()
+    } else if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        format!("_{}", name)
+    } else {
+        name.to_string()
+    }
+}
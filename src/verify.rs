@@ -0,0 +1,244 @@
+//! Structural checks of finished downloads.
+//!
+//! [`verify`] reads an MP4 or MPEG-TS file the way a player would find its
+//! way around it, without decoding any media: every box must fit inside the
+//! file, and every sample the MP4 sample tables point at must lie inside an
+//! `mdat` box. A file that passes is complete, though not necessarily free of
+//! corrupt frames.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+const TS_PACKET_SIZE: u64 = 188;
+
+/// What [`verify`] found in a file that passed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verified {
+    /// An MP4 file. `fragments` counts `moof` boxes, which hold the samples of
+    /// fragmented files instead of the tracks' sample tables.
+    Mp4 { tracks: Vec<TrackSummary>, fragments: usize },
+    /// An MPEG-TS file made of `packets` whole packets.
+    Ts { packets: u64 },
+}
+
+/// One track of a verified MP4 file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackSummary {
+    /// Handler type, e.g. `vide`, `soun` or `sbtl`.
+    pub handler: String,
+    pub samples: u32,
+    /// Duration in seconds, from the track's media header.
+    pub duration: f64,
+}
+
+/// Checks that the file at `path` is a complete MP4 or MPEG-TS file.
+pub fn verify(path: &Path) -> Result<Verified> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = [0u8; 8];
+    let read = file.read(&mut head)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if read >= 1 && head[0] == 0x47 {
+        verify_ts(file, len)
+    } else if read == 8 && &head[4..8] == b"ftyp" {
+        verify_mp4(file, len)
+    } else {
+        Err(check_failed("not an MP4 or MPEG-TS file"))
+    }
+}
+
+fn check_failed(reason: impl Into<String>) -> Error {
+    Error::OutputCheck { reason: reason.into() }
+}
+
+fn verify_ts(file: File, len: u64) -> Result<Verified> {
+    if !len.is_multiple_of(TS_PACKET_SIZE) {
+        return Err(check_failed(format!("{} bytes is not a whole number of MPEG-TS packets", len)));
+    }
+    let mut reader = BufReader::with_capacity(TS_PACKET_SIZE as usize * 1024, file);
+    let mut packet = [0u8; TS_PACKET_SIZE as usize];
+    for index in 0..len / TS_PACKET_SIZE {
+        reader.read_exact(&mut packet)?;
+        if packet[0] != 0x47 {
+            return Err(check_failed(format!("MPEG-TS packet {} has no sync byte", index)));
+        }
+    }
+    Ok(Verified::Ts { packets: len / TS_PACKET_SIZE })
+}
+
+fn verify_mp4(mut file: File, len: u64) -> Result<Verified> {
+    let mut moov = None;
+    let mut mdats: Vec<Range<u64>> = Vec::new();
+    let mut fragments = 0;
+
+    // Top-level boxes are visited by seeking, so `mdat` is never read.
+    let mut offset = 0;
+    while offset < len {
+        let mut header = [0u8; 16];
+        let available = (len - offset).min(16) as usize;
+        if available < 8 {
+            return Err(check_failed(format!("truncated box header at byte {}", offset)));
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header[..available])?;
+
+        let kind: [u8; 4] = header[4..8].try_into().expect("4 bytes");
+        let (header_len, size) = match u32::from_be_bytes(header[..4].try_into().expect("4 bytes")) {
+            0 => (8, len - offset),
+            1 if available == 16 => (16, u64::from_be_bytes(header[8..16].try_into().expect("8 bytes"))),
+            1 => return Err(check_failed(format!("truncated box header at byte {}", offset))),
+            size => (8, u64::from(size)),
+        };
+        if size < header_len || size > len - offset {
+            return Err(check_failed(format!(
+                "{} box at byte {} claims {} bytes but only {} remain",
+                String::from_utf8_lossy(&kind), offset, size, len - offset,
+            )));
+        }
+
+        match &kind {
+            b"moov" => {
+                let mut body = vec![0u8; (size - header_len) as usize];
+                file.seek(SeekFrom::Start(offset + header_len))?;
+                file.read_exact(&mut body)?;
+                moov = Some(body);
+            }
+            b"mdat" => mdats.push(offset + header_len..offset + size),
+            b"moof" => fragments += 1,
+            _ => {}
+        }
+        offset += size;
+    }
+
+    let moov = moov.ok_or_else(|| check_failed("no moov box"))?;
+    let mut tracks = Vec::new();
+    for trak in children(&moov)?.into_iter().filter(|(kind, _)| kind == b"trak").map(|(_, body)| body) {
+        tracks.push(verify_track(trak, &mdats)?);
+    }
+    if tracks.is_empty() {
+        return Err(check_failed("no tracks"));
+    }
+    if fragments == 0 && tracks.iter().all(|track| track.samples == 0) {
+        return Err(check_failed("no samples"));
+    }
+    Ok(Verified::Mp4 { tracks, fragments })
+}
+
+/// Checks that every chunk of the track lies inside one of `mdats`.
+fn verify_track(trak: &[u8], mdats: &[Range<u64>]) -> Result<TrackSummary> {
+    let mdia = child(trak, b"mdia")?;
+    let hdlr = child(mdia, b"hdlr")?;
+    let handler = String::from_utf8_lossy(hdlr.get(8..12).ok_or_else(|| check_failed("truncated hdlr box"))?).into_owned();
+
+    let mdhd = child(mdia, b"mdhd")?;
+    let (timescale, duration) = match mdhd.first() {
+        Some(1) => (u32_at(mdhd, 20)?, u64_at(mdhd, 24)?),
+        _ => (u32_at(mdhd, 12)?, u64::from(u32_at(mdhd, 16)?)),
+    };
+
+    let stbl = child(child(mdia, b"minf")?, b"stbl")?;
+    let media_len = mdats.iter().map(|mdat| mdat.end - mdat.start).sum();
+    let sizes = sample_sizes(child(stbl, b"stsz")?, media_len)?;
+    let offsets = match child(stbl, b"stco") {
+        Ok(stco) => entries(stco, 4, |data, at| u32_at(data, at).map(u64::from))?,
+        Err(_) => entries(child(stbl, b"co64")?, 8, u64_at)?,
+    };
+
+    // `stsc` runs give the number of samples in each chunk from their first chunk on.
+    let stsc = entries(child(stbl, b"stsc")?, 12, |data, at| Ok((u32_at(data, at)?, u32_at(data, at + 4)?)))?;
+    let mut samples = sizes.iter();
+    for (index, offset) in offsets.iter().enumerate() {
+        let chunk = index as u32 + 1;
+        let per_chunk = stsc.iter().rev().find(|(first, _)| *first <= chunk).map_or(0, |(_, count)| *count);
+        let chunk_len: u64 = samples.by_ref().take(per_chunk as usize).map(|&size| u64::from(size)).sum();
+        let end = offset.checked_add(chunk_len).ok_or_else(|| check_failed("chunk offset overflows"))?;
+        if !mdats.iter().any(|mdat| mdat.start <= *offset && end <= mdat.end) {
+            return Err(check_failed(format!(
+                "{} track chunk {} at bytes {}..{} lies outside the media data",
+                handler, chunk, offset, end,
+            )));
+        }
+    }
+    if samples.next().is_some() {
+        return Err(check_failed(format!("{} track has more samples than its chunks hold", handler)));
+    }
+
+    Ok(TrackSummary {
+        handler,
+        samples: sizes.len() as u32,
+        duration: if timescale == 0 { 0.0 } else { duration as f64 / f64::from(timescale) },
+    })
+}
+
+/// Sample sizes from `stsz`. Counts are checked before anything is allocated
+/// for them: samples of one fixed size must fit in the `media_len` bytes of
+/// media data, and a table of sizes in the box itself.
+fn sample_sizes(stsz: &[u8], media_len: u64) -> Result<Vec<u32>> {
+    let fixed = u32_at(stsz, 4)?;
+    let count = u32_at(stsz, 8)?;
+    if fixed != 0 {
+        if u64::from(count) * u64::from(fixed) > media_len {
+            return Err(check_failed(format!(
+                "{} samples of {} bytes do not fit in {} bytes of media data",
+                count, fixed, media_len,
+            )));
+        }
+        return Ok(vec![fixed; count as usize]);
+    }
+    let count = checked_count(stsz, 12, count, 4)?;
+    (0..count).map(|i| u32_at(stsz, 12 + 4 * i)).collect()
+}
+
+/// Entries of a full box that starts with an entry count.
+fn entries<T>(full_box: &[u8], entry_len: usize, read: impl Fn(&[u8], usize) -> Result<T>) -> Result<Vec<T>> {
+    let count = checked_count(full_box, 8, u32_at(full_box, 4)?, entry_len)?;
+    (0..count).map(|i| read(full_box, 8 + entry_len * i)).collect()
+}
+
+/// `count` if that many entries of `entry_len` bytes from `start` fit in `full_box`.
+fn checked_count(full_box: &[u8], start: usize, count: u32, entry_len: usize) -> Result<usize> {
+    let needed = start as u64 + u64::from(count) * entry_len as u64;
+    if needed > full_box.len() as u64 {
+        return Err(check_failed(format!("{} entries do not fit in a box of {} bytes", count, full_box.len())));
+    }
+    Ok(count as usize)
+}
+
+/// Child boxes of a container's body as `(type, body)` pairs.
+fn children(data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut boxes = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 8 {
+        let size = u32_at(rest, 0)? as usize;
+        if size < 8 || size > rest.len() {
+            return Err(check_failed(format!("{} box overruns its parent", String::from_utf8_lossy(&rest[4..8]))));
+        }
+        boxes.push((rest[4..8].try_into().expect("4 bytes"), &rest[8..size]));
+        rest = &rest[size..];
+    }
+    Ok(boxes)
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Result<&'a [u8]> {
+    children(data)?.into_iter()
+        .find(|(k, _)| k == kind)
+        .map(|(_, body)| body)
+        .ok_or_else(|| check_failed(format!("no {} box", String::from_utf8_lossy(kind))))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().expect("4 bytes")))
+        .ok_or_else(|| check_failed("truncated box"))
+}
+
+fn u64_at(data: &[u8], at: usize) -> Result<u64> {
+    data.get(at..at + 8)
+        .map(|bytes| u64::from_be_bytes(bytes.try_into().expect("8 bytes")))
+        .ok_or_else(|| check_failed("truncated box"))
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use hotmart_ripper::{batch, Error, OutputFormat};
+
+const URL_A: &str = "https://player.hotmart.com/embed/abc123?signature=s&token=t";
+const URL_B: &str = "https://player.hotmart.com/embed/def456?signature=s&token=t";
+
+#[test]
+fn parses_urls_with_optional_output_paths() {
+    let input = format!(
+        "# Module 1\n{}   Module 1/01 Welcome.mp4  \n\n  # indented comment\r\n{}\r\n",
+        URL_A, URL_B,
+    );
+    let entries = batch::parse(&input).unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!((entries[0].line, entries[0].url.as_str()), (2, URL_A));
+    assert_eq!(entries[0].output, Some(PathBuf::from("Module 1/01 Welcome.mp4")));
+    assert_eq!((entries[1].line, entries[1].url.as_str(), entries[1].output.as_ref()), (5, URL_B, None));
+}
+
+#[test]
+fn reports_the_line_of_an_invalid_url() {
+    let input = format!("{}\nplayer.hotmart.com/embed/xyz\n", URL_A);
+    match batch::parse(&input) {
+        Err(Error::UrlParse(reason)) => assert!(reason.starts_with("line 2:"), "{}", reason),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn output_paths_take_the_extension_of_the_format() {
+    let input = format!("{} 01.mp4\n{} Lesson 1.5 intro\n{}\n", URL_A, URL_B, URL_A);
+    let entries = batch::parse(&input).unwrap();
+    let paths = |format| entries.iter().map(|entry| entry.output_path(format)).collect::<Vec<_>>();
+
+    assert_eq!(paths(OutputFormat::Mp4), [Some(PathBuf::from("01.mp4")), Some(PathBuf::from("Lesson 1.5 intro.mp4")), None]);
+    assert_eq!(paths(OutputFormat::Ts), [Some(PathBuf::from("01.ts")), Some(PathBuf::from("Lesson 1.5 intro.ts")), None]);
+}
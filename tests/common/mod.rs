@@ -0,0 +1,180 @@
+//! A local stand-in for the Hotmart player, content API and CDN.
+//!
+//! [`MockServer`] is a minimal HTTP/1.1 server on a random local port that
+//! answers each request from a table of canned responses and records what it
+//! was asked, so the whole pipeline can be exercised offline.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use aes::Aes128;
+use cbc::Encryptor;
+use cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+pub const VIDEO_ID: &str = "VID123";
+pub const TOKEN: &str = "tok-123";
+pub const SIGNATURE: &str = "sig-456";
+pub const KEY: [u8; 16] = *b"0123456789abcdef";
+pub const IV: [u8; 16] = [7; 16];
+
+/// A request as the server received it.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path including the query string.
+    pub path: String,
+    /// Header names in lower case.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self { status: 200, content_type, body: body.into() }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self { status, content_type: "text/plain", body: Vec::new() }
+    }
+}
+
+pub struct MockServer {
+    /// `http://127.0.0.1:<port>`
+    pub base: String,
+    routes: Arc<Mutex<HashMap<String, Response>>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Arc<Mutex<HashMap<String, Response>>> = Arc::default();
+        let requests: Arc<Mutex<Vec<Request>>> = Arc::default();
+
+        let (served_routes, log) = (routes.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (routes, log) = (served_routes.clone(), log.clone());
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    let Some(request) = read_request(&mut stream).await else { return };
+                    let route = request.path.split('?').next().unwrap_or_default().to_string();
+                    let response = routes.lock().unwrap().get(&route).cloned().unwrap_or(Response::status(404));
+                    log.lock().unwrap().push(request);
+
+                    let head = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        response.status,
+                        response.content_type,
+                        response.body.len(),
+                    );
+                    let stream = stream.get_mut();
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    let _ = stream.write_all(&response.body).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        Self { base, routes, requests }
+    }
+
+    /// Answers requests for `path`, ignoring any query string, with `response`.
+    pub fn route(&self, path: &str, response: Response) {
+        self.routes.lock().unwrap().insert(path.to_string(), response);
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    pub fn embed_url(&self) -> String {
+        self.url(&format!("/embed/{}?signature={}&token={}", VIDEO_ID, SIGNATURE, TOKEN))
+    }
+
+    /// Requests received so far whose path starts with `prefix`.
+    pub fn requests(&self, prefix: &str) -> Vec<Request> {
+        self.requests.lock().unwrap().iter().filter(|request| request.path.starts_with(prefix)).cloned().collect()
+    }
+
+    /// Serves the embed page with `application_data` as its `__NEXT_DATA__`.
+    pub fn serve_embed_page(&self, application_data: Value) {
+        let next_data = json!({ "props": { "pageProps": { "applicationData": application_data } } });
+        let html = format!(
+            "<!DOCTYPE html><html><body><div id=\"__next\"></div>\
+             <script id=\"__NEXT_DATA__\" type=\"application/json\">{}</script></body></html>",
+            next_data,
+        );
+        self.route(&format!("/embed/{}", VIDEO_ID), Response::ok("text/html", html));
+    }
+
+    /// Serves a video under `prefix`: a master playlist with a low and a high
+    /// variant, of which the high one has AES-128 encrypted segments. Returns
+    /// the master playlist URL and the plaintext of the high variant.
+    pub fn serve_video(&self, prefix: &str) -> (String, Vec<u8>) {
+        let master = "#EXTM3U\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360\n\
+                      low/index.m3u8\n\
+                      #EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720\n\
+                      high/index.m3u8\n";
+        self.route(&format!("{}/master.m3u8", prefix), Response::ok("application/vnd.apple.mpegurl", master));
+
+        let mut low = String::from("#EXTM3U\n#EXT-X-TARGETDURATION:4\n");
+        let mut high = format!(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-KEY:METHOD=AES-128,URI=\"{}/key\",IV=0x{}\n",
+            prefix,
+            hex::encode(IV),
+        );
+        let mut plaintext = Vec::new();
+        for i in 0..3 {
+            let segment: Vec<u8> = (0..188 * (i + 2)).map(|n| (n * 31 + i) as u8).collect();
+            let encrypted = Encryptor::<Aes128>::new(&KEY.into(), &IV.into()).encrypt_padded_vec_mut::<Pkcs7>(&segment);
+            self.route(&format!("{}/high/{}.ts", prefix, i), Response::ok("video/mp2t", encrypted));
+            self.route(&format!("{}/low/{}.ts", prefix, i), Response::ok("video/mp2t", segment.clone()));
+            high.push_str(&format!("#EXTINF:4.0,\n{}.ts?sig=abc\n", i));
+            low.push_str(&format!("#EXTINF:4.0,\n{}.ts\n", i));
+            plaintext.extend(segment);
+        }
+        high.push_str("#EXT-X-ENDLIST\n");
+        low.push_str("#EXT-X-ENDLIST\n");
+        self.route(&format!("{}/high/index.m3u8", prefix), Response::ok("application/vnd.apple.mpegurl", high));
+        self.route(&format!("{}/low/index.m3u8", prefix), Response::ok("application/vnd.apple.mpegurl", low));
+        self.route(&format!("{}/key", prefix), Response::ok("application/octet-stream", KEY.to_vec()));
+
+        (self.url(&format!("{}/master.m3u8", prefix)), plaintext)
+    }
+}
+
+async fn read_request<R: AsyncBufReadExt + Unpin>(stream: &mut R) -> Option<Request> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.ok()?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string()),
+            None => break,
+        };
+    }
+
+    let length = headers.get("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.ok()?;
+    Some(Request { method, path, headers, body })
+}
@@ -0,0 +1,36 @@
+use aes::Aes128;
+use cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use hotmart_ripper::{decrypt_segment, DecryptError};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+fn encrypt(data: &[u8], key: &[u8; 16], iv: &[u8; 16]) -> Vec<u8> {
+    cbc::Encryptor::<Aes128>::new(key.into(), iv.into()).encrypt_padded_vec_mut::<Pkcs7>(data)
+}
+
+proptest! {
+    #[test]
+    fn decrypts_what_was_encrypted(data in vec(any::<u8>(), 0..2048), key: [u8; 16], iv: [u8; 16]) {
+        let encrypted = encrypt(&data, &key, &iv);
+        prop_assert_eq!(encrypted.len(), (data.len() / 16 + 1) * 16);
+        prop_assert_eq!(decrypt_segment(&encrypted, &key, &iv).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_truncated_segments(data in vec(any::<u8>(), 0..2048), key: [u8; 16], iv: [u8; 16], cut in 1usize..16) {
+        let encrypted = encrypt(&data, &key, &iv);
+        let truncated = &encrypted[..encrypted.len() - cut];
+        prop_assert!(matches!(decrypt_segment(truncated, &key, &iv), Err(DecryptError::BadPadding)));
+    }
+
+    #[test]
+    fn rejects_keys_and_ivs_of_the_wrong_length(key in vec(any::<u8>(), 0..40), iv in vec(any::<u8>(), 0..40)) {
+        prop_assume!(key.len() != 16 || iv.len() != 16);
+        let result = decrypt_segment(&[0; 32], &key, &iv);
+        prop_assert!(
+            matches!(result, Err(DecryptError::InvalidLength { key_len, iv_len }) if key_len == key.len() && iv_len == iv.len()),
+            "{:?}",
+            result,
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use hotmart_ripper::m3u8::{
+    self, ByteRange, KeyMethod, MediaType, Playlist, PlaylistType, Resolution,
+};
+use proptest::prelude::*;
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+#[test]
+fn master_playlist_variants() {
+    let master = m3u8::parse_master(&fixture("master.m3u8")).unwrap();
+
+    assert_eq!(master.version, Some(4));
+    assert!(master.independent_segments);
+    assert_eq!(master.variants.len(), 3);
+
+    let first = &master.variants[0];
+    assert_eq!(first.uri, "480p/playlist.m3u8?token=abc,def");
+    assert_eq!(first.bandwidth, 1_280_000);
+    assert_eq!(first.average_bandwidth, Some(1_000_000));
+    assert_eq!(first.codecs.as_deref(), Some("avc1.4d401f,mp4a.40.2"));
+    assert_eq!(first.resolution, Some(Resolution { width: 854, height: 480 }));
+    assert_eq!(first.frame_rate, Some(29.97));
+    assert_eq!(first.audio.as_deref(), Some("aac"));
+    assert_eq!(first.subtitles.as_deref(), Some("subs"));
+    assert_eq!(first.closed_captions.as_deref(), Some("NONE"));
+
+    let third = &master.variants[2];
+    assert_eq!(third.uri, "360p/playlist.m3u8");
+    assert_eq!(third.audio, None);
+    assert_eq!(third.frame_rate, None);
+
+    assert_eq!(master.i_frame_streams.len(), 1);
+    assert_eq!(master.i_frame_streams[0].uri, "480p/iframes.m3u8");
+}
+
+#[test]
+fn master_playlist_renditions() {
+    let master = m3u8::parse_master(&fixture("master.m3u8")).unwrap();
+    assert_eq!(master.renditions.len(), 3);
+
+    let portuguese = &master.renditions[0];
+    assert_eq!(portuguese.media_type, MediaType::Audio);
+    assert_eq!(portuguese.group_id, "aac");
+    assert_eq!(portuguese.language.as_deref(), Some("pt-BR"));
+    assert_eq!(portuguese.name, "Português");
+    assert!(portuguese.default && portuguese.autoselect && !portuguese.forced);
+    assert_eq!(portuguese.channels.as_deref(), Some("2"));
+    assert_eq!(portuguese.uri.as_deref(), Some("audio/pt/playlist.m3u8"));
+
+    assert_eq!(master.renditions[1].name, "English, original");
+    assert!(!master.renditions[1].default);
+    assert_eq!(master.renditions[2].media_type, MediaType::Subtitles);
+}
+
+#[test]
+fn media_playlist_keys_and_discontinuities() {
+    let media = m3u8::parse_media(&fixture("media_aes.m3u8")).unwrap();
+
+    assert_eq!(media.target_duration, 10);
+    assert_eq!(media.media_sequence, 7);
+    assert_eq!(media.playlist_type, Some(PlaylistType::Vod));
+    assert!(media.end_list);
+    assert_eq!(media.segments.len(), 3);
+    assert!((media.duration() - 24.52).abs() < 1e-9);
+
+    let key = media.segments[0].key.as_ref().unwrap();
+    assert_eq!(key.method, KeyMethod::Aes128);
+    assert_eq!(key.uri, "https://keys.example.com/key?id=1,2");
+    let mut iv = [0u8; 16];
+    iv[14] = 0x0a;
+    iv[15] = 0xbc;
+    assert_eq!(key.iv, Some(iv));
+
+    assert_eq!(media.segments[1].key.as_ref(), Some(key));
+    assert_eq!(media.segments[1].title.as_deref(), Some("second"));
+    assert!(!media.segments[1].discontinuity);
+
+    let last = &media.segments[2];
+    assert_eq!(last.key, None);
+    assert!(last.discontinuity);
+    assert_eq!(last.duration, 4.5);
+    assert_eq!(last.uri, "https://cdn.example.com/segment-9.ts");
+}
+
+#[test]
+fn media_playlist_maps_and_byte_ranges() {
+    let media = m3u8::parse_media(&fixture("media_fmp4.m3u8")).unwrap();
+    assert_eq!(media.segments.len(), 3);
+
+    let first_map = media.segments[0].map.as_ref().unwrap();
+    assert_eq!(first_map.uri, "video.mp4");
+    assert_eq!(first_map.byte_range, Some(ByteRange { length: 720, offset: Some(0) }));
+    assert_eq!(media.segments[1].map.as_ref(), Some(first_map));
+
+    assert_eq!(media.segments[0].byte_range, Some(ByteRange { length: 100_000, offset: Some(720) }));
+    assert_eq!(media.segments[1].byte_range, Some(ByteRange { length: 95_000, offset: None }));
+
+    let last = &media.segments[2];
+    assert_eq!(last.map.as_ref().unwrap().uri, "video-b.mp4");
+    assert_eq!(last.map.as_ref().unwrap().byte_range, None);
+    assert!(last.discontinuity);
+    assert_eq!(last.program_date_time.as_deref(), Some("2024-01-01T00:00:12.000Z"));
+}
+
+#[test]
+fn map_keeps_the_key_it_was_declared_under() {
+    let media = m3u8::parse_media(&fixture("media_fmp4_encrypted.m3u8")).unwrap();
+
+    for segment in &media.segments {
+        let map = segment.map.as_ref().unwrap();
+        assert_eq!(map.uri, "init.mp4");
+        assert_eq!(map.key.as_ref().unwrap().uri, "init.key");
+        assert!(map.key.as_ref().unwrap().iv.is_some());
+        assert_eq!(segment.key.as_ref().unwrap().uri, "media.key");
+    }
+}
+
+#[test]
+fn crlf_line_endings() {
+    let media = m3u8::parse_media(&fixture("media_crlf.m3u8")).unwrap();
+    let uris: Vec<_> = media.segments.iter().map(|s| s.uri.as_str()).collect();
+    assert_eq!(uris, ["a.ts", "b.ts"]);
+}
+
+#[test]
+fn detects_playlist_kind() {
+    assert!(matches!(m3u8::parse(&fixture("master.m3u8")), Ok(Playlist::Master(_))));
+    assert!(matches!(m3u8::parse(&fixture("media_aes.m3u8")), Ok(Playlist::Media(_))));
+}
+
+#[test]
+fn round_trips_through_display() {
+    for name in ["master.m3u8", "media_aes.m3u8", "media_fmp4.m3u8", "media_crlf.m3u8", "media_implicit_iv.m3u8", "media_fmp4_encrypted.m3u8"] {
+        let parsed = m3u8::parse(&fixture(name)).unwrap();
+        let written = parsed.to_string();
+        let reparsed = m3u8::parse(&written).unwrap_or_else(|e| panic!("{}: {}\n{}", name, e, written));
+        assert_eq!(parsed, reparsed, "{} did not round-trip:\n{}", name, written);
+    }
+}
+
+#[test]
+fn rejects_malformed_playlists() {
+    let cases = [
+        ("", "empty playlist"),
+        ("#EXT-X-TARGETDURATION:10\n", "does not start with #EXTM3U"),
+        ("#EXTM3U\n#EXTINF:10,\na.ts\n", "missing #EXT-X-TARGETDURATION"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\na.ts\n", "without #EXTINF"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:abc,\na.ts\n", "invalid #EXTINF duration"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:METHOD=AES-128,URI=\"k\",IV=0xZZ\n", "invalid IV"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:METHOD=AES-128,URI=\"k\n", "unterminated"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:METHOD=AES-128,URI=1\"k\"\n", "stray '\"'"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10,\na.ts\n#EXT-X-MEDIA-SEQUENCE:5\n", "after the first segment"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10,\n#EXTINF:10,\na.ts\n", "line 3: #EXTINF is not followed by a segment URI"),
+        ("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1\n", "not followed by a URI"),
+        ("#EXTM3U\n#EXT-X-STREAM-INF:RESOLUTION=1x1\na.m3u8\n", "BANDWIDTH"),
+    ];
+
+    for (input, expected) in cases {
+        let error = m3u8::parse(input).expect_err(input);
+        assert!(error.to_string().contains(expected), "{:?}: {}", input, error);
+    }
+}
+
+#[test]
+fn rejects_quoted_enumerated_attributes() {
+    // Written back unquoted, these would split at the comma.
+    let cases = [
+        ("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1,HDCP-LEVEL=\"A,B\"\na.m3u8\n", "HDCP-LEVEL=\"A,B\" must not be quoted"),
+        ("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:METHOD=\"X,Y\",URI=\"k\"\n", "METHOD=\"X,Y\" must not be quoted"),
+    ];
+
+    for (input, expected) in cases {
+        let error = m3u8::parse(input).expect_err(input);
+        assert!(error.to_string().contains(expected), "{:?}: {}", input, error);
+    }
+}
+
+#[test]
+fn rejects_frame_rates_that_are_not_finite_and_non_negative() {
+    for rate in ["NaN", "inf", "-inf", "-1", "-0.5"] {
+        let input = format!("#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1,FRAME-RATE={}\na.m3u8\n", rate);
+        let error = m3u8::parse(&input).expect_err(rate);
+        assert!(error.to_string().contains("is not a non-negative decimal number"), "{}: {}", rate, error);
+    }
+}
+
+#[test]
+fn tags_that_only_start_like_master_tags_do_not_make_a_master_playlist() {
+    // Found by the m3u8 fuzz target: the unknown tag was skipped while
+    // parsing, and the empty master playlist was written as a bare header.
+    let input = "#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-STREAM-INFT-SEGMYES,AUTOSELECT=YES\n#EXT-X-TARGETDURATION:10\n";
+    let Playlist::Media(media) = m3u8::parse(input).unwrap() else { panic!("parsed as a master playlist") };
+    assert_eq!(m3u8::parse(&media.to_string()).unwrap(), Playlist::Media(media));
+
+    let error = m3u8::parse("#EXTM3U\n#EXT-X-MEDIAX:TYPE=AUDIO\n").unwrap_err();
+    assert!(error.to_string().contains("missing #EXT-X-TARGETDURATION"), "{}", error);
+}
+
+#[test]
+fn session_tags_alone_do_not_make_a_master_playlist() {
+    let input = "#EXTM3U\n#EXT-X-SESSION-DATA:DATA-ID=\"a\",VALUE=\"b\"\n#EXT-X-TARGETDURATION:10\n";
+    let Playlist::Media(media) = m3u8::parse(input).unwrap() else { panic!("parsed as a master playlist") };
+    assert_eq!(m3u8::parse(&media.to_string()).unwrap(), Playlist::Media(media));
+
+    // With no target duration, it fails as a media playlist instead of writing a bare header.
+    let error = m3u8::parse("#EXTM3U\n#EXT-X-SESSION-KEY:METHOD=AES-128,URI=\"k\"\n").unwrap_err();
+    assert!(error.to_string().contains("missing #EXT-X-TARGETDURATION"), "{}", error);
+}
+
+#[test]
+fn tracks_media_sequence_per_segment() {
+    let media = m3u8::parse_media(&fixture("media_implicit_iv.m3u8")).unwrap();
+    let sequences: Vec<_> = media.segments.iter().map(|s| s.sequence).collect();
+    assert_eq!(sequences, [41, 42, 43]);
+
+    let without_header = m3u8::parse_media(&fixture("media_crlf.m3u8")).unwrap();
+    let sequences: Vec<_> = without_header.segments.iter().map(|s| s.sequence).collect();
+    assert_eq!(sequences, [0, 1]);
+}
+
+#[test]
+fn implicit_iv_is_the_big_endian_sequence_number() {
+    let media = m3u8::parse_media(&fixture("media_implicit_iv.m3u8")).unwrap();
+
+    let implicit = &media.segments[1];
+    let key = implicit.key.as_ref().unwrap();
+    assert_eq!(key.iv, None);
+    let mut expected = [0u8; 16];
+    expected[15] = 42;
+    assert_eq!(key.iv_for(implicit.sequence), expected);
+
+    let explicit = &media.segments[2];
+    let key = explicit.key.as_ref().unwrap();
+    assert_eq!(key.iv_for(explicit.sequence), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+}
+
+#[test]
+fn segments_decrypt_with_the_derived_iv() {
+    use aes::Aes128;
+    use cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+
+    let media = m3u8::parse_media(&fixture("media_implicit_iv.m3u8")).unwrap();
+    let aes_key = *b"0123456789abcdef";
+
+    for segment in &media.segments {
+        let iv = segment.key.as_ref().unwrap().iv_for(segment.sequence);
+        let plaintext = format!("payload of {} spanning more than one AES block", segment.uri);
+        let ciphertext = cbc::Encryptor::<Aes128>::new(&aes_key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+
+        let decrypted = hotmart_ripper::decrypt_segment(&ciphertext, &aes_key, &iv).unwrap();
+        assert_eq!(decrypted, plaintext.as_bytes());
+
+        // The old all-zero fallback only matches for sequence number 0.
+        assert!(hotmart_ripper::decrypt_segment(&ciphertext, &aes_key, &[0; 16])
+            .map_or(true, |wrong| wrong != plaintext.as_bytes()));
+    }
+}
+
+fn playlist_with_iv(iv: &str) -> String {
+    format!("#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\",IV={}\n#EXTINF:4,\na.ts\n", iv)
+}
+
+proptest! {
+    #[test]
+    fn explicit_ivs_parse_exactly(iv: [u8; 16], upper: bool, leading_zeros in 0usize..16) {
+        let digits = if upper { hex::encode_upper(iv) } else { hex::encode(iv) };
+        // Leading zero bytes may be left out; the value is a 128-bit integer.
+        let skipped = iv.iter().take(leading_zeros).take_while(|&&b| b == 0).count() * 2;
+        let media = m3u8::parse_media(&playlist_with_iv(&format!("0x{}", &digits[skipped..]))).unwrap();
+        prop_assert_eq!(media.segments[0].key.as_ref().unwrap().iv, Some(iv));
+    }
+
+    #[test]
+    fn malformed_ivs_are_rejected(digits in "[0-9a-fA-F]{0,40}", junk in "[g-zG-Z_ ]") {
+        let too_long = digits.len() > 32;
+        let result = m3u8::parse_media(&playlist_with_iv(&format!("0x{}", digits)));
+        prop_assert_eq!(result.is_err(), digits.is_empty() || too_long);
+
+        let middle = digits.len() / 2;
+        let corrupted = format!("0x{}{}{}", &digits[..middle], junk, &digits[middle..]);
+        prop_assert!(m3u8::parse_media(&playlist_with_iv(&corrupted)).is_err(), "{}", corrupted);
+
+        // The 0x prefix is required.
+        prop_assert!(m3u8::parse_media(&playlist_with_iv(&digits)).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use hotmart_ripper::metadata::AssetFormat;
+use hotmart_ripper::{Error, MediaAsset, VideoMetadata};
+use serde_json::json;
+
+#[test]
+fn reads_the_embed_page() {
+    let page = json!({
+        "mediaTitle": " Boas-vindas ",
+        "mediaDuration": 754.3,
+        "thumbnailUrl": "https://example.com/thumb.jpg",
+        "producer": { "name": "Ana Souza" },
+        "course": { "name": "Curso de Rust" },
+        "moduleOrder": "3",
+        "locale": "pt-BR",
+        "createdAt": 1710770531000u64,
+        "mediaAssets": [
+            { "url": "https://example.com/master.m3u8", "type": "VIDEO", "contentType": "application/x-mpegURL" },
+            { "url": "https://example.com/video.mp4" },
+            { "type": "AUDIO" },
+        ],
+    });
+    let metadata = VideoMetadata::from_page("abc", &page).unwrap();
+    assert_eq!(metadata, VideoMetadata {
+        video_id: "abc".to_string(),
+        title: Some("Boas-vindas".to_string()),
+        duration: Some(754.3),
+        thumbnail_url: Some("https://example.com/thumb.jpg".to_string()),
+        producer: Some("Ana Souza".to_string()),
+        course: Some("Curso de Rust".to_string()),
+        module: Some(3),
+        lesson: None,
+        locale: Some("pt-BR".to_string()),
+        upload_date: Some("2024-03-18".to_string()),
+        assets: vec![
+            MediaAsset {
+                url: "https://example.com/master.m3u8".to_string(),
+                kind: Some("VIDEO".to_string()),
+                mime_type: Some("application/x-mpegURL".to_string()),
+                language: None,
+            },
+            MediaAsset { url: "https://example.com/video.mp4".to_string(), kind: None, mime_type: None, language: None },
+        ],
+    });
+    assert_eq!(metadata.master_url(), Some("https://example.com/master.m3u8"));
+
+    let error = VideoMetadata::from_page("abc", &json!({ "mediaTitle": "No assets", "mediaAssets": [] })).unwrap_err();
+    assert!(matches!(error, Error::PlaylistNotFound { .. }), "{}", error);
+}
+
+#[test]
+fn reads_the_content_api_response() {
+    let response = json!({
+        "response": {
+            "title": "Aula 4",
+            "lesson": { "order": 4 },
+            "uploadDate": "2023-11-02T09:15:00Z",
+            "streaming": { "hls": { "url": "https://example.com/api/master.m3u8" } },
+        },
+    });
+    let metadata = VideoMetadata::from_api("abc", &response).unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("Aula 4"));
+    assert_eq!(metadata.lesson, Some(4));
+    assert_eq!(metadata.upload_date.as_deref(), Some("2023-11-02T09:15:00Z"));
+    assert_eq!(metadata.master_url(), Some("https://example.com/api/master.m3u8"));
+
+    // Timestamps given as strings become dates too.
+    let response = json!({ "uploadDate": "1710770531", "streaming": { "hls": { "url": "https://example.com/master.m3u8" } } });
+    let metadata = VideoMetadata::from_api("abc", &response).unwrap();
+    assert_eq!(metadata.upload_date.as_deref(), Some("2024-03-18"));
+
+    let error = VideoMetadata::from_api("abc", &json!({ "title": "No stream" })).unwrap_err();
+    assert!(matches!(error, Error::PlaylistNotFound { .. }), "{}", error);
+}
+
+#[test]
+fn prefers_hls_assets() {
+    let page = json!({
+        "mediaAssets": [
+            { "url": "https://example.com/video.mp4?token=x" },
+            { "url": "https://example.com/stream?token=x" },
+            { "url": "https://example.com/manifest.mpd" },
+            { "url": "https://example.com/es/master.m3u8?token=x", "language": "es" },
+            { "url": "https://example.com/pt/playlist", "contentType": "application/vnd.apple.mpegurl", "language": "pt-BR" },
+        ],
+    });
+    let metadata = VideoMetadata::from_page("abc", &page).unwrap();
+    let formats: Vec<AssetFormat> = metadata.assets.iter().map(MediaAsset::format).collect();
+    assert_eq!(formats, [AssetFormat::File, AssetFormat::Unknown, AssetFormat::Dash, AssetFormat::Hls, AssetFormat::Hls]);
+    assert_eq!(metadata.preferred_assets(), [3, 4, 1]);
+    assert_eq!(metadata.master_url(), Some("https://example.com/es/master.m3u8?token=x"));
+    assert_eq!(metadata.assets[4].language.as_deref(), Some("pt-BR"));
+
+    // Without an asset the downloader can fetch, the page is no use.
+    let page = json!({ "mediaAssets": [{ "url": "https://example.com/manifest.mpd" }] });
+    let error = VideoMetadata::from_page("abc", &page).unwrap_err();
+    assert!(error.to_string().contains("only DASH"), "{}", error);
+}
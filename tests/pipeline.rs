@@ -0,0 +1,357 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{MockServer, Response, SIGNATURE, TOKEN, VIDEO_ID};
+use hotmart_ripper::m3u8::KeyMethod;
+use hotmart_ripper::{Error, HotmartDownloader, OutputFormat, OutputTemplate, ProgressEvent, RetryPolicy};
+use serde_json::{json, Value};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hotmart-pipeline-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn downloader(server: &MockServer) -> HotmartDownloader {
+    HotmartDownloader::builder()
+        .api_url(server.url("/video/content"))
+        .player_url(&server.base)
+        .output_format(OutputFormat::Ts)
+        .retries(0)
+        .build()
+        .unwrap()
+}
+
+/// Records events as short strings, leaving out the URLs that change per run.
+fn recorder() -> (Arc<Mutex<Vec<String>>>, impl Fn(ProgressEvent) + Send + Sync + 'static) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    let on_event = move |event: ProgressEvent| {
+        let event = match event {
+            ProgressEvent::PageFallback { .. } => "page fallback".to_string(),
+            ProgressEvent::AssetFallback { reason, .. } => format!("asset fallback: {}", reason.split(" at ").next().unwrap_or_default()),
+            ProgressEvent::MasterPlaylist { url } => format!("master {}", url.rsplit('/').nth(1).unwrap_or_default()),
+            ProgressEvent::VariantSelected { variant } => format!("variant {}", variant.bandwidth),
+            ProgressEvent::SegmentsFound { total } => format!("segments {}", total),
+            ProgressEvent::Segment { current, total } => format!("segment {}/{}", current, total),
+            ProgressEvent::Finished { path } => format!("finished {}", path.file_name().unwrap().to_string_lossy()),
+            other => format!("{:?}", other),
+        };
+        log.lock().unwrap().push(event);
+    };
+    (events, on_event)
+}
+
+#[tokio::test]
+async fn downloads_and_decrypts_through_the_embed_page() {
+    let server = MockServer::start().await;
+    let (master_url, plaintext) = server.serve_video("/hls");
+    server.serve_embed_page(json!({
+        "mediaTitle": "Aula 1: Boas-vindas",
+        "mediaAssets": [{ "url": master_url, "contentType": "application/x-mpegURL" }],
+    }));
+
+    let dir = temp_dir("page");
+    let template: OutputTemplate = "{title}.{ext}".parse().unwrap();
+    let (events, on_event) = recorder();
+    let path = downloader(&server).download_to(&server.embed_url(), &template.under(&dir), on_event).await.unwrap();
+
+    assert_eq!(path, dir.join("Aula 1_ Boas-vindas.ts"));
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+    assert_eq!(*events.lock().unwrap(), [
+        "master hls",
+        "variant 2500000",
+        "segments 3",
+        "segment 1/3",
+        "segment 2/3",
+        "segment 3/3",
+        "finished Aula 1_ Boas-vindas.ts",
+    ]);
+
+    // The checkpoint is gone, and the content API was never needed.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    assert!(server.requests("/video/content").is_empty());
+    let page = &server.requests("/embed/")[0];
+    assert_eq!(page.headers["origin"], server.base);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn falls_back_to_the_content_api() {
+    let server = MockServer::start().await;
+    let (master_url, plaintext) = server.serve_video("/api-hls");
+    server.route(&format!("/embed/{}", VIDEO_ID), Response::ok("text/html", "<html><body>Loading...</body></html>"));
+    let api_response = json!({ "response": { "title": "Aula 2", "streaming": { "hls": { "url": master_url } } } });
+    server.route("/video/content", Response::ok("application/json", api_response.to_string()));
+
+    let dir = temp_dir("api");
+    let (events, on_event) = recorder();
+    let path = downloader(&server)
+        .download_to(&server.embed_url(), &OutputTemplate::default().under(&dir), on_event)
+        .await
+        .unwrap();
+
+    assert_eq!(path, dir.join(format!("{}.ts", VIDEO_ID)));
+    assert_eq!(std::fs::read(&path).unwrap(), plaintext);
+    assert_eq!(events.lock().unwrap()[..2], ["page fallback", "master api-hls"]);
+
+    let api = &server.requests("/video/content")[0];
+    assert_eq!(api.method, "POST");
+    assert_eq!(api.headers["x-hotmart-key"], TOKEN);
+    let body: Value = serde_json::from_slice(&api.body).unwrap();
+    assert_eq!((body["videoId"].as_str(), body["token"].as_str(), body["signature"].as_str()), (Some(VIDEO_ID), Some(TOKEN), Some(SIGNATURE)));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn describes_the_first_asset_that_loads() {
+    let server = MockServer::start().await;
+    let (master_url, _) = server.serve_video("/good");
+    server.serve_embed_page(json!({
+        "mediaAssets": [
+            { "url": server.url("/video.mp4") },
+            { "url": server.url("/missing/master.m3u8") },
+            { "url": master_url },
+        ],
+    }));
+
+    let (events, on_event) = recorder();
+    let info = downloader(&server).info(&server.embed_url(), &on_event).await.unwrap();
+
+    assert_eq!((info.asset, info.master_url.as_str()), (2, master_url.as_str()));
+    assert_eq!(info.selected, Some(1));
+    assert_eq!((info.segment_count, info.duration), (3, 12.0));
+    assert_eq!(info.encryption, Some(KeyMethod::Aes128));
+    assert_eq!(*events.lock().unwrap(), ["master missing", "asset fallback: HTTP 404", "master good"]);
+    assert!(server.requests("/video.mp4").is_empty());
+}
+
+#[tokio::test]
+async fn batches_never_share_an_output_path() {
+    let server = MockServer::start().await;
+    let (master_url, plaintext) = server.serve_video("/hls");
+    server.serve_embed_page(json!({ "mediaTitle": "Aula", "mediaAssets": [{ "url": master_url }] }));
+
+    let dir = temp_dir("batch-conflict");
+    let template: OutputTemplate = "{title}".parse().unwrap();
+    let videos = vec![(server.embed_url(), template.clone().under(&dir)), (server.embed_url(), template.under(&dir))];
+    let results = downloader(&server).download_batch(&videos, 2, |_, _| {}).await;
+
+    let output = dir.join("Aula.ts");
+    let conflicts: Vec<_> = results.iter().filter_map(|result| result.as_ref().err()).collect();
+    assert_eq!(conflicts.len(), 1, "{:?}", results);
+    assert!(matches!(conflicts[0], Error::OutputConflict { path, .. } if *path == output), "{}", conflicts[0]);
+    assert!(results.iter().any(|result| result.as_ref().ok() == Some(&output)));
+    assert_eq!(std::fs::read(&output).unwrap(), plaintext);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn reports_expired_links() {
+    let server = MockServer::start().await;
+    server.route("/expired/master.m3u8", Response::status(403));
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": server.url("/expired/master.m3u8") }] }));
+
+    let dir = temp_dir("expired");
+    let error = downloader(&server).download(&server.embed_url(), dir.join("video.ts"), |_| {}).await.unwrap_err();
+    assert!(matches!(error, Error::LinkExpired { status: 403, .. }), "{}", error);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    // Expiring halfway leaves an existing file alone and keeps the partial one.
+    let (master_url, plaintext) = server.serve_video("/hls");
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": master_url }] }));
+    server.route("/hls/high/1.ts", Response::status(403));
+    let output = dir.join("video.ts");
+    std::fs::write(&output, "previous download").unwrap();
+
+    let error = downloader(&server).download(&server.embed_url(), &output, |_| {}).await.unwrap_err();
+    assert!(matches!(error, Error::LinkExpired { status: 403, .. }), "{}", error);
+    assert_eq!(std::fs::read(&output).unwrap(), b"previous download");
+    assert_eq!(std::fs::read(dir.join("video.ts.part")).unwrap(), plaintext[..188 * 2]);
+
+    // Finishing replaces the file and cleans up after itself.
+    server.serve_video("/hls");
+    downloader(&server).download(&server.embed_url(), &output, |_| {}).await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), plaintext);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn refuses_audio_it_cannot_mux() {
+    let server = MockServer::start().await;
+    let playlist = |body: &str| Response::ok("application/vnd.apple.mpegurl", format!("#EXTM3U\n#EXT-X-TARGETDURATION:4\n{}#EXT-X-ENDLIST\n", body));
+    server.route("/fmp4/master.m3u8", Response::ok("application/vnd.apple.mpegurl", "#EXTM3U\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Português\",LANGUAGE=\"pt-BR\",URI=\"audio.m3u8\"\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aud\"\n\
+        video.m3u8\n"));
+    server.route("/fmp4/video.m3u8", playlist("#EXTINF:4.0,\n0.ts\n"));
+    server.route("/fmp4/audio.m3u8", playlist("#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:4.0,\n0.m4s\n"));
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": server.url("/fmp4/master.m3u8") }] }));
+
+    let dir = temp_dir("unsupported-audio");
+    let mp4 = HotmartDownloader::builder().player_url(&server.base).build().unwrap();
+    let error = mp4.download(&server.embed_url(), dir.join("video.mp4"), |_| {}).await.unwrap_err();
+    assert!(matches!(&error, Error::UnsupportedAudio { audio, video } if audio == "fragmented MP4" && video == "MPEG-TS"), "{}", error);
+    assert!(server.requests("/fmp4/0.").is_empty());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn names_the_audio_sidecar_after_its_contents() {
+    let server = MockServer::start().await;
+    let playlist = |body: &str| Response::ok("application/vnd.apple.mpegurl", format!("#EXTM3U\n#EXT-X-TARGETDURATION:4\n{}#EXT-X-ENDLIST\n", body));
+    server.route("/packed/master.m3u8", Response::ok("application/vnd.apple.mpegurl", "#EXTM3U\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Português\",LANGUAGE=\"pt-BR\",URI=\"audio.m3u8\"\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aud\"\n\
+        video.m3u8\n"));
+    server.route("/packed/video.m3u8", playlist("#EXTINF:4.0,\n0.ts\n"));
+    server.route("/packed/0.ts", Response::ok("video/mp2t", vec![0x47; 188]));
+    // Packed audio whose URL does not end in `.aac`.
+    server.route("/packed/audio.m3u8", playlist("#EXTINF:4.0,\naudio-0?sig=abc\n"));
+    server.route("/packed/audio-0", Response::ok("audio/aac", b"ID3\x04\x00\x00\x00\x00\x00\x00".to_vec()));
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": server.url("/packed/master.m3u8") }] }));
+
+    let dir = temp_dir("packed-audio");
+    downloader(&server).download(&server.embed_url(), dir.join("video.ts"), |_| {}).await.unwrap();
+    assert!(dir.join("video.ts").exists());
+    assert!(dir.join("video.pt-BR.aac").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn cancelled_downloads_resume_from_the_checkpoint() {
+    let server = MockServer::start().await;
+    let (master_url, plaintext) = server.serve_video("/hls");
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": master_url }] }));
+
+    let dir = temp_dir("cancel");
+    let output = dir.join("video.ts");
+    let cancelling = downloader(&server);
+    let control = cancelling.control().clone();
+    let error = cancelling
+        .download(&server.embed_url(), &output, move |event| {
+            if let ProgressEvent::Segment { .. } = event {
+                control.cancel();
+            }
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Cancelled), "{}", error);
+    assert!(!output.exists());
+    assert!(dir.join("video.ts.resume.json").exists());
+
+    let (events, on_event) = recorder();
+    downloader(&server).download(&server.embed_url(), &output, on_event).await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), plaintext);
+    assert!(events.lock().unwrap().contains(&"Resumed { completed: 1, total: 3 }".to_string()));
+
+    // The key is not part of the checkpoint, so the resumed run fetches it again.
+    assert_eq!(server.requests("/hls/key").len(), 2);
+    assert_eq!(server.requests("/hls/high/0.ts").len(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn changed_playlists_restart_from_the_first_segment() {
+    let server = MockServer::start().await;
+    let (master_url, plaintext) = server.serve_video("/hls");
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": master_url }] }));
+
+    let dir = temp_dir("changed");
+    let output = dir.join("video.ts");
+    let cancelling = downloader(&server);
+    let control = cancelling.control().clone();
+    let error = cancelling
+        .download(&server.embed_url(), &output, move |event| {
+            if let ProgressEvent::Segment { .. } = event {
+                control.cancel();
+            }
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Cancelled), "{}", error);
+
+    // Same segments, but the key has moved.
+    let playlist = reqwest::get(server.url("/hls/high/index.m3u8")).await.unwrap().text().await.unwrap();
+    server.route("/hls/high/index.m3u8", Response::ok("application/vnd.apple.mpegurl", playlist.replace("/hls/key", "/hls/new-key")));
+    server.route("/hls/new-key", Response::ok("application/octet-stream", common::KEY.to_vec()));
+
+    let (events, on_event) = recorder();
+    downloader(&server).download(&server.embed_url(), &output, on_event).await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), plaintext);
+    let events = events.lock().unwrap();
+    assert!(events.contains(&"ResumeDiscarded { reason: \"the playlist has changed\" }".to_string()), "{:?}", events);
+    assert!(!events.iter().any(|event| event.starts_with("Resumed")), "{:?}", events);
+    assert_eq!(server.requests("/hls/high/0.ts").len(), 2);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn paused_downloads_request_no_segments() {
+    let server = MockServer::start().await;
+    let (master_url, plaintext) = server.serve_video("/hls");
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": master_url }] }));
+
+    let dir = temp_dir("pause");
+    let output = dir.join("video.ts");
+    let downloader = downloader(&server);
+    let control = downloader.control().clone();
+    control.pause();
+
+    let embed_url = server.embed_url();
+    let download = downloader.download(&embed_url, &output, |_| {});
+    tokio::pin!(download);
+    let paused = tokio::time::timeout(Duration::from_millis(300), &mut download).await;
+    assert!(paused.is_err(), "a paused download finished");
+    assert!(server.requests("/hls/high/").iter().all(|request| request.path.ends_with(".m3u8")));
+
+    control.resume();
+    download.await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), plaintext);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn cancelling_cuts_the_wait_between_retries_short() {
+    let server = MockServer::start().await;
+    server.route("/busy/master.m3u8", Response::status(503));
+    server.serve_embed_page(json!({ "mediaAssets": [{ "url": server.url("/busy/master.m3u8") }] }));
+
+    let dir = temp_dir("retry-cancel");
+    let retrying = HotmartDownloader::builder()
+        .player_url(&server.base)
+        .retry_policy(RetryPolicy { max_retries: 3, base_delay: Duration::from_secs(60), max_delay: Duration::from_secs(60) })
+        .build()
+        .unwrap();
+    let control = retrying.control().clone();
+    let embed_url = server.embed_url();
+    let download = retrying.download(&embed_url, dir.join("video.mp4"), move |event| {
+        if let ProgressEvent::Retry { .. } = event {
+            control.cancel();
+        }
+    });
+
+    let error = tokio::time::timeout(Duration::from_secs(5), download).await
+        .expect("the backoff outlived the cancellation")
+        .unwrap_err();
+    assert!(matches!(error, Error::Cancelled), "{}", error);
+    assert_eq!(server.requests("/busy/").len(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,81 @@
+use hotmart_ripper::m3u8::MediaType;
+use hotmart_ripper::{m3u8, resolve_renditions, resolve_segments, resolve_variants, Error};
+
+const PLAYLIST_URL: &str = "https://cdn.example.com/video/720p/playlist.m3u8?sig=abc";
+
+fn resolve_fixture(name: &str) -> Vec<hotmart_ripper::MediaSegment> {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let playlist = m3u8::parse_media(&std::fs::read_to_string(path).unwrap()).unwrap();
+    resolve_segments(PLAYLIST_URL, &playlist).unwrap()
+}
+
+#[test]
+fn byte_ranges_continue_from_the_previous_segment() {
+    let segments = resolve_fixture("media_fmp4.m3u8");
+
+    assert_eq!(segments[0].url, "https://cdn.example.com/video/720p/video.mp4");
+    assert_eq!(segments[0].byte_range, Some(720..100_720));
+    assert_eq!(segments[1].byte_range, Some(100_720..195_720));
+    assert_eq!(segments[2].url, "https://cdn.example.com/video/720p/video-b.mp4");
+    assert_eq!(segments[2].byte_range, Some(0..40_000));
+
+    let map = segments[0].map.as_ref().unwrap();
+    assert_eq!(map.url, "https://cdn.example.com/video/720p/video.mp4");
+    assert_eq!(map.byte_range, Some(0..720));
+    assert_eq!(segments[2].map.as_ref().unwrap().byte_range, None);
+}
+
+#[test]
+fn byte_range_without_offset_needs_a_preceding_sub_range() {
+    let input = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n\
+                 #EXTINF:4,\n#EXT-X-BYTERANGE:100@0\na.ts\n\
+                 #EXTINF:4,\n#EXT-X-BYTERANGE:100\nb.ts\n";
+    let playlist = m3u8::parse_media(input).unwrap();
+
+    assert!(matches!(resolve_segments(PLAYLIST_URL, &playlist), Err(Error::InvalidPlaylist { .. })));
+}
+
+#[test]
+fn keys_resolve_to_absolute_urls_and_ivs() {
+    let segments = resolve_fixture("media_implicit_iv.m3u8");
+
+    let key = segments[0].key.as_ref().unwrap();
+    assert_eq!(key.url, "https://cdn.example.com/video/720p/key.bin");
+    assert_eq!(key.iv, 41u128.to_be_bytes());
+    assert_eq!(segments[1].key.as_ref().unwrap().iv, 42u128.to_be_bytes());
+    assert_eq!(segments[2].key.as_ref().unwrap().iv[0], 1);
+    assert!(segments.iter().all(|s| s.byte_range.is_none() && s.map.is_none()));
+}
+
+#[test]
+fn encrypted_map_uses_its_own_key() {
+    let segments = resolve_fixture("media_fmp4_encrypted.m3u8");
+
+    let map_key = segments[0].map.as_ref().unwrap().key.as_ref().unwrap();
+    assert_eq!(map_key.url, "https://cdn.example.com/video/720p/init.key");
+    assert_eq!(segments[1].key.as_ref().unwrap().url, "https://cdn.example.com/video/720p/media.key");
+}
+
+#[test]
+fn master_playlist_resolves_variants_and_renditions() {
+    let master_url = "https://cdn.example.com/video/master.m3u8?sig=abc";
+    let path = format!("{}/tests/fixtures/master.m3u8", env!("CARGO_MANIFEST_DIR"));
+    let master = m3u8::parse_master(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+    let variants = resolve_variants(master_url, &master).unwrap();
+    assert_eq!(variants.len(), 3);
+    assert_eq!(variants[0].url, "https://cdn.example.com/video/480p/playlist.m3u8?token=abc,def");
+    assert_eq!(variants[0].average_bandwidth, Some(1_000_000));
+    // Ten minutes at the average bit rate, or the peak one when there is none.
+    assert_eq!(variants[0].estimated_size(600.0), 75_000_000);
+    assert_eq!(variants[2].estimated_size(600.0), 48_000_000);
+
+    let renditions = resolve_renditions(master_url, &master).unwrap();
+    assert_eq!(renditions.len(), 3);
+    assert_eq!(renditions[0].media_type, MediaType::Audio);
+    assert_eq!(renditions[0].language.as_deref(), Some("pt-BR"));
+    assert_eq!(renditions[0].url.as_deref(), Some("https://cdn.example.com/video/audio/pt/playlist.m3u8"));
+    assert!(renditions[0].default && !renditions[1].default);
+    assert_eq!(renditions[2].media_type, MediaType::Subtitles);
+    assert_eq!(renditions[2].group_id, "subs");
+}
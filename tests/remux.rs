@@ -0,0 +1,362 @@
+use std::path::PathBuf;
+
+use std::time::Duration;
+
+use hotmart_ripper::subtitles::{Cue, SubtitleTrack};
+use hotmart_ripper::{remux, Error, Verified};
+
+const PMT_PID: u16 = 0x1000;
+const VIDEO_PID: u16 = 0x100;
+const AUDIO_PID: u16 = 0x101;
+
+/// Baseline profile SPS for 1920x1088 coded, cropped to 1920x1080.
+const SPS: &[u8] = &[0x67, 0x42, 0xc0, 0x28, 0xda, 0x01, 0xe0, 0x08, 0x9f, 0x95];
+const PPS: &[u8] = &[0x68, 0xce, 0x3c, 0x80];
+const IDR: &[u8] = &[0x65, 0x88, 0x84, 0x00, 0x33, 0xff];
+const NON_IDR: &[u8] = &[0x41, 0x9a, 0x02, 0x04];
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hotmart-remux-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Splits `payload` into 188-byte packets, padding the last one with an
+/// adaptation field.
+fn packetize(pid: u16, payload: &[u8], out: &mut Vec<u8>) {
+    for (i, chunk) in payload.chunks(184).enumerate() {
+        let start = if i == 0 { 0x40 } else { 0 };
+        out.extend([0x47, start | (pid >> 8) as u8, pid as u8]);
+        if chunk.len() == 184 {
+            out.push(0x10);
+        } else {
+            out.push(0x30);
+            let stuffing = 183 - chunk.len();
+            out.push(stuffing as u8);
+            if stuffing > 0 {
+                out.push(0);
+                out.extend(std::iter::repeat_n(0xff, stuffing - 1));
+            }
+        }
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn psi(table_id: u8, body: &[u8]) -> Vec<u8> {
+    let length = body.len() + 4;
+    let mut section = vec![0, table_id, 0xb0 | (length >> 8) as u8, length as u8];
+    section.extend_from_slice(body);
+    section.extend([0; 4]); // CRC, not checked
+    section
+}
+
+fn timestamp(prefix: u8, ts: u64) -> [u8; 5] {
+    [
+        prefix << 4 | ((ts >> 29) & 0x0e) as u8 | 1,
+        (ts >> 22) as u8,
+        ((ts >> 14) & 0xfe) as u8 | 1,
+        (ts >> 7) as u8,
+        ((ts << 1) & 0xfe) as u8 | 1,
+    ]
+}
+
+fn pes(stream_id: u8, pts: u64, dts: Option<u64>, data: &[u8]) -> Vec<u8> {
+    let mut header = vec![0x80];
+    match dts {
+        Some(dts) => {
+            header.extend([0xc0, 10]);
+            header.extend(timestamp(3, pts));
+            header.extend(timestamp(1, dts));
+        }
+        None => {
+            header.extend([0x80, 5]);
+            header.extend(timestamp(2, pts));
+        }
+    }
+    let mut pes = vec![0, 0, 1, stream_id, 0, 0];
+    pes.extend(header);
+    pes.extend_from_slice(data);
+    pes
+}
+
+fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+    let mut data = vec![0, 0, 0, 1, 0x09, 0xf0]; // access unit delimiter
+    for nal in nals {
+        data.extend([0, 0, 0, 1]);
+        data.extend_from_slice(nal);
+    }
+    data
+}
+
+/// AAC-LC, 48 kHz, stereo.
+fn adts(raw: &[u8]) -> Vec<u8> {
+    let len = raw.len() + 7;
+    let mut frame = vec![0xff, 0xf1, 0x4c, 0x80 | (len >> 11) as u8, (len >> 3) as u8, ((len & 7) << 5) as u8 | 0x1f, 0xfc];
+    frame.extend_from_slice(raw);
+    frame
+}
+
+fn sample_stream(audio_frames: &[Vec<u8>]) -> Vec<u8> {
+    let mut ts = Vec::new();
+    packetize(0, &psi(0x00, &[0, 1, 0xc1, 0, 0, 0, 1, 0xe0 | (PMT_PID >> 8) as u8, PMT_PID as u8]), &mut ts);
+    packetize(PMT_PID, &psi(0x02, &[
+        0, 1, 0xc1, 0, 0, 0xe1, 0x00, 0xf0, 0x00,
+        0x1b, 0xe1, 0x00, 0xf0, 0x00,
+        0x0f, 0xe1, 0x01, 0xf0, 0x00,
+    ]), &mut ts);
+
+    // Decode order I P B: the P frame is presented after the B frame.
+    packetize(VIDEO_PID, &pes(0xe0, 93_003, Some(90_000), &annex_b(&[SPS, PPS, IDR])), &mut ts);
+
+    let mut audio = adts(&audio_frames[0]);
+    audio.extend(adts(&audio_frames[1]));
+    let third = adts(&audio_frames[2]);
+    let (head, tail) = third.split_at(third.len() / 2);
+    audio.extend_from_slice(head);
+    packetize(AUDIO_PID, &pes(0xc0, 94_000, None, &audio), &mut ts);
+
+    packetize(VIDEO_PID, &pes(0xe0, 99_009, Some(93_003), &annex_b(&[NON_IDR])), &mut ts);
+    packetize(AUDIO_PID, &pes(0xc0, 97_840, None, tail), &mut ts);
+    packetize(VIDEO_PID, &pes(0xe0, 96_006, Some(96_006), &annex_b(&[&[0x01, 0x9e, 0x01]])), &mut ts);
+    ts
+}
+
+/// Child boxes of `data` as `(type, body)` pairs.
+fn children(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 8 {
+        let size = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        boxes.push((rest[4..8].try_into().unwrap(), &rest[8..size]));
+        rest = &rest[size..];
+    }
+    boxes
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> &'a [u8] {
+    children(data).into_iter()
+        .find(|(k, _)| k == kind)
+        .unwrap_or_else(|| panic!("no {} box", String::from_utf8_lossy(kind)))
+        .1
+}
+
+fn path<'a>(data: &'a [u8], kinds: &[&[u8; 4]]) -> &'a [u8] {
+    kinds.iter().fold(data, |data, kind| child(data, kind))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Entries of a full box that starts with an entry count, as `u32`s.
+fn table(full_box: &[u8], skip: usize) -> Vec<u32> {
+    let count = u32_at(full_box, 4 + skip) as usize;
+    (0..count).map(|i| u32_at(full_box, 8 + skip + 4 * i)).collect()
+}
+
+/// Entries of `stts` or `ctts` as `(count, value)` pairs.
+fn runs(full_box: &[u8]) -> Vec<(u32, u32)> {
+    let count = u32_at(full_box, 4) as usize;
+    (0..count).map(|i| (u32_at(full_box, 8 + 8 * i), u32_at(full_box, 12 + 8 * i))).collect()
+}
+
+#[test]
+fn remuxes_h264_and_aac_into_faststart_mp4() {
+    let dir = temp_dir("ok");
+    let input = dir.join("video.ts");
+    let output = dir.join("video.mp4");
+    let audio_frames: Vec<Vec<u8>> = (0..3u8).map(|i| vec![0x21, i, 0x40, 0x80 + i]).collect();
+    std::fs::write(&input, sample_stream(&audio_frames)).unwrap();
+
+    remux::ts_to_mp4(&input, &output).unwrap();
+    let mp4 = std::fs::read(&output).unwrap();
+
+    let top: Vec<_> = children(&mp4).into_iter().map(|(kind, _)| kind).collect();
+    assert_eq!(top, [*b"ftyp", *b"moov", *b"mdat"]);
+    assert!(!dir.join("video.mp4.mdat").exists());
+
+    let moov = child(&mp4, b"moov");
+    let traks: Vec<_> = children(moov).into_iter().filter(|(k, _)| k == b"trak").map(|(_, b)| b).collect();
+    assert_eq!(traks.len(), 2);
+
+    // Video track
+    let stbl = path(traks[0], &[b"mdia", b"minf", b"stbl"]);
+    let avc1 = child(&child(stbl, b"stsd")[8..], b"avc1");
+    assert_eq!((u16_at(avc1, 24), u16_at(avc1, 26)), (1920, 1080));
+    let avcc = child(&avc1[78..], b"avcC");
+    assert_eq!(&avcc[8..8 + SPS.len()], SPS);
+
+    let sizes = table(child(stbl, b"stsz"), 4);
+    assert_eq!(sizes, [4 + IDR.len() as u32, 4 + NON_IDR.len() as u32, 4 + 3]);
+    assert_eq!(table(child(stbl, b"stss"), 0), [1]);
+    assert_eq!(runs(child(stbl, b"stts")), [(3, 3003)]);
+    assert_eq!(runs(child(stbl, b"ctts")), [(1, 3003), (1, 6006), (1, 0)]);
+
+    let offsets = table(child(stbl, b"stco"), 0);
+    let first = offsets[0] as usize;
+    assert_eq!(u32_at(&mp4, first) as usize, IDR.len());
+    assert_eq!(&mp4[first + 4..first + 4 + IDR.len()], IDR);
+
+    // Audio track, which starts after the video and so is delayed by an empty edit.
+    let elst = path(traks[1], &[b"edts", b"elst"]);
+    assert_eq!(u32_at(elst, 4), 2);
+    assert_eq!(u32_at(elst, 12), u32::MAX);
+
+    let stbl = path(traks[1], &[b"mdia", b"minf", b"stbl"]);
+    let mp4a = child(&child(stbl, b"stsd")[8..], b"mp4a");
+    assert_eq!(u16_at(mp4a, 16), 2);
+    assert_eq!(u32_at(mp4a, 24) >> 16, 48_000);
+
+    let sizes = table(child(stbl, b"stsz"), 4);
+    assert_eq!(sizes, [4, 4, 4]);
+    let offsets = table(child(stbl, b"stco"), 0);
+    for (offset, frame) in offsets.iter().zip(&audio_frames) {
+        assert_eq!(&mp4[*offset as usize..*offset as usize + frame.len()], &frame[..]);
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Packed audio segment: an ID3 tag with the MPEG-TS timestamp, then ADTS frames.
+fn packed_audio(pts: u64, frames: &[Vec<u8>]) -> Vec<u8> {
+    let owner = b"com.apple.streaming.transportStreamTimestamp\0";
+    let mut frame = b"PRIV".to_vec();
+    frame.extend([0, 0, 0, (owner.len() + 8) as u8, 0, 0]);
+    frame.extend_from_slice(owner);
+    frame.extend(pts.to_be_bytes());
+
+    let mut data = b"ID3\x04\x00\x00".to_vec();
+    data.extend([0, 0, 0, frame.len() as u8]);
+    data.extend(frame);
+    for raw in frames {
+        data.extend(adts(raw));
+    }
+    data
+}
+
+#[test]
+fn muxes_audio_from_a_separate_rendition() {
+    let dir = temp_dir("audio");
+    let video = dir.join("video.ts");
+    let audio = dir.join("audio.aac");
+    let output = dir.join("video.mp4");
+    // The video stream carries audio of its own, which must be replaced.
+    let muxed_frames: Vec<Vec<u8>> = (0..3u8).map(|i| vec![0x21, i, 0x00]).collect();
+    let rendition_frames: Vec<Vec<u8>> = (0..4u8).map(|i| vec![0x21, i, 0x40, 0x80 + i, 0xff]).collect();
+    std::fs::write(&video, sample_stream(&muxed_frames)).unwrap();
+    let mut packed = packed_audio(91_000, &rendition_frames[..2]);
+    packed.extend(packed_audio(91_000 + 2 * 1024 * 90_000 / 48_000, &rendition_frames[2..]));
+    std::fs::write(&audio, packed).unwrap();
+
+    remux::ts_to_mp4_with_audio(&video, &audio, &output).unwrap();
+    let mp4 = std::fs::read(&output).unwrap();
+
+    let moov = child(&mp4, b"moov");
+    let traks: Vec<_> = children(moov).into_iter().filter(|(k, _)| k == b"trak").map(|(_, b)| b).collect();
+    assert_eq!(traks.len(), 2);
+    child(&child(path(traks[0], &[b"mdia", b"minf", b"stbl"]), b"stsd")[8..], b"avc1");
+
+    let stbl = path(traks[1], &[b"mdia", b"minf", b"stbl"]);
+    assert_eq!(table(child(stbl, b"stsz"), 4), [5, 5, 5, 5]);
+    let offsets = table(child(stbl, b"stco"), 0);
+    for (offset, frame) in offsets.iter().zip(&rendition_frames) {
+        assert_eq!(&mp4[*offset as usize..*offset as usize + frame.len()], &frame[..]);
+    }
+
+    // The rendition's timestamp puts its audio ahead of the first video frame
+    // (presented at 93003), so now the video is the track delayed by an empty edit.
+    assert!(children(traks[1]).iter().all(|(kind, _)| kind != b"edts"));
+    let elst = path(traks[0], &[b"edts", b"elst"]);
+    assert_eq!(u32_at(elst, 4), 2);
+    assert_eq!(u32_at(elst, 12), u32::MAX);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn keeps_gaps_where_audio_frames_are_missing() {
+    let dir = temp_dir("audio-gap");
+    let video = dir.join("video.ts");
+    let audio = dir.join("audio.aac");
+    let output = dir.join("video.mp4");
+    let frames: Vec<Vec<u8>> = (0..4u8).map(|i| vec![0x21, i, 0x40]).collect();
+    std::fs::write(&video, sample_stream(&frames)).unwrap();
+    // Two frames are missing between the segments, a third of a frame of jitter is not.
+    let frame = 1024 * 90_000 / 48_000;
+    let mut packed = packed_audio(91_000, &frames[..2]);
+    packed.extend(packed_audio(91_000 + 4 * frame, &frames[2..3]));
+    packed.extend(packed_audio(91_000 + 5 * frame + frame / 3, &frames[3..]));
+    std::fs::write(&audio, packed).unwrap();
+
+    remux::ts_to_mp4_with_audio(&video, &audio, &output).unwrap();
+    let mp4 = std::fs::read(&output).unwrap();
+
+    let moov = child(&mp4, b"moov");
+    let traks: Vec<_> = children(moov).into_iter().filter(|(k, _)| k == b"trak").map(|(_, b)| b).collect();
+    let stbl = path(traks[1], &[b"mdia", b"minf", b"stbl"]);
+    assert_eq!(runs(child(stbl, b"stts")), [(1, 1024), (1, 3 * 1024), (2, 1024)]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn embeds_subtitles_as_mov_text() {
+    let dir = temp_dir("subs");
+    let input = dir.join("video.ts");
+    let output = dir.join("video.mp4");
+    let audio_frames: Vec<Vec<u8>> = (0..3u8).map(|i| vec![0x21, i]).collect();
+    std::fs::write(&input, sample_stream(&audio_frames)).unwrap();
+
+    let cue = |start: u64, end: u64, text: &str| Cue {
+        start: Duration::from_millis(start),
+        end: Duration::from_millis(end),
+        settings: String::new(),
+        text: text.to_string(),
+    };
+    let track = SubtitleTrack {
+        language: Some("pt-BR".to_string()),
+        cues: vec![cue(500, 1500, "<i>Olá</i>"), cue(1200, 2000, "overlap"), cue(3000, 3500, "fim")],
+    };
+
+    assert_eq!(remux::start_time(&input).unwrap(), Some(93_003));
+    remux::ts_to_mp4_with(&input, None, &[track], &output).unwrap();
+    let mp4 = std::fs::read(&output).unwrap();
+
+    let moov = child(&mp4, b"moov");
+    let traks: Vec<_> = children(moov).into_iter().filter(|(k, _)| k == b"trak").map(|(_, b)| b).collect();
+    assert_eq!(traks.len(), 3);
+    let mdia = child(traks[2], b"mdia");
+    assert_eq!(&child(mdia, b"hdlr")[8..12], b"sbtl");
+    // "por" packed as three 5-bit letters.
+    assert_eq!(u16_at(child(mdia, b"mdhd"), 20), (16 << 10) | (15 << 5) | 18);
+
+    let stbl = path(mdia, &[b"minf", b"stbl"]);
+    child(&child(stbl, b"stsd")[8..], b"tx3g");
+    // Gap, cue, overlapping cue cut short, gap, cue, end marker.
+    assert_eq!(runs(child(stbl, b"stts")), [(1, 500), (1, 1000), (1, 500), (1, 1000), (1, 500), (1, 0)]);
+    let offsets = table(child(stbl, b"stco"), 0);
+    let sample = &mp4[offsets[1] as usize..];
+    assert_eq!(&sample[..5], b"\0\x04Ol\xc3");
+
+    let Verified::Mp4 { tracks, .. } = hotmart_ripper::verify(&output).unwrap() else { panic!("not an MP4") };
+    let handlers: Vec<&str> = tracks.iter().map(|track| track.handler.as_str()).collect();
+    assert_eq!(handlers, ["vide", "soun", "sbtl"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_input_that_is_not_mpeg_ts() {
+    let dir = temp_dir("bad");
+    let input = dir.join("video.ts");
+    std::fs::write(&input, b"\0\0\0\x18ftypisom").unwrap();
+
+    let error = remux::ts_to_mp4(&input, &dir.join("video.mp4")).unwrap_err();
+    assert!(matches!(error, Error::Remux { .. }), "{}", error);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
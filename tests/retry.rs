@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use hotmart_ripper::{Error, RetryPolicy};
+
+#[test]
+fn backoff_doubles_with_jitter_and_caps() {
+    let policy = RetryPolicy {
+        max_retries: 10,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(1),
+    };
+
+    for (attempt, ceiling) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (10, 1000)] {
+        let ceiling = Duration::from_millis(ceiling);
+        for _ in 0..50 {
+            let delay = policy.backoff(attempt);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
+
+#[test]
+fn classifies_retryable_failures() {
+    let status = |status| Error::HttpStatus { url: "u".to_string(), status, retry_after: None };
+
+    assert!(status(500).is_retryable());
+    assert!(status(503).is_retryable());
+    assert!(status(429).is_retryable());
+    assert!(status(408).is_retryable());
+    assert!(Error::Timeout { url: "u".to_string() }.is_retryable());
+
+    assert!(!status(404).is_retryable());
+    assert!(!status(400).is_retryable());
+    assert!(!Error::LinkExpired { url: "u".to_string(), status: 403 }.is_retryable());
+    assert!(!Error::NoVariants.is_retryable());
+}
+
+#[test]
+fn key_fetches_refused_by_status_count_as_expired_links() {
+    let key_fetch = |status| Error::KeyFetch { url: "k".to_string(), status, reason: String::new() };
+
+    assert!(Error::LinkExpired { url: "u".to_string(), status: 403 }.is_link_expired());
+    assert!(key_fetch(Some(401)).is_link_expired());
+    assert!(key_fetch(Some(410)).is_link_expired());
+    assert!(!key_fetch(Some(404)).is_link_expired());
+    assert!(!key_fetch(None).is_link_expired());
+}
@@ -0,0 +1,110 @@
+use hotmart_ripper::m3u8::{self, MediaType, Resolution};
+use hotmart_ripper::{resolve_renditions, select_rendition, select_subtitles, Rendition, SubtitleSelection, Variant, VariantSelector};
+
+fn variant(width: u32, height: u32, bandwidth: u64, codecs: &str) -> Variant {
+    Variant {
+        url: format!("https://cdn.example.com/{}p/{}/playlist.m3u8", height, bandwidth),
+        bandwidth,
+        average_bandwidth: None,
+        resolution: Some(Resolution { width, height }),
+        codecs: Some(codecs.to_string()),
+        frame_rate: None,
+        audio: None,
+        subtitles: None,
+    }
+}
+
+fn ladder() -> Vec<Variant> {
+    vec![
+        variant(640, 360, 800_000, "avc1.4d401e,mp4a.40.2"),
+        variant(1280, 720, 2_500_000, "avc1.4d401f,mp4a.40.2"),
+        variant(1280, 720, 2_000_000, "hvc1.1.6.L93.B0,mp4a.40.2"),
+        variant(1920, 1080, 5_000_000, "avc1.640028,mp4a.40.2"),
+        // Audio-only rendition with a higher bandwidth than the 360p one.
+        Variant { resolution: None, codecs: Some("mp4a.40.2".to_string()), ..variant(0, 0, 900_000, "") },
+    ]
+}
+
+fn pick(quality: &str) -> Option<(u32, u64)> {
+    let variants = ladder();
+    let selector: VariantSelector = quality.parse().unwrap();
+    selector.select(&variants).map(|v| (v.resolution.map_or(0, |r| r.height), v.bandwidth))
+}
+
+#[test]
+fn selects_by_resolution_bandwidth_and_codec() {
+    assert_eq!(pick("best"), Some((1080, 5_000_000)));
+    assert_eq!(pick("worst"), Some((0, 900_000)));
+    assert_eq!(pick("<=720"), Some((720, 2_500_000)));
+    assert_eq!(pick("<=720p,worst"), Some((0, 900_000)));
+    assert_eq!(pick("720p,worst"), Some((720, 2_000_000)));
+    assert_eq!(pick("1280x720,codec=hvc1"), Some((720, 2_000_000)));
+    assert_eq!(pick("bw<=2.2M"), Some((720, 2_000_000)));
+    assert_eq!(pick("bandwidth<=850k"), Some((360, 800_000)));
+    // A codec nobody offers is only a preference, not a limit.
+    assert_eq!(pick("codec=av01"), Some((1080, 5_000_000)));
+    assert_eq!(pick("480p"), None);
+}
+
+#[test]
+fn parses_and_formats_quality_strings() {
+    let selector: VariantSelector = " worst , <=1080p, bw<=3M, Codec=AVC1 ".parse().unwrap();
+    assert_eq!(selector, VariantSelector {
+        worst: true,
+        max_height: Some(1080),
+        max_bandwidth: Some(3_000_000),
+        codec: Some("avc1".to_string()),
+        ..VariantSelector::default()
+    });
+    assert_eq!(selector.to_string(), "worst,<=1080,bw<=3000000,codec=avc1");
+    assert_eq!(selector.to_string().parse::<VariantSelector>().unwrap(), selector);
+
+    for invalid in ["high", "<=tall", "bw<=fast", "720x", "p"] {
+        assert!(invalid.parse::<VariantSelector>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn selects_audio_rendition_by_language() {
+    let path = format!("{}/tests/fixtures/master.m3u8", env!("CARGO_MANIFEST_DIR"));
+    let master = m3u8::parse_master(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let renditions = resolve_renditions("https://cdn.example.com/master.m3u8", &master).unwrap();
+    let pick = |language: Option<&str>| {
+        select_rendition(&renditions, MediaType::Audio, "aac", language).map(|r| r.name.as_str())
+    };
+
+    assert_eq!(pick(Some("en")), Some("English, original"));
+    assert_eq!(pick(Some("EN-us")), Some("English, original"));
+    assert_eq!(pick(Some("pt")), Some("Português"));
+    // Unknown languages and no preference fall back to the default rendition.
+    assert_eq!(pick(Some("de")), Some("Português"));
+    assert_eq!(pick(None), Some("Português"));
+    assert_eq!(select_rendition(&renditions, MediaType::Audio, "subs", None), None);
+}
+
+#[test]
+fn selects_subtitles_by_exact_language_first() {
+    let subtitle = |language: &str| Rendition {
+        media_type: MediaType::Subtitles,
+        group_id: "subs".to_string(),
+        name: language.to_string(),
+        language: Some(language.to_string()),
+        default: false,
+        url: Some(format!("https://cdn.example.com/subs/{}.m3u8", language)),
+        channels: None,
+    };
+    let renditions = [subtitle("pt-BR"), subtitle("pt-PT"), subtitle("en")];
+    let pick = |languages: &str| -> Vec<&str> {
+        select_subtitles(&renditions, "subs", &languages.parse::<SubtitleSelection>().unwrap())
+            .into_iter()
+            .map(|r| r.name.as_str())
+            .collect()
+    };
+
+    assert_eq!(pick("pt-BR"), ["pt-BR"]);
+    assert_eq!(pick("PT-pt,en"), ["pt-PT", "en"]);
+    assert_eq!(pick("pt"), ["pt-BR", "pt-PT"]);
+    assert_eq!(pick("en-US"), ["en"]);
+    assert_eq!(pick("de"), Vec::<&str>::new());
+    assert_eq!(pick("all").len(), 3);
+}
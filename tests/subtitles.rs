@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use hotmart_ripper::subtitles::{self, Cue};
+use hotmart_ripper::Error;
+
+const FIRST: &str = "WEBVTT\r\nX-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000\r\n\r\n\
+                     1\r\n00:00:01.000 --> 00:00:03.500 line:90%\r\nOlá, <i>pessoal</i>!\r\n\r\n\
+                     NOTE a comment\r\n\r\n\
+                     00:00:05.000 --> 00:00:07.000\r\nA cue that continues\r\n";
+
+/// Local times restart at zero, the timestamp map moves them 6 s later.
+const SECOND: &str = "\u{feff}WEBVTT\n\
+                      X-TIMESTAMP-MAP=LOCAL:00:00:00.000,MPEGTS:1440000\n\n\
+                      00:00:00.000 --> 00:00:01.000\nA cue that continues\n\n\
+                      00:00:02.000 --> 00:00:04.250\n<v Ana>Tom &amp; <c.yellow>Jerry</c>\n";
+
+fn cue(start_ms: u64, end_ms: u64, text: &str) -> (Duration, Duration, String) {
+    (Duration::from_millis(start_ms), Duration::from_millis(end_ms), text.to_string())
+}
+
+fn timings(cues: &[Cue]) -> Vec<(Duration, Duration, String)> {
+    cues.iter().map(|c| (c.start, c.end, c.text.clone())).collect()
+}
+
+#[test]
+fn stitches_segments_on_the_mpeg_ts_clock() {
+    // The video starts 10 s into the MPEG-TS clock, like the first subtitle segment.
+    let cues = subtitles::stitch(&[FIRST, SECOND], Some(900_000)).unwrap();
+    assert_eq!(timings(&cues), [
+        cue(1_000, 3_500, "Olá, <i>pessoal</i>!"),
+        cue(5_000, 7_000, "A cue that continues"),
+        cue(6_000, 7_000, "A cue that continues"),
+        cue(8_000, 10_250, "<v Ana>Tom &amp; <c.yellow>Jerry</c>"),
+    ]);
+    assert_eq!(cues[0].settings, "line:90%");
+
+    // Starting half a second later shifts every cue back.
+    let cues = subtitles::stitch(&[FIRST, SECOND], Some(945_000)).unwrap();
+    assert_eq!(cues[0].start, Duration::from_millis(500));
+
+    // Without a known start the first segment's map is the origin.
+    assert_eq!(subtitles::stitch(&[FIRST, SECOND], None).unwrap(), subtitles::stitch(&[FIRST, SECOND], Some(900_000)).unwrap());
+}
+
+#[test]
+fn repeated_cues_are_kept_once() {
+    let cues = subtitles::stitch(&[FIRST, FIRST], None).unwrap();
+    assert_eq!(cues.len(), 2);
+}
+
+#[test]
+fn writes_webvtt_and_srt() {
+    let cues = subtitles::stitch(&[FIRST, SECOND], Some(900_000)).unwrap();
+
+    let vtt = subtitles::to_webvtt(&cues[..1]);
+    assert_eq!(vtt, "WEBVTT\n\n00:00:01.000 --> 00:00:03.500 line:90%\nOlá, <i>pessoal</i>!\n");
+    assert_eq!(subtitles::stitch(&[vtt], None).unwrap(), cues[..1]);
+
+    let srt = subtitles::to_srt(&[cues[0].clone(), cues[3].clone()]);
+    assert_eq!(srt, "1\n00:00:01,000 --> 00:00:03,500\nOlá, <i>pessoal</i>!\n\n\
+                     2\n00:00:08,000 --> 00:00:10,250\nTom & Jerry\n\n");
+    assert_eq!(subtitles::plain_text(&cues[0]), "Olá, pessoal!");
+}
+
+#[test]
+fn rejects_malformed_segments() {
+    let cases = [
+        "",
+        "1\n00:00:01.000 --> 00:00:02.000\nno header\n",
+        "WEBVTT\n\n00:01.0 --> 00:02.000\nbad time\n",
+        "WEBVTT\n\n9223372036854775807:00:00.000 --> 9223372036854775807:00:01.000\ntoo late\n",
+        "WEBVTT\n\n100000000000:00:00.000 --> 100000000000:00:01.000\ntoo late in ticks\n",
+        "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:0,LOCAL:100000000000:00:00.000\n\n00:01.000 --> 00:02.000\ntoo late a map\n",
+    ];
+    for input in cases {
+        let error = subtitles::stitch(&[input], None).unwrap_err();
+        assert!(matches!(error, Error::Subtitles { .. }), "{}", error);
+    }
+}
@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+use hotmart_ripper::template::sanitize;
+use hotmart_ripper::{OutputTemplate, VideoMetadata};
+
+fn lesson() -> VideoMetadata {
+    VideoMetadata {
+        video_id: "DLNymXB7qr".to_string(),
+        title: Some("Aula 1: Introdução / Visão geral?".to_string()),
+        course: Some("Curso de Rust".to_string()),
+        module: Some(2),
+        lesson: Some(7),
+        producer: Some("Ana Souza".to_string()),
+        upload_date: Some("2024-03-18T14:02:11Z".to_string()),
+        ..VideoMetadata::default()
+    }
+}
+
+#[test]
+fn renders_fields_into_a_path() {
+    let template: OutputTemplate = "{course}/{module:02}-{lesson:02} {title}.{ext}".parse().unwrap();
+    assert_eq!(
+        template.render(&lesson(), "mp4"),
+        PathBuf::from("Curso de Rust/02-07 Aula 1_ Introdução _ Visão geral_.mp4"),
+    );
+    assert_eq!(template.to_string(), "{course}/{module:02}-{lesson:02} {title}.{ext}");
+
+    let template: OutputTemplate = "{producer}/{date} {id}".parse().unwrap();
+    assert_eq!(template.render(&lesson(), "mp4"), PathBuf::from("Ana Souza/2024-03-18 DLNymXB7qr.mp4"));
+
+    // Missing fields become NA; the extension is appended when the template has none.
+    let fields = VideoMetadata { video_id: "abc".to_string(), ..VideoMetadata::default() };
+    let template: OutputTemplate = "{{{id}}} {title}".parse().unwrap();
+    assert_eq!(template.render(&fields, "ts"), PathBuf::from("{abc} NA.ts"));
+
+    assert_eq!(OutputTemplate::default().under("videos").render(&fields, "mp4"), PathBuf::from("videos/abc.mp4"));
+    assert_eq!(OutputTemplate::literal("out/{x}.mp4").under("videos").render(&fields, "ts"), PathBuf::from("videos/out/{x}.mp4"));
+}
+
+#[test]
+fn rejects_invalid_templates() {
+    for template in ["", "{name}", "{title", "title}", "{module:2}"] {
+        assert!(template.parse::<OutputTemplate>().is_err(), "{:?}", template);
+    }
+}
+
+#[test]
+fn sanitizes_reserved_names_and_characters() {
+    assert_eq!(sanitize("a<b>c:d\"e|f?g*h\\i/j"), "a_b_c_d_e_f_g_h_i_j");
+    assert_eq!(sanitize("  line\nbreak\t tab\u{7f}  "), "line break tab_");
+    assert_eq!(sanitize("ends with dots..."), "ends with dots");
+    assert_eq!(sanitize("con"), "_con");
+    assert_eq!(sanitize("LPT1.txt"), "_LPT1.txt");
+    assert_eq!(sanitize("Console"), "Console");
+    assert_eq!(sanitize(".."), "_");
+    assert_eq!(sanitize(&"é".repeat(150)).len(), 200);
+}
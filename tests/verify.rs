@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use hotmart_ripper::{verify, Error, TrackSummary, Verified};
+
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("hotmart-verify-{}-{}", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn boxed(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+fn full_box(kind: &[u8; 4], fields: &[u32]) -> Vec<u8> {
+    let body: Vec<u8> = std::iter::once(0).chain(fields.iter().copied()).flat_map(u32::to_be_bytes).collect();
+    boxed(kind, &body)
+}
+
+/// A one-track MP4 with three samples of 4, 5 and 6 bytes in two chunks,
+/// the first of which starts at `first_chunk` bytes into the `mdat` payload.
+fn mp4(first_chunk: u32) -> Vec<u8> {
+    let ftyp = boxed(b"ftyp", b"isom\0\0\x02\0isomiso2");
+    let mut moov_len = 0;
+    // The chunk offsets depend on the size of `moov`, which they are part of.
+    loop {
+        let mdat_start = (ftyp.len() + moov_len + 8) as u32;
+        let stbl = [
+            full_box(b"stsz", &[0, 3, 4, 5, 6]),
+            full_box(b"stsc", &[2, 1, 2, 1, 2, 1, 1]),
+            full_box(b"stco", &[2, mdat_start + first_chunk, mdat_start + first_chunk + 9]),
+        ].concat();
+        let mdhd = full_box(b"mdhd", &[0, 0, 1000, 3000, 0]);
+        let hdlr = full_box(b"hdlr", &[0, u32::from_be_bytes(*b"vide"), 0, 0, 0]);
+        let minf = boxed(b"minf", &boxed(b"stbl", &stbl));
+        let trak = boxed(b"trak", &boxed(b"mdia", &[mdhd, hdlr, minf].concat()));
+        let moov = boxed(b"moov", &trak);
+        if moov.len() == moov_len {
+            return [ftyp, moov, boxed(b"mdat", &[0xaa; 15])].concat();
+        }
+        moov_len = moov.len();
+    }
+}
+
+#[test]
+fn accepts_complete_files() {
+    let path = temp_file("ok.mp4", &mp4(0));
+    assert_eq!(verify(&path).unwrap(), Verified::Mp4 {
+        tracks: vec![TrackSummary { handler: "vide".to_string(), samples: 3, duration: 3.0 }],
+        fragments: 0,
+    });
+
+    let mut ts = Vec::new();
+    for _ in 0..3 {
+        ts.extend([0x47, 0x1f, 0xff, 0x10]);
+        ts.extend([0xff; 184]);
+    }
+    let path = temp_file("ok.ts", &ts);
+    assert_eq!(verify(&path).unwrap(), Verified::Ts { packets: 3 });
+}
+
+#[test]
+fn rejects_incomplete_files() {
+    let complete = mp4(0);
+    let mut ts = vec![0x47; 188 * 2];
+    ts[188] = 0;
+    let cases = [
+        ("truncated.mp4", complete[..complete.len() - 1].to_vec()),
+        ("outside.mp4", mp4(1)),
+        ("no-sync.ts", ts),
+        ("partial.ts", vec![0x47; 200]),
+        ("text", b"not a video".to_vec()),
+    ];
+    for (name, contents) in cases {
+        let error = verify(&temp_file(name, &contents)).unwrap_err();
+        assert!(matches!(error, Error::OutputCheck { .. }), "{}: {}", name, error);
+    }
+}
+
+/// `file` with field `index` (after version and flags) of its `kind` box set to `value`.
+fn with_field(mut file: Vec<u8>, kind: &[u8; 4], index: usize, value: u32) -> Vec<u8> {
+    let at = file.windows(4).position(|window| window == kind).unwrap() + 8 + 4 * index;
+    file[at..at + 4].copy_from_slice(&value.to_be_bytes());
+    file
+}
+
+#[test]
+fn rejects_counts_the_file_cannot_hold() {
+    // Trusting any of these counts would allocate gigabytes before failing.
+    let cases = [
+        ("fixed-size.mp4", with_field(with_field(mp4(0), b"stsz", 0, 1), b"stsz", 1, u32::MAX)),
+        ("sizes.mp4", with_field(mp4(0), b"stsz", 1, u32::MAX)),
+        ("chunks.mp4", with_field(mp4(0), b"stco", 0, u32::MAX)),
+        ("runs.mp4", with_field(mp4(0), b"stsc", 0, u32::MAX)),
+    ];
+    for (name, contents) in cases {
+        let error = verify(&temp_file(name, &contents)).unwrap_err();
+        assert!(matches!(error, Error::OutputCheck { .. }), "{}: {}", name, error);
+    }
+}